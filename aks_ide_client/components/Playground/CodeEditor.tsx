@@ -29,7 +29,7 @@ const CodeEditor: React.FC<CodeEditorProps> = React.memo(
 
     const activeFile = openFiles[activeFileIdx] ?? null;
 
-    const { codeContent, modifiedPaths, onEditorChange } = useEditorFiles({
+    const { codeContent, activeBinary, modifiedPaths, onEditorChange } = useEditorFiles({
       activeFile,
       email: userProfile?.email,
       onSaveStatusChange,
@@ -75,7 +75,24 @@ const CodeEditor: React.FC<CodeEditorProps> = React.memo(
         {activeFile && <EditorBreadcrumb absolutePath={activeFile.absolutePath} />}
 
         <div className="flex-1 min-h-0">
-          {activeFile ? (
+          {activeFile && activeBinary ? (
+            <div className="flex flex-col items-center justify-center h-full gap-3 p-4 overflow-auto">
+              {activeBinary.mimeType.startsWith("image/") ? (
+                <img
+                  src={activeBinary.url}
+                  alt={activeFile.name}
+                  className="max-w-full max-h-[80%] object-contain"
+                />
+              ) : (
+                <p className="text-xs text-zinc-500 select-none">
+                  Binary file not shown in the editor
+                </p>
+              )}
+              <span className="text-[11px] text-zinc-600 select-none">
+                {activeBinary.mimeType} · {activeBinary.size.toLocaleString()} bytes
+              </span>
+            </div>
+          ) : activeFile ? (
             <Editor
               path={activeFile.absolutePath}
               height="100%"
@@ -1,8 +1,24 @@
 "use client";
 
 import { useCallback, useEffect, useReducer, useRef, useState } from "react";
+import type * as monaco from "monaco-editor";
 import socket from "@/utils/Socket";
-import type { OpenFile, SaveStatus } from "../types";
+import type {
+  BinaryFile,
+  FileConflict,
+  FileData,
+  FileError,
+  FileSaved,
+  OpenFile,
+  PatchRejected,
+  SaveStatus,
+  TextEdit,
+} from "../types";
+
+// Rough wire size of a patch, to decide whether it beats sending the file.
+function patchSize(edits: TextEdit[]): number {
+  return edits.reduce((size, edit) => size + edit.text.length + 32, 0);
+}
 
 interface UseEditorFilesOptions {
   activeFile: OpenFile | null;
@@ -16,13 +32,60 @@ export function useEditorFiles({ activeFile, email, onSaveStatusChange }: UseEdi
 
   const saveTimeoutsRef = useRef<Record<string, ReturnType<typeof setTimeout>>>({});
   const pendingFetchesRef = useRef<string[]>([]);
-  const pendingSavesRef = useRef<string[]>([]);
   const codeContentRef = useRef<Record<string, string>>({});
+  const binaryFilesRef = useRef<Record<string, BinaryFile>>({});
+  // Version each file was loaded or last saved at; sent with every save so
+  // the server can refuse to overwrite changes made outside the editor.
+  const versionsRef = useRef<Record<string, string>>({});
+  // One save per file at a time: the next one needs the version the
+  // previous one produced. Edits made meanwhile wait here.
+  const savingRef = useRef<Set<string>>(new Set());
+  const queuedSavesRef = useRef<Record<string, string>>({});
+  // Edits since the content at versionsRef, sent as a patch_file when
+  // that's smaller than the file. Paths in fullSaveRef lost edits along
+  // the way and must be saved whole next time.
+  const pendingEditsRef = useRef<Record<string, TextEdit[]>>({});
+  const fullSaveRef = useRef<Set<string>>(new Set());
+
+  const sendSave = useCallback((path: string, content: string, force = false) => {
+    if (savingRef.current.has(path)) {
+      queuedSavesRef.current[path] = content;
+      return;
+    }
+    savingRef.current.add(path);
+    const edits = pendingEditsRef.current[path];
+    delete pendingEditsRef.current[path];
+    const base = versionsRef.current[path];
+    if (!force && !fullSaveRef.current.has(path) && base && edits?.length
+      && patchSize(edits) < content.length / 2) {
+      socket.emit("patch_file", { email, path, base_version: base, edits });
+      return;
+    }
+    fullSaveRef.current.delete(path);
+    socket.emit("save_data", {
+      email,
+      path,
+      content,
+      expected_version: versionsRef.current[path],
+      force,
+    });
+  }, [email]);
 
-  const onEditorChange = (value: string | undefined) => {
+  const onEditorChange = (value: string | undefined, event?: monaco.editor.IModelContentChangedEvent) => {
     if (!activeFile || value === undefined) return;
     const path = activeFile.absolutePath;
+    if (binaryFilesRef.current[path]) return;
     codeContentRef.current[path] = value;
+    if (event) {
+      // Changes in one event are all relative to the text before it;
+      // applied last-first, each one leaves the earlier offsets valid.
+      const edits = [...event.changes]
+        .sort((a, b) => b.rangeOffset - a.rangeOffset)
+        .map((change) => ({ offset: change.rangeOffset, length: change.rangeLength, text: change.text }));
+      (pendingEditsRef.current[path] ??= []).push(...edits);
+    } else {
+      fullSaveRef.current.add(path);
+    }
     setModifiedPaths((prev) => {
       if (prev.has(path)) return prev;
       const next = new Set(prev);
@@ -32,49 +95,118 @@ export function useEditorFiles({ activeFile, email, onSaveStatusChange }: UseEdi
     if (saveTimeoutsRef.current[path]) clearTimeout(saveTimeoutsRef.current[path]);
     onSaveStatusChange("saving");
     saveTimeoutsRef.current[path] = setTimeout(() => {
-      pendingSavesRef.current.push(path);
-      socket.emit("save_data", { email, path, content: value });
+      sendSave(path, value);
       delete saveTimeoutsRef.current[path];
     }, 500);
   };
 
-  const getFileContent = useCallback((data: string) => {
-    const path = pendingFetchesRef.current.shift();
-    if (!path) return;
-    codeContentRef.current[path] = data;
+  const getFileContent = useCallback((file: FileData) => {
+    const path = file.path;
+    pendingFetchesRef.current = pendingFetchesRef.current.filter((p) => p !== path);
+    versionsRef.current[path] = file.version;
+    delete pendingEditsRef.current[path];
+    fullSaveRef.current.delete(path);
+    if (file.is_binary && file.data) {
+      const previous = binaryFilesRef.current[path];
+      if (previous) URL.revokeObjectURL(previous.url);
+      binaryFilesRef.current[path] = {
+        mimeType: file.mime_type,
+        size: file.size,
+        url: URL.createObjectURL(new Blob([file.data], { type: file.mime_type })),
+      };
+      codeContentRef.current[path] = "";
+    } else {
+      codeContentRef.current[path] = file.content ?? "";
+    }
     forceUpdate();
   }, []);
 
-  const handleFileSaved = useCallback((_message: string) => {
-    const savedPath = pendingSavesRef.current.shift();
-    if (savedPath) {
-      setModifiedPaths((prev) => {
-        const next = new Set(prev);
-        next.delete(savedPath);
-        return next;
-      });
+  const markSaved = useCallback((path: string) => {
+    setModifiedPaths((prev) => {
+      const next = new Set(prev);
+      next.delete(path);
+      return next;
+    });
+  }, []);
+
+  const handleFileSaved = useCallback(({ path, version }: FileSaved) => {
+    versionsRef.current[path] = version;
+    savingRef.current.delete(path);
+    const queued = queuedSavesRef.current[path];
+    if (queued !== undefined) {
+      delete queuedSavesRef.current[path];
+      sendSave(path, queued);
+      return;
     }
+    markSaved(path);
     onSaveStatusChange("saved");
     setTimeout(() => onSaveStatusChange("idle"), 2000);
-  }, [onSaveStatusChange]);
+  }, [markSaved, onSaveStatusChange, sendSave]);
 
-  const handleFileError = useCallback((error: string) => {
+  const handleFileConflict = useCallback(({ path, current }: FileConflict) => {
+    savingRef.current.delete(path);
+    const mine = queuedSavesRef.current[path] ?? codeContentRef.current[path] ?? "";
+    delete queuedSavesRef.current[path];
+    delete pendingEditsRef.current[path];
+    fullSaveRef.current.add(path);
+    const overwrite = window.confirm(
+      current
+        ? `${path} was changed outside the editor.\n\nOK overwrites it with your version; Cancel loads the version on disk.`
+        : `${path} was deleted outside the editor.\n\nOK saves your version again; Cancel keeps it unsaved.`,
+    );
+    if (overwrite) {
+      sendSave(path, mine, true);
+      return;
+    }
+    if (current) {
+      getFileContent(current);
+      markSaved(path);
+      onSaveStatusChange("idle");
+    } else {
+      delete versionsRef.current[path];
+      onSaveStatusChange("error");
+      setTimeout(() => onSaveStatusChange("idle"), 3000);
+    }
+  }, [getFileContent, markSaved, onSaveStatusChange, sendSave]);
+
+  // The server couldn't apply a patch (the file moved on, or the edits
+  // didn't fit it): send the whole file, still checked against the version.
+  const handlePatchRejected = useCallback(({ path, reason }: PatchRejected) => {
+    console.warn(`Patch of ${path} rejected (${reason}); saving the whole file`);
+    savingRef.current.delete(path);
+    delete queuedSavesRef.current[path];
+    delete pendingEditsRef.current[path];
+    fullSaveRef.current.add(path);
+    sendSave(path, codeContentRef.current[path] ?? "");
+  }, [sendSave]);
+
+  const handleFileError = useCallback((error: FileError) => {
+    console.error(`File ${error.op} error (${error.path}):`, error.message);
+    if (error.op !== "save" && error.op !== "patch") return;
+    savingRef.current.delete(error.path);
+    delete queuedSavesRef.current[error.path];
+    delete pendingEditsRef.current[error.path];
+    fullSaveRef.current.add(error.path);
     onSaveStatusChange("error");
-    console.error("File save error:", error);
     setTimeout(() => onSaveStatusChange("idle"), 3000);
   }, [onSaveStatusChange]);
 
   useEffect(() => {
     socket.on("files_data", getFileContent);
     socket.on("file_saved", handleFileSaved);
+    socket.on("file_conflict", handleFileConflict);
+    socket.on("patch_rejected", handlePatchRejected);
     socket.on("file_error", handleFileError);
     return () => {
       socket.off("files_data", getFileContent);
       socket.off("file_saved", handleFileSaved);
+      socket.off("file_conflict", handleFileConflict);
+      socket.off("patch_rejected", handlePatchRejected);
       socket.off("file_error", handleFileError);
       Object.values(saveTimeoutsRef.current).forEach(clearTimeout);
+      Object.values(binaryFilesRef.current).forEach((file) => URL.revokeObjectURL(file.url));
     };
-  }, [getFileContent, handleFileSaved, handleFileError]);
+  }, [getFileContent, handleFileSaved, handleFileConflict, handlePatchRejected, handleFileError]);
 
   useEffect(() => {
     if (!activeFile || !email) return;
@@ -87,5 +219,7 @@ export function useEditorFiles({ activeFile, email, onSaveStatusChange }: UseEdi
     ? (codeContentRef.current[activeFile.absolutePath] ?? "")
     : "";
 
-  return { codeContent, modifiedPaths, onEditorChange };
+  const activeBinary = activeFile ? (binaryFilesRef.current[activeFile.absolutePath] ?? null) : null;
+
+  return { codeContent, activeBinary, modifiedPaths, onEditorChange };
 }
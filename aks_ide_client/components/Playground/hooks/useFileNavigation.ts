@@ -3,11 +3,47 @@
 import { useCallback, useEffect, useRef, useState } from "react";
 import socket from "@/utils/Socket";
 
-export type TreeNode = { name: string; is_dir: boolean; path: string };
+export type TreeNode = {
+  name: string;
+  is_dir: boolean;
+  path: string;
+  kind: "file" | "dir" | "symlink" | "other";
+  size: number;
+  mtime: number;
+  mode: number;
+  permissions: string;
+  symlink_target: string | null;
+  depth: number;
+  excluded: boolean;
+};
 
 interface RepoStructureResponse {
   current_directory: string;
   items: TreeNode[];
+  depth: number;
+  offset: number;
+  total: number;
+  next_offset: number | null;
+  truncated: boolean;
+  error: string | null;
+}
+
+interface FsChange {
+  kind: "created" | "deleted" | "modified" | "renamed";
+  path: string;
+  old_path: string | null;
+  is_dir: boolean;
+}
+
+// Files the server's exclude rules come from; changing one can hide or
+// reveal entries anywhere in the tree.
+function isRulesFile(path: string): boolean {
+  return path.endsWith("/.gitignore") || path.endsWith("/.aks/settings.json");
+}
+
+function parentDir(path: string): string {
+  const idx = path.lastIndexOf("/");
+  return idx <= 0 ? "/" : path.slice(0, idx);
 }
 
 interface UseFileNavigationOptions {
@@ -34,10 +70,13 @@ export function useFileNavigation({
   const [explorerLoadingStatus, setExplorerLoadingStatus] = useState(true);
 
   const fetchingRef = useRef<Set<string>>(new Set());
+  const treeMapRef = useRef(treeMap);
+  treeMapRef.current = treeMap;
 
   const openRepo = useCallback(() => {
     if (!email) return;
     setExplorerLoadingStatus(true);
+    treeMapRef.current.forEach((_, path) => socket.emit("unwatch_directory", { email, path }));
     setTreeMap(new Map());
     fetchingRef.current.clear();
     setFetchingPaths(new Set());
@@ -59,7 +98,20 @@ export function useFileNavigation({
 
     const onRepoStructure = (data: RepoStructureResponse) => {
       const dir = data.current_directory;
-      setTreeMap((prev) => new Map(prev).set(dir, data.items ?? []));
+      if (data.error) console.error(`Failed to list ${dir}:`, data.error);
+      const items = data.items ?? [];
+      setTreeMap((prev) => {
+        const next = new Map(prev);
+        next.set(dir, data.offset > 0 ? [...(prev.get(dir) ?? []), ...items] : items);
+        return next;
+      });
+      // Large directories arrive in pages; keep the folder loading until
+      // the last one is in.
+      if (data.next_offset !== null) {
+        socket.emit("repo_tree", { email, path: dir, offset: data.next_offset });
+        return;
+      }
+      if (!data.error) socket.emit("watch_directory", { email, path: dir });
       fetchingRef.current.delete(dir);
       setFetchingPaths((prev) => {
         const next = new Set(prev);
@@ -69,6 +121,30 @@ export function useFileNavigation({
       setExplorerLoadingStatus(false);
     };
 
+    const onFsChanged = ({ changes }: { changes: FsChange[] }) => {
+      const stale = new Set<string>();
+      const removed = new Set<string>();
+      for (const change of changes) {
+        stale.add(parentDir(change.path));
+        if (change.old_path) stale.add(parentDir(change.old_path));
+        if (change.kind === "deleted") removed.add(change.path);
+        if (change.kind === "renamed" && change.old_path) removed.add(change.old_path);
+      }
+      if (removed.size > 0) {
+        setTreeMap((prev) => {
+          const next = new Map(prev);
+          removed.forEach((path) => next.delete(path));
+          return next;
+        });
+      }
+      if (changes.some((change) => isRulesFile(change.path))) {
+        treeMapRef.current.forEach((_, dir) => stale.add(dir));
+      }
+      stale.forEach((dir) => {
+        if (treeMapRef.current.has(dir) && !removed.has(dir)) fetchPath(dir);
+      });
+    };
+
     const onTerminalError = () => setExplorerLoadingStatus(false);
 
     const onTerminalSuccess = () => {
@@ -80,15 +156,17 @@ export function useFileNavigation({
     };
 
     socket.on("repo_structure", onRepoStructure);
+    socket.on("fs_changed", onFsChanged);
     socket.on("terminal_error", onTerminalError);
     socket.on("terminal_success", onTerminalSuccess);
 
     return () => {
       socket.off("repo_structure", onRepoStructure);
+      socket.off("fs_changed", onFsChanged);
       socket.off("terminal_error", onTerminalError);
       socket.off("terminal_success", onTerminalSuccess);
     };
-  }, [email, openRepo, repoLoadedRef, terminalReadyRef]);
+  }, [email, openRepo, fetchPath, repoLoadedRef, terminalReadyRef]);
 
   useEffect(() => {
     if (!email) return;
@@ -6,6 +6,28 @@ export type SaveStatus = "idle" | "saving" | "saved" | "error";
 
 export type OpenFile = { name: string; absolutePath: string };
 
+export type FileData = {
+  path: string;
+  content: string | null;
+  data: ArrayBuffer | null;
+  mime_type: string;
+  size: number;
+  is_binary: boolean;
+  version: string;
+};
+
+export type FileSaved = { path: string; version: string };
+
+export type FileConflict = { path: string; expected_version: string; current: FileData | null };
+
+export type TextEdit = { offset: number; length: number; text: string };
+
+export type PatchRejected = { path: string; base_version: string; reason: string };
+
+export type FileError = { op: string; path: string; message: string };
+
+export type BinaryFile = { mimeType: string; size: number; url: string };
+
 export const LANG_MAP: Record<string, string> = {
   js: "javascript", jsx: "javascript",
   ts: "typescript", tsx: "typescript",
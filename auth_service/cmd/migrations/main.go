@@ -38,4 +38,19 @@ func main() {
 		log.Printf("Error migrating WorkspaceContainer: %v", err)
 		panic(err)
 	}
+
+	if err := database.DB.AutoMigrate(&model.WorkspaceHook{}); err != nil {
+		log.Printf("Error migrating WorkspaceHook: %v", err)
+		panic(err)
+	}
+
+	if err := database.DB.AutoMigrate(&model.TerminalPreference{}); err != nil {
+		log.Printf("Error migrating TerminalPreference: %v", err)
+		panic(err)
+	}
+
+	if err := database.DB.AutoMigrate(&model.WorkspaceTask{}); err != nil {
+		log.Printf("Error migrating WorkspaceTask: %v", err)
+		panic(err)
+	}
 }
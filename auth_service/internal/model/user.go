@@ -4,6 +4,7 @@ import (
 	"time"
 
 	"github.com/google/uuid"
+	"gorm.io/datatypes"
 	"gorm.io/gorm"
 )
 
@@ -76,11 +77,50 @@ type RefreshToken struct {
 }
 
 // WorkspaceContainer tracks the Docker dev-env container for each user.
+// WorkspaceDir is where the workspace opens, e.g. the repo cloned at creation time.
 type WorkspaceContainer struct {
 	Base
-	UserID      uuid.UUID `gorm:"type:uuid;not null;index"`
-	ContainerID string    `gorm:"not null"`
-	ImageName   string    `gorm:"not null;default:'ubuntu:20.04'"`
-	Status      string    `gorm:"not null;default:'created'"`
-	User        User      `gorm:"foreignKey:UserID;constraint:OnDelete:CASCADE"`
+	UserID       uuid.UUID `gorm:"type:uuid;not null;index"`
+	ContainerID  string    `gorm:"not null"`
+	ImageName    string    `gorm:"not null;default:'ubuntu:20.04'"`
+	Status       string    `gorm:"not null;default:'created'"`
+	WorkspaceDir *string   `gorm:"type:text"`
+	User         User      `gorm:"foreignKey:UserID;constraint:OnDelete:CASCADE"`
+}
+
+// WorkspaceHook is a lifecycle command run inside a workspace container by ws_ide.
+// Template hooks set Template (the image name) and leave UserID nil; workspace hooks set UserID.
+// Stage is one of on_create, on_start or on_attach; hooks run in ascending Position.
+type WorkspaceHook struct {
+	Base
+	UserID   *uuid.UUID `gorm:"type:uuid;index"`
+	Template *string    `gorm:"index"`
+	Stage    string     `gorm:"not null;index"`
+	Command  string     `gorm:"not null"`
+	Position int        `gorm:"not null;default:0"`
+}
+
+// TerminalPreference holds a user's defaults for new terminals in ws_ide.
+// Nil fields fall back to ws_ide's built-in defaults; Env is a JSON object of extra variables.
+type TerminalPreference struct {
+	Base
+	UserID uuid.UUID      `gorm:"type:uuid;not null;uniqueIndex"`
+	Shell  *string        `gorm:"type:text"`
+	Cwd    *string        `gorm:"type:text"`
+	Env    datatypes.JSON `gorm:"type:jsonb;not null;default:'{}'"`
+	Rows   *int
+	Cols   *int
+}
+
+// WorkspaceTask is a named command (build, test, run, ...) a user can run from ws_ide.
+// Tasks in the workspace's .aks/tasks.json override these by name. ProblemMatcher is one of
+// rustc, tsc, gcc or eslint and selects how the task's output is parsed into diagnostics.
+type WorkspaceTask struct {
+	Base
+	UserID         uuid.UUID `gorm:"type:uuid;not null;uniqueIndex:idx_workspace_task_name"`
+	Name           string    `gorm:"not null;uniqueIndex:idx_workspace_task_name"`
+	Command        string    `gorm:"not null"`
+	Cwd            *string   `gorm:"type:text"`
+	ProblemMatcher *string   `gorm:"type:text"`
+	Position       int       `gorm:"not null;default:0"`
 }
@@ -4,24 +4,26 @@ use bollard::models::HostConfig;
 use bollard::Docker;
 use futures_util::stream::TryStreamExt;
 use sea_orm::{ColumnTrait, EntityTrait, QueryFilter, Set};
-use socketioxide::extract::SocketRef;
-use socketioxide::socket::Sid;
 use uuid::Uuid;
 
+use crate::docker_vm::exec::exec_output;
+use crate::docker_vm::provision::{ContainerOrigin, ProgressSubscribers};
 use crate::entities::{users, workspace_containers};
 use crate::events;
-use crate::state::AppState;
-use crate::types::TerminalStatusPayload;
+use crate::state::{workspace_slug, AppState};
 
-const IMAGE: &str = "ubuntu:20.04";
+pub const IMAGE: &str = "ubuntu:20.04";
+
+/// Tools ws_ide itself relies on inside the workspace, e.g. `inotifywait`
+/// for the file watcher. The stock image has none of them.
+const SETUP_SCRIPT: &str = "command -v inotifywait >/dev/null 2>&1 || \
+    (apt-get update && DEBIAN_FRONTEND=noninteractive apt-get install -y --no-install-recommends inotify-tools)";
 
 pub async fn create_container(
-    s: &SocketRef,
-    _id: Sid,
+    progress: &ProgressSubscribers,
     state: AppState,
     email: String,
-    terminal_id: String,
-) -> Option<String> {
+) -> Option<(String, ContainerOrigin)> {
     println!(
         "[container] Creating dev container for {} using image {}",
         email, IMAGE
@@ -35,21 +37,13 @@ pub async fn create_container(
         }
         Err(e) => {
             eprintln!("[container] Step 1 FAIL: cannot reach Docker - {}", e);
-            s.emit(
-                events::outgoing::TERMINAL_ERROR,
-                &TerminalStatusPayload { terminal_id: terminal_id.clone(), message: format!("Failed to connect to Docker: {}", e) },
-            )
-            .ok();
+            progress.emit_status(events::outgoing::TERMINAL_ERROR, format!("Failed to connect to Docker: {}", e));
             return None;
         }
     };
 
     println!("[container] Step 2: pulling image `{}`", IMAGE);
-    s.emit(
-        events::outgoing::TERMINAL_INFO,
-        &TerminalStatusPayload { terminal_id: terminal_id.clone(), message: "Pulling Ubuntu image, this may take a moment".to_string() },
-    )
-    .ok();
+    progress.emit_status(events::outgoing::TERMINAL_INFO, "Pulling Ubuntu image, this may take a moment".to_string());
 
     let image_pull_result = docker
         .create_image(
@@ -66,27 +60,16 @@ pub async fn create_container(
     match image_pull_result {
         Ok(_) => {
             println!("[container] Step 2 OK: image `{}` ready", IMAGE);
-            s.emit(
-                events::outgoing::TERMINAL_INFO,
-                &TerminalStatusPayload { terminal_id: terminal_id.clone(), message: "Image pulled successfully. Creating container".to_string() },
-            )
-            .ok();
+            progress.emit_status(events::outgoing::TERMINAL_INFO, "Image pulled successfully. Creating container".to_string());
         }
         Err(e) => {
             eprintln!("[container] Step 2 FAIL: could not pull image - {}", e);
-            s.emit(events::outgoing::TERMINAL_ERROR, &TerminalStatusPayload { terminal_id: terminal_id.clone(), message: format!("Failed to pull image: {}", e) })
-                .ok();
+            progress.emit_status(events::outgoing::TERMINAL_ERROR, format!("Failed to pull image: {}", e));
             return None;
         }
     }
 
-    let container_name = format!(
-        "dev-env-{}",
-        email
-            .chars()
-            .map(|c| if c.is_alphanumeric() { c } else { '-' })
-            .collect::<String>()
-    );
+    let container_name = format!("dev-env-{}", workspace_slug(&email));
     println!(
         "[container] Step 3: creating container name={} image={}",
         container_name, IMAGE
@@ -125,14 +108,15 @@ pub async fn create_container(
                 .await
             {
                 eprintln!("[container] Step 3 FAIL: could not restart container - {}", e);
-                s.emit(events::outgoing::TERMINAL_ERROR, &TerminalStatusPayload { terminal_id: terminal_id.clone(), message: format!("Failed to restart container: {}", e) }).ok();
+                progress.emit_status(events::outgoing::TERMINAL_ERROR, format!("Failed to restart container: {}", e));
                 return None;
             }
             tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
         }
-        s.emit(events::outgoing::TERMINAL_INFO, &TerminalStatusPayload { terminal_id: terminal_id.clone(), message: "Reconnecting to existing workspace container".to_string() }).ok();
+        progress.emit_status(events::outgoing::TERMINAL_INFO, "Reconnecting to existing workspace container".to_string());
         println!("[container] ── reusing existing container, returning id={}", existing_id);
-        return Some(existing_id);
+        let origin = if running { ContainerOrigin::AlreadyRunning } else { ContainerOrigin::Started };
+        return Some((existing_id, origin));
     }
 
     let create_result = docker
@@ -151,11 +135,7 @@ pub async fn create_container(
                 "[container] Step 3 OK: container created - id={}",
                 container.id
             );
-            s.emit(
-                events::outgoing::TERMINAL_INFO,
-                &TerminalStatusPayload { terminal_id: terminal_id.clone(), message: "Container created successfully. Starting container".to_string() },
-            )
-            .ok();
+            progress.emit_status(events::outgoing::TERMINAL_INFO, "Container created successfully. Starting container".to_string());
             container
         }
         Err(e) => {
@@ -163,11 +143,7 @@ pub async fn create_container(
                 "[container] Step 3 FAIL: could not create container - {}",
                 e
             );
-            s.emit(
-                events::outgoing::TERMINAL_ERROR,
-                &TerminalStatusPayload { terminal_id: terminal_id.clone(), message: format!("Failed to create container: {}", e) },
-            )
-            .ok();
+            progress.emit_status(events::outgoing::TERMINAL_ERROR, format!("Failed to create container: {}", e));
             return None;
         }
     };
@@ -182,26 +158,30 @@ pub async fn create_container(
                 "[container] Step 4 OK: container running - id={}",
                 container.id
             );
-            s.emit(
-                events::outgoing::TERMINAL_INFO,
-                &TerminalStatusPayload { terminal_id: terminal_id.clone(), message: "Container started successfully. Setting up workspace".to_string() },
-            )
-            .ok();
+            progress.emit_status(events::outgoing::TERMINAL_INFO, "Container started successfully. Setting up workspace".to_string());
             println!("[container] Step 4: waiting 500ms for container to stabilise");
             tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
             println!("[container] Step 4: container ready");
         }
         Err(e) => {
             eprintln!("[container] Step 4 FAIL: could not start container - {}", e);
-            s.emit(
-                events::outgoing::TERMINAL_ERROR,
-                &TerminalStatusPayload { terminal_id: terminal_id.clone(), message: format!("Failed to start container: {}", e) },
-            )
-            .ok();
+            progress.emit_status(events::outgoing::TERMINAL_ERROR, format!("Failed to start container: {}", e));
             return None;
         }
     }
 
+    // Missing tools only disable the features that need them.
+    println!("[container] Step 4b: installing workspace tools id={}", container.id);
+    match exec_output(&container.id, &["/bin/sh", "-c", SETUP_SCRIPT], None).await {
+        Ok((0, _)) => println!("[container] Step 4b OK: workspace tools installed"),
+        Ok((code, output)) => eprintln!(
+            "[container] Step 4b FAIL: installing workspace tools exited with {}: {}",
+            code,
+            output.trim()
+        ),
+        Err(e) => eprintln!("[container] Step 4b FAIL: could not run setup - {}", e),
+    }
+
     // Find user by email then record the container in workspace_containers
     println!(
         "[container] Step 5: looking up user by email={} to store container record",
@@ -234,38 +214,25 @@ pub async fn create_container(
                         "[container] Step 5 OK: stored container={} → user={}",
                         container.id, user.id
                     );
-                    s.emit(
-                        events::outgoing::TERMINAL_INFO,
-                        &TerminalStatusPayload { terminal_id: terminal_id.clone(), message: "Workspace recorded. Container ready for terminal session.".to_string() },
-                    )
-                    .ok();
+                    progress.emit_status(events::outgoing::TERMINAL_INFO, "Workspace recorded. Container ready for terminal session.".to_string());
                 }
                 Err(e) => {
                     eprintln!(
                         "[container] Step 5 FAIL: could not insert workspace_containers row - {}",
                         e
                     );
-                    s.emit(
-                        events::outgoing::TERMINAL_ERROR,
-                        &TerminalStatusPayload { terminal_id: terminal_id.clone(), message: format!("Failed to record workspace: {}", e) },
-                    )
-                    .ok();
+                    progress.emit_status(events::outgoing::TERMINAL_ERROR, format!("Failed to record workspace: {}", e));
                 }
             }
         }
         Ok(None) => {
             eprintln!("[container] Step 5 FAIL: no user found for email={}", email);
-            s.emit(
-                events::outgoing::TERMINAL_ERROR,
-                &TerminalStatusPayload { terminal_id: terminal_id.clone(), message: format!("No user found with email: {}", email) },
-            )
-            .ok();
+            progress.emit_status(events::outgoing::TERMINAL_ERROR, format!("No user found with email: {}", email));
             return None;
         }
         Err(e) => {
             eprintln!("[container] Step 5 FAIL: DB error looking up user - {}", e);
-            s.emit(events::outgoing::TERMINAL_ERROR, &TerminalStatusPayload { terminal_id: terminal_id.clone(), message: format!("Database error: {}", e) })
-                .ok();
+            progress.emit_status(events::outgoing::TERMINAL_ERROR, format!("Database error: {}", e));
             return None;
         }
     }
@@ -274,5 +241,5 @@ pub async fn create_container(
         "[container] ── create_container done, returning id={}",
         container.id
     );
-    Some(container.id)
+    Some((container.id, ContainerOrigin::Created))
 }
@@ -0,0 +1,226 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::process::{Child, Command};
+use tokio::sync::mpsc;
+
+use crate::terminal_session::utf8_decoder::Utf8StreamDecoder;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+/// How a command finished. `exit_code` is `None` when it was killed.
+#[derive(Debug, Clone, Copy)]
+pub struct ExecOutcome {
+    pub exit_code: Option<i32>,
+    pub timed_out: bool,
+}
+
+fn pump<R>(mut reader: R, stream: OutputStream, tx: mpsc::UnboundedSender<(OutputStream, String)>)
+where
+    R: AsyncRead + Unpin + Send + 'static,
+{
+    tokio::spawn(async move {
+        let mut buf = [0u8; 4096];
+        let mut decoder = Utf8StreamDecoder::default();
+        loop {
+            match reader.read(&mut buf).await {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    let text = decoder.decode(&buf[..n]);
+                    if !text.is_empty() && tx.send((stream, text)).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+}
+
+/// Kills every process in the container whose environment has `name=value`.
+/// `docker exec` has no way to signal what it started, so commands that may
+/// need killing are tagged with a marker variable instead.
+pub async fn kill_marked(container_id: &str, name: &str, value: &str) -> Result<(), std::io::Error> {
+    let script = r#"for p in /proc/[0-9]*; do
+    if tr '\0' '\n' < "$p/environ" 2>/dev/null | grep -qx "$1=$2"; then kill -KILL "${p#/proc/}" 2>/dev/null; fi
+done"#;
+    // Straight through exec_raw: exec_with calls this on timeout, so going
+    // through exec_output would make the two recursive.
+    exec_raw(container_id, &["/bin/sh", "-c", script, "sh", name, value], &[]).await?;
+    Ok(())
+}
+
+/// Sends `signal` (`INT`, `TERM`, ...) to every process in group `pgid`
+/// inside the container.
+pub async fn signal_process_group(container_id: &str, pgid: i32, signal: &str) -> Result<(), std::io::Error> {
+    let pgid = pgid.to_string();
+    let (code, output) = exec_output(
+        container_id,
+        &["/bin/sh", "-c", r#"kill -s "$1" -- "-$2""#, "sh", signal, &pgid],
+        None,
+    )
+    .await?;
+    if code != 0 {
+        return Err(std::io::Error::other(format!(
+            "kill -s {} -{} failed: {}", signal, pgid, output.trim()
+        )));
+    }
+    Ok(())
+}
+
+/// Runs `argv` inside the container with `env` set, handing every chunk of
+/// stdout/stderr to `on_output` as it arrives. When `timeout` elapses the
+/// process is killed; pass `kill_marker` (an env var set in `env`) so the
+/// kill reaches the process inside the container and not just `docker exec`.
+pub async fn exec_with<F>(
+    container_id: &str,
+    argv: &[&str],
+    workdir: Option<&str>,
+    env: &HashMap<String, String>,
+    timeout: Option<(Duration, &str)>,
+    mut on_output: F,
+) -> Result<ExecOutcome, std::io::Error>
+where
+    F: FnMut(OutputStream, &str),
+{
+    let mut cmd = Command::new("docker");
+    cmd.arg("exec");
+    if let Some(dir) = workdir {
+        cmd.arg("-w").arg(dir);
+    }
+    for (name, value) in env {
+        cmd.arg("-e").arg(format!("{}={}", name, value));
+    }
+    cmd.arg(container_id)
+        .args(argv)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+
+    let mut child = cmd.spawn()?;
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    if let Some(stdout) = child.stdout.take() {
+        pump(stdout, OutputStream::Stdout, tx.clone());
+    }
+    if let Some(stderr) = child.stderr.take() {
+        pump(stderr, OutputStream::Stderr, tx.clone());
+    }
+    drop(tx);
+
+    let run = async {
+        while let Some((stream, chunk)) = rx.recv().await {
+            on_output(stream, &chunk);
+        }
+        child.wait().await
+    };
+
+    match timeout {
+        None => {
+            let status = run.await?;
+            Ok(ExecOutcome { exit_code: status.code(), timed_out: false })
+        }
+        Some((limit, marker)) => match tokio::time::timeout(limit, run).await {
+            Ok(status) => Ok(ExecOutcome { exit_code: status?.code(), timed_out: false }),
+            Err(_) => {
+                if let Some(value) = env.get(marker) {
+                    if let Err(e) = kill_marked(container_id, marker, value).await {
+                        eprintln!("[exec] failed to kill timed out command in {}: {}", container_id, e);
+                    }
+                }
+                Ok(ExecOutcome { exit_code: None, timed_out: true })
+            }
+        },
+    }
+}
+
+/// Runs `argv` inside the container and hands every chunk of stdout/stderr to
+/// `on_output` as it arrives. Returns the exit code (-1 if killed by a signal).
+pub async fn exec_streaming<F>(
+    container_id: &str,
+    argv: &[&str],
+    workdir: Option<&str>,
+    mut on_output: F,
+) -> Result<i32, std::io::Error>
+where
+    F: FnMut(&str),
+{
+    let outcome = exec_with(container_id, argv, workdir, &HashMap::new(), None, |_, chunk| {
+        on_output(chunk)
+    })
+    .await?;
+    Ok(outcome.exit_code.unwrap_or(-1))
+}
+
+/// Like `exec_streaming`, but collects stdout and stderr into one string.
+pub async fn exec_output(
+    container_id: &str,
+    argv: &[&str],
+    workdir: Option<&str>,
+) -> Result<(i32, String), std::io::Error> {
+    let mut output = String::new();
+    let code = exec_streaming(container_id, argv, workdir, |chunk| output.push_str(chunk)).await?;
+    Ok((code, output))
+}
+
+/// Runs `argv` inside the container with `input` on its stdin. Returns the
+/// exit code, raw stdout and stderr.
+pub async fn exec_raw(
+    container_id: &str,
+    argv: &[&str],
+    input: &[u8],
+) -> Result<(i32, Vec<u8>, String), std::io::Error> {
+    let mut child = Command::new("docker")
+        .arg("exec")
+        .arg("-i")
+        .arg(container_id)
+        .args(argv)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(input).await?;
+    }
+    let output = child.wait_with_output().await?;
+    Ok((
+        output.status.code().unwrap_or(-1),
+        output.stdout,
+        String::from_utf8_lossy(&output.stderr).into_owned(),
+    ))
+}
+
+/// Like `exec_raw`, but with stdout and stderr as one string, like
+/// `exec_output`.
+pub async fn exec_with_input(
+    container_id: &str,
+    argv: &[&str],
+    input: &[u8],
+) -> Result<(i32, String), std::io::Error> {
+    let (code, stdout, stderr) = exec_raw(container_id, argv, input).await?;
+    let mut text = String::from_utf8_lossy(&stdout).into_owned();
+    text.push_str(&stderr);
+    Ok((code, text))
+}
+
+/// Starts `argv` inside the container with its raw stdout piped, for output
+/// too large to collect. Dropping the child kills it.
+pub fn exec_reader(container_id: &str, argv: &[&str]) -> Result<Child, std::io::Error> {
+    Command::new("docker")
+        .arg("exec")
+        .arg(container_id)
+        .args(argv)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+}
@@ -0,0 +1,121 @@
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+use crate::{
+    docker_vm::exec::{exec_with, OutputStream},
+    state::AppState,
+    terminal_session::options::is_valid_env_name,
+    types::{ExecRequest, ExecResult},
+};
+
+/// Output kept per stream in the result; the rest is only available when
+/// streaming.
+const MAX_CAPTURE_BYTES: usize = 1024 * 1024;
+
+const DEFAULT_TIMEOUT_MS: u64 = 60_000;
+const MAX_TIMEOUT_MS: u64 = 30 * 60_000;
+
+/// Env var tagging every process an exec starts, so a timeout can kill them.
+const EXEC_MARKER: &str = "AKS_EXEC_ID";
+
+fn capture(buf: &mut String, chunk: &str, truncated: &mut bool) {
+    let room = MAX_CAPTURE_BYTES.saturating_sub(buf.len());
+    if chunk.len() <= room {
+        buf.push_str(chunk);
+        return;
+    }
+    let mut end = room;
+    while !chunk.is_char_boundary(end) {
+        end -= 1;
+    }
+    buf.push_str(&chunk[..end]);
+    *truncated = true;
+}
+
+/// Runs `request` in `email`'s workspace container. When the request asks to
+/// stream, chunks go to `on_output` and are not captured in the result.
+pub async fn run_exec_command<F>(
+    state: &AppState,
+    email: &str,
+    request: &ExecRequest,
+    mut on_output: F,
+) -> Result<ExecResult, std::io::Error>
+where
+    F: FnMut(OutputStream, &str),
+{
+    let container_id = state
+        .docker_container_id
+        .get(email)
+        .map(|r| r.clone())
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("No Docker container found for email: {}", email),
+            )
+        })?;
+
+    let argv: Vec<String> = match (&request.argv, &request.command) {
+        (Some(argv), None) if !argv.is_empty() => argv.clone(),
+        (None, Some(command)) if !command.trim().is_empty() => {
+            vec!["/bin/sh".to_string(), "-c".to_string(), command.clone()]
+        }
+        _ => {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Provide either a non-empty argv or a command string",
+            ))
+        }
+    };
+
+    if let Some(bad) = request.env.keys().find(|k| !is_valid_env_name(k)) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("Invalid environment variable name: {}", bad),
+        ));
+    }
+    let mut env = request.env.clone();
+    let exec_id = Uuid::new_v4().to_string();
+    env.insert(EXEC_MARKER.to_string(), exec_id);
+
+    let cwd = request
+        .cwd
+        .clone()
+        .or_else(|| state.workspace_root.get(email).map(|r| r.clone()));
+    let timeout = Duration::from_millis(
+        request.timeout_ms.unwrap_or(DEFAULT_TIMEOUT_MS).clamp(1, MAX_TIMEOUT_MS),
+    );
+
+    let mut stdout = String::new();
+    let mut stderr = String::new();
+    let mut truncated = false;
+    let started = Instant::now();
+
+    let argv_refs: Vec<&str> = argv.iter().map(String::as_str).collect();
+    let outcome = exec_with(
+        &container_id,
+        &argv_refs,
+        cwd.as_deref(),
+        &env,
+        Some((timeout, EXEC_MARKER)),
+        |stream, chunk| {
+            if request.stream {
+                on_output(stream, chunk);
+                return;
+            }
+            match stream {
+                OutputStream::Stdout => capture(&mut stdout, chunk, &mut truncated),
+                OutputStream::Stderr => capture(&mut stderr, chunk, &mut truncated),
+            }
+        },
+    )
+    .await?;
+
+    Ok(ExecResult {
+        stdout,
+        stderr,
+        exit_code: outcome.exit_code,
+        duration_ms: started.elapsed().as_millis() as u64,
+        timed_out: outcome.timed_out,
+        truncated,
+    })
+}
@@ -0,0 +1,301 @@
+use uuid::Uuid;
+
+use crate::docker_vm::exec::{exec_output, exec_raw, exec_with_input};
+
+/// Makes `path` absolute against the workspace root and folds `.` and `..`
+/// away, so checks on the result see the path the container will use.
+pub fn resolve_path(root: Option<&str>, path: &str) -> Result<String, String> {
+    let path = path.trim();
+    if path.is_empty() {
+        return Err("Path is empty".to_string());
+    }
+    let joined = if path.starts_with('/') {
+        path.to_string()
+    } else {
+        format!("{}/{}", root.unwrap_or("/").trim_end_matches('/'), path)
+    };
+    let mut parts: Vec<&str> = Vec::new();
+    for part in joined.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            _ => parts.push(part),
+        }
+    }
+    Ok(format!("/{}", parts.join("/")))
+}
+
+/// Resolves `path` as `resolve_path` does, but only to a path inside the
+/// workspace root. Without a known root nothing is allowed.
+pub fn resolve_in_workspace(root: Option<&str>, path: &str) -> Result<String, String> {
+    let root = root.ok_or_else(|| "No workspace is open".to_string())?;
+    let root = resolve_path(None, root)?;
+    let resolved = resolve_path(Some(&root), path)?;
+    let inside = root == "/"
+        || resolved == root
+        || resolved.strip_prefix(&root).is_some_and(|rest| rest.starts_with('/'));
+    if !inside {
+        return Err(format!("{} is outside the workspace", resolved));
+    }
+    Ok(resolved)
+}
+
+/// Refuses to delete or move `/` or the workspace root itself.
+pub fn ensure_removable(root: Option<&str>, path: &str) -> Result<(), String> {
+    if path == "/" || root.is_some_and(|root| root.trim_end_matches('/') == path) {
+        return Err(format!("Refusing to modify {}", path));
+    }
+    Ok(())
+}
+
+/// Runs `script` with `args` in the container, feeding it `input` when given.
+/// Returns its output; a failing script's output is the reason reported to
+/// the client.
+async fn run_script_with(
+    container_id: &str,
+    script: &str,
+    args: &[&str],
+    input: Option<&[u8]>,
+) -> Result<String, String> {
+    let mut argv = vec!["/bin/sh", "-c", script, "sh"];
+    argv.extend_from_slice(args);
+    let result = match input {
+        Some(input) => exec_with_input(container_id, &argv, input).await,
+        None => exec_output(container_id, &argv, None).await,
+    };
+    let (code, output) = result.map_err(|e| format!("Failed to execute docker command: {}", e))?;
+    if code == 0 {
+        return Ok(output);
+    }
+    let reason = output.trim();
+    Err(if reason.is_empty() {
+        format!("Command exited with {}", code)
+    } else {
+        reason.to_string()
+    })
+}
+
+async fn run_script(container_id: &str, script: &str, args: &[&str]) -> Result<(), String> {
+    run_script_with(container_id, script, args, None).await.map(|_| ())
+}
+
+fn flag(value: bool) -> &'static str {
+    if value {
+        "1"
+    } else {
+        "0"
+    }
+}
+
+/// Creates an empty file, and its parent directories. An existing file is
+/// only truncated with `overwrite`.
+pub async fn create_file(container_id: &str, path: &str, overwrite: bool) -> Result<(), String> {
+    let script = r#"if [ -d "$1" ]; then echo "$1 is a directory" >&2; exit 1; fi
+if [ -e "$1" ] && [ "$2" != 1 ]; then echo "$1 already exists" >&2; exit 1; fi
+mkdir -p -- "$(dirname -- "$1")" && : > "$1""#;
+    run_script(container_id, script, &[path, flag(overwrite)]).await
+}
+
+/// Creates a directory, and its parents. Fails if the path already exists.
+pub async fn create_directory(container_id: &str, path: &str) -> Result<(), String> {
+    let script = r#"if [ -e "$1" ]; then echo "$1 already exists" >&2; exit 1; fi
+mkdir -p -- "$1""#;
+    run_script(container_id, script, &[path]).await
+}
+
+/// Deletes a file, a symlink or an empty directory; a directory with
+/// contents only when `recursive`.
+pub async fn delete_path(container_id: &str, path: &str, recursive: bool) -> Result<(), String> {
+    let script = r#"if [ ! -e "$1" ] && [ ! -L "$1" ]; then echo "$1 does not exist" >&2; exit 1; fi
+if [ -d "$1" ] && [ ! -L "$1" ]; then
+    if [ "$2" = 1 ]; then rm -rf -- "$1"
+    elif [ -z "$(ls -A -- "$1")" ]; then rmdir -- "$1"
+    else echo "$1 is not empty; delete it recursively" >&2; exit 1
+    fi
+else
+    rm -f -- "$1"
+fi"#;
+    run_script(container_id, script, &[path, flag(recursive)]).await
+}
+
+/// Checks shared by move and copy, run before the operation itself.
+const TRANSFER_CHECKS: &str = r#"if [ ! -e "$1" ] && [ ! -L "$1" ]; then echo "$1 does not exist" >&2; exit 1; fi
+case "$2/" in "$1"/*) echo "Cannot put $1 inside itself" >&2; exit 1 ;; esac
+if [ -d "$2" ]; then echo "$2 is an existing directory" >&2; exit 1; fi
+if [ -e "$2" ] && [ "$3" != 1 ]; then echo "$2 already exists" >&2; exit 1; fi
+mkdir -p -- "$(dirname -- "$2")" || exit 1
+"#;
+
+/// Renames or moves `from` to `to`. An existing file at `to` is only
+/// replaced with `overwrite`; an existing directory never is.
+pub async fn move_path(container_id: &str, from: &str, to: &str, overwrite: bool) -> Result<(), String> {
+    let script = format!(r#"{}mv -f -- "$1" "$2""#, TRANSFER_CHECKS);
+    run_script(container_id, &script, &[from, to, flag(overwrite)]).await
+}
+
+/// Copies `from` (recursively, keeping modes and links) to `to`, on the same
+/// terms as `move_path`.
+pub async fn copy_path(container_id: &str, from: &str, to: &str, overwrite: bool) -> Result<(), String> {
+    let script = format!(r#"{}cp -a -- "$1" "$2""#, TRANSFER_CHECKS);
+    run_script(container_id, &script, &[from, to, flag(overwrite)]).await
+}
+
+/// Size in bytes of the regular file at `path`.
+pub async fn file_size(container_id: &str, path: &str) -> Result<u64, String> {
+    let script = r#"if [ ! -f "$1" ]; then echo "$1 is not a file" >&2; exit 1; fi
+wc -c < "$1""#;
+    let output = run_script_with(container_id, script, &[path], None).await?;
+    output
+        .trim()
+        .parse()
+        .map_err(|_| format!("Unexpected size for {}: {}", path, output.trim()))
+}
+
+/// Creates the empty part file an upload to `path` is written to.
+pub async fn prepare_upload(container_id: &str, part: &str, path: &str) -> Result<(), String> {
+    let script = r#"if [ -d "$2" ]; then echo "$2 is a directory" >&2; exit 1; fi
+mkdir -p -- "$(dirname -- "$2")" && : > "$1""#;
+    run_script(container_id, script, &[part, path]).await
+}
+
+/// Appends `data` to the part file, which must hold exactly `offset` bytes.
+/// Anything past `offset`, left by a chunk that failed half way, is cut off
+/// first.
+pub async fn append_chunk(container_id: &str, part: &str, offset: u64, data: &[u8]) -> Result<(), String> {
+    let script = r#"if [ ! -f "$1" ]; then echo "The partial upload is gone; start it again" >&2; exit 1; fi
+size=$(wc -c < "$1" | tr -d ' ')
+if [ "$size" -lt "$2" ]; then echo "The partial upload has $size of $2 bytes; start it again" >&2; exit 1; fi
+if [ "$size" -gt "$2" ]; then truncate -s "$2" "$1" || exit 1; fi
+cat >> "$1""#;
+    run_script_with(container_id, script, &[part, &offset.to_string()], Some(data))
+        .await
+        .map(|_| ())
+}
+
+/// Moves a finished part file over `path`.
+pub async fn commit_upload(container_id: &str, part: &str, path: &str) -> Result<(), String> {
+    run_script(container_id, r#"mv -f -- "$1" "$2""#, &[part, path]).await
+}
+
+/// Removes a file if it exists, e.g. an abandoned part file.
+pub async fn remove_file(container_id: &str, path: &str) -> Result<(), String> {
+    run_script(container_id, r#"rm -f -- "$1""#, &[path]).await
+}
+
+/// Exit status the read and write scripts use for "no such file" and "the
+/// file changed", as opposed to a plain failure.
+const EXIT_MISSING: i32 = 3;
+const EXIT_CONFLICT: i32 = 4;
+
+/// The content-hash half of a version token. Tokens are `<mtime>-<hash>`;
+/// the mtime tells a client its copy is old at a glance, but only the hash
+/// decides a conflict, so a `touch` or a formatter that changes nothing
+/// isn't one.
+pub fn content_hash(version: &str) -> &str {
+    version.rsplit('-').next().unwrap_or(version)
+}
+
+fn docker_error(e: std::io::Error) -> String {
+    format!("Failed to execute docker command: {}", e)
+}
+
+fn script_error(code: i32, stderr: &str) -> String {
+    match stderr.trim() {
+        "" => format!("Command exited with {}", code),
+        reason => reason.to_string(),
+    }
+}
+
+/// Reads a file of at most `max_bytes` with its version token. The token is
+/// taken from a snapshot of the file, so it always matches the bytes
+/// returned. `None` when the file doesn't exist.
+pub async fn read_file(container_id: &str, path: &str, max_bytes: u64) -> Result<Option<(Vec<u8>, String)>, String> {
+    let script = r#"if [ ! -e "$1" ]; then echo "$1 does not exist" >&2; exit 3; fi
+if [ ! -f "$1" ]; then echo "$1 is not a file" >&2; exit 1; fi
+size=$(wc -c < "$1") || exit 1
+if [ "$size" -gt "$2" ]; then echo "$1 is $size bytes; files over $2 bytes must be fetched with download_file" >&2; exit 1; fi
+snap=$(mktemp) || exit 1
+trap 'rm -f -- "$snap"' EXIT
+mtime=$(stat -c %Y -- "$1") && cat -- "$1" > "$snap" || exit 1
+printf '%s-%s\n' "$mtime" "$(sha256sum < "$snap" | cut -c1-16)"
+cat -- "$snap""#;
+    let max = max_bytes.to_string();
+    let (code, stdout, stderr) = exec_raw(container_id, &["/bin/sh", "-c", script, "sh", path, &max], &[])
+        .await
+        .map_err(docker_error)?;
+    match code {
+        0 => {}
+        EXIT_MISSING => return Ok(None),
+        code => return Err(script_error(code, &stderr)),
+    }
+    let Some(split) = stdout.iter().position(|b| *b == b'\n') else {
+        return Err(format!("Unexpected output reading {}", path));
+    };
+    let version = String::from_utf8_lossy(&stdout[..split]).into_owned();
+    Ok(Some((stdout[split + 1..].to_vec(), version)))
+}
+
+/// What `write_file` did.
+pub enum WriteOutcome {
+    /// Written; the file's new version token.
+    Written(String),
+    /// The file no longer has the expected version and was left alone.
+    Conflict,
+}
+
+/// Writes `data` to `path` through a part file renamed over it, keeping the
+/// file's mode. With `expected`, the file is only replaced while its content
+/// still has that version; the check runs right before the rename.
+pub async fn write_file(
+    container_id: &str,
+    path: &str,
+    data: &[u8],
+    expected: Option<&str>,
+) -> Result<WriteOutcome, String> {
+    let script = r#"if [ -d "$1" ]; then echo "$1 is a directory" >&2; exit 1; fi
+mkdir -p -- "$(dirname -- "$1")" || exit 1
+trap 'rm -f -- "$3"' EXIT
+cat > "$3" || exit 1
+if [ -n "$2" ]; then
+    current=missing
+    if [ -f "$1" ]; then current=$(sha256sum < "$1" | cut -c1-16); fi
+    if [ "$current" != "$2" ]; then exit 4; fi
+fi
+if [ -f "$1" ]; then chmod "$(stat -c %a -- "$1")" -- "$3"; fi
+hash=$(sha256sum < "$3" | cut -c1-16)
+mv -f -- "$3" "$1" || exit 1
+printf '%s-%s\n' "$(stat -c %Y -- "$1")" "$hash""#;
+    let (dir, name) = path.rsplit_once('/').unwrap_or(("", path));
+    let part = format!("{}/.{}.{}.save", dir, name, &Uuid::new_v4().to_string()[..8]);
+    let expected = expected.map(content_hash).unwrap_or_default();
+    let (code, stdout, stderr) = exec_raw(container_id, &["/bin/sh", "-c", script, "sh", path, expected, &part], data)
+        .await
+        .map_err(docker_error)?;
+    match code {
+        0 => Ok(WriteOutcome::Written(String::from_utf8_lossy(&stdout).trim().to_string())),
+        EXIT_CONFLICT => Ok(WriteOutcome::Conflict),
+        code => Err(script_error(code, &stderr)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_relative_paths_against_the_root() {
+        assert_eq!(resolve_in_workspace(Some("/workspace/"), "src/main.rs").unwrap(), "/workspace/src/main.rs");
+        assert_eq!(resolve_in_workspace(Some("/workspace"), "/workspace").unwrap(), "/workspace");
+        assert_eq!(resolve_in_workspace(Some("/workspace"), "a/../b").unwrap(), "/workspace/b");
+    }
+
+    #[test]
+    fn refuses_paths_outside_the_root() {
+        assert!(resolve_in_workspace(Some("/workspace"), "/root/.ssh/id_rsa").is_err());
+        assert!(resolve_in_workspace(Some("/workspace"), "../etc/passwd").is_err());
+        assert!(resolve_in_workspace(Some("/workspace"), "/workspace-other/file").is_err());
+        assert!(resolve_in_workspace(None, "file").is_err());
+    }
+}
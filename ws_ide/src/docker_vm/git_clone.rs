@@ -0,0 +1,205 @@
+use sea_orm::{sea_query::Expr, ColumnTrait, EntityTrait, QueryFilter};
+
+use crate::{
+    docker_vm::{
+        exec::{exec_output, exec_streaming},
+        provision::ProgressSubscribers,
+    },
+    entities::workspace_containers,
+    events,
+    state::AppState,
+    types::CloneProgressPayload,
+};
+
+/// Parent directory that cloned repositories are placed under.
+pub const WORKSPACE_ROOT: &str = "/workspace";
+
+#[derive(Debug, Clone)]
+pub struct GitSource {
+    pub url: String,
+    pub branch: Option<String>,
+}
+
+/// `https://host/org/repo.git`, `git@host:org/repo`, `file:///srv/repo.git` and
+/// `/srv/repo.git` all map to `repo`.
+fn repo_dir_name(url: &str) -> Option<String> {
+    let trimmed = url.trim_end_matches('/');
+    let last = trimmed.rsplit(['/', ':']).next()?;
+    let name = last.strip_suffix(".git").unwrap_or(last);
+    let name: String = name
+        .chars()
+        .map(|c| if c.is_alphanumeric() || matches!(c, '-' | '_' | '.') { c } else { '-' })
+        .collect();
+    if name.is_empty() || name.chars().all(|c| c == '.') {
+        None
+    } else {
+        Some(name)
+    }
+}
+
+/// Directories tried for one repo name before giving up.
+const MAX_DIR_ATTEMPTS: u32 = 10;
+
+/// Prints `missing` when `$1` doesn't exist, otherwise `exists` followed by
+/// its origin URL and checked-out branch (empty when it isn't a git repo).
+const CHECKOUT_SCRIPT: &str = r#"[ -e "$1" ] || { echo missing; exit 0; }
+url=$(git -C "$1" remote get-url origin 2>/dev/null) || url=
+branch=$(git -C "$1" rev-parse --abbrev-ref HEAD 2>/dev/null) || branch=
+printf 'exists\n%s\n%s\n' "$url" "$branch""#;
+
+/// What already sits at a clone target.
+#[derive(Debug, Default)]
+struct Checkout {
+    origin: String,
+    branch: String,
+}
+
+impl Checkout {
+    /// Whether it is `source`: the same origin and, when one was asked for,
+    /// the same branch.
+    fn matches(&self, source: &GitSource) -> bool {
+        let normalize = |url: &str| url.trim().trim_end_matches('/').to_string();
+        !self.origin.is_empty()
+            && normalize(&self.origin) == normalize(&source.url)
+            && source.branch.as_deref().is_none_or(|branch| branch == self.branch)
+    }
+}
+
+async fn existing_checkout(container_id: &str, dir: &str) -> Result<Option<Checkout>, String> {
+    let (code, output) = exec_output(container_id, &["/bin/sh", "-c", CHECKOUT_SCRIPT, "sh", dir], None)
+        .await
+        .map_err(|e| format!("Failed to run docker exec: {}", e))?;
+    if code != 0 {
+        return Err(format!("Checking {} exited with status {}", dir, code));
+    }
+    let mut lines = output.lines();
+    if lines.next() != Some("exists") {
+        return Ok(None);
+    }
+    Ok(Some(Checkout {
+        origin: lines.next().unwrap_or_default().trim().to_string(),
+        branch: lines.next().unwrap_or_default().trim().to_string(),
+    }))
+}
+
+fn emit_progress(progress: &ProgressSubscribers, data: &str) {
+    progress.emit_with(events::outgoing::CLONE_PROGRESS, |terminal_id| CloneProgressPayload {
+        terminal_id: terminal_id.to_string(),
+        data: data.to_string(),
+    });
+}
+
+/// Clones `source` into `WORKSPACE_ROOT/<repo>` inside the container, streaming
+/// git's progress output. Returns the directory the workspace should open on.
+/// An existing checkout of the same origin (and branch, if one is given) is
+/// reused instead of cloned again; a different one moves the clone to
+/// `<repo>-2`, `<repo>-3` and so on.
+pub async fn clone_into_workspace(
+    state: &AppState,
+    container_id: &str,
+    source: &GitSource,
+    progress: &ProgressSubscribers,
+) -> Result<String, String> {
+    if source.url.starts_with('-') {
+        return Err(format!("Invalid git URL: {}", source.url));
+    }
+    if source.branch.as_deref().is_some_and(|b| b.starts_with('-')) {
+        return Err(format!("Invalid branch: {}", source.branch.as_deref().unwrap_or_default()));
+    }
+    let name = repo_dir_name(&source.url)
+        .ok_or_else(|| format!("Cannot derive a directory name from {}", source.url))?;
+    let install = "command -v git >/dev/null 2>&1 || \
+                   (apt-get update && DEBIAN_FRONTEND=noninteractive apt-get install -y git ca-certificates)";
+    let code = exec_streaming(container_id, &["/bin/sh", "-c", install], None, |data| {
+        emit_progress(progress, data)
+    })
+    .await
+    .map_err(|e| format!("Failed to run docker exec: {}", e))?;
+    if code != 0 {
+        return Err(format!("Installing git failed with status {}", code));
+    }
+
+    // Another repo or branch with the same name gets `<repo>-2` and so on.
+    let mut target = None;
+    for attempt in 1..=MAX_DIR_ATTEMPTS {
+        let candidate = match attempt {
+            1 => format!("{}/{}", WORKSPACE_ROOT, name),
+            n => format!("{}/{}-{}", WORKSPACE_ROOT, name, n),
+        };
+        match existing_checkout(container_id, &candidate).await? {
+            None => {
+                target = Some(candidate);
+                break;
+            }
+            Some(checkout) if checkout.matches(source) => {
+                emit_progress(progress, &format!("{} already exists, skipping clone\r\n", candidate));
+                record_workspace_dir(state, container_id, &candidate).await;
+                return Ok(candidate);
+            }
+            Some(_) => {}
+        }
+    }
+    let target = target.ok_or_else(|| {
+        format!("{}/{} and the next {} names all hold other checkouts", WORKSPACE_ROOT, name, MAX_DIR_ATTEMPTS - 1)
+    })?;
+
+    let mut argv = vec!["git", "clone", "--progress"];
+    if let Some(branch) = source.branch.as_deref() {
+        argv.extend(["--branch", branch, "--single-branch"]);
+    }
+    argv.extend(["--", source.url.as_str(), target.as_str()]);
+
+    println!("[clone] cloning {} into {} for container {}", source.url, target, container_id);
+    let code = exec_streaming(container_id, &argv, None, |data| emit_progress(progress, data))
+        .await
+        .map_err(|e| format!("Failed to run docker exec: {}", e))?;
+    if code != 0 {
+        return Err(format!("git clone exited with status {}", code));
+    }
+
+    record_workspace_dir(state, container_id, &target).await;
+    Ok(target)
+}
+
+async fn record_workspace_dir(state: &AppState, container_id: &str, dir: &str) {
+    if let Err(e) = workspace_containers::Entity::update_many()
+        .col_expr(workspace_containers::Column::WorkspaceDir, Expr::value(dir))
+        .filter(workspace_containers::Column::ContainerId.eq(container_id))
+        .exec(&*state.db)
+        .await
+    {
+        eprintln!("[clone] failed to record workspace dir for {}: {}", container_id, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(url: &str, branch: Option<&str>) -> GitSource {
+        GitSource { url: url.to_string(), branch: branch.map(str::to_string) }
+    }
+
+    #[test]
+    fn repo_dir_names() {
+        assert_eq!(repo_dir_name("https://github.com/org/repo.git").as_deref(), Some("repo"));
+        assert_eq!(repo_dir_name("https://github.com/org/repo/").as_deref(), Some("repo"));
+        assert_eq!(repo_dir_name("https://github.com/org/repo.git/").as_deref(), Some("repo"));
+        assert_eq!(repo_dir_name("git@github.com:org/repo.git").as_deref(), Some("repo"));
+        assert_eq!(repo_dir_name("git@host:repo").as_deref(), Some("repo"));
+        assert_eq!(repo_dir_name("file:///srv/my repo.git").as_deref(), Some("my-repo"));
+        assert_eq!(repo_dir_name("/srv/repo.js.git").as_deref(), Some("repo.js"));
+        assert_eq!(repo_dir_name("https://host/.."), None);
+        assert_eq!(repo_dir_name("https://host/.git"), None);
+    }
+
+    #[test]
+    fn checkouts_match_on_origin_and_branch() {
+        let checkout = Checkout { origin: "https://github.com/org1/repo.git".to_string(), branch: "main".to_string() };
+        assert!(checkout.matches(&source("https://github.com/org1/repo.git/", None)));
+        assert!(checkout.matches(&source("https://github.com/org1/repo.git", Some("main"))));
+        assert!(!checkout.matches(&source("https://github.com/org2/repo.git", None)));
+        assert!(!checkout.matches(&source("https://github.com/org1/repo.git", Some("dev"))));
+        assert!(!Checkout::default().matches(&source("https://github.com/org1/repo.git", None)));
+    }
+}
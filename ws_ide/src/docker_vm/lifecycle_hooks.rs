@@ -0,0 +1,134 @@
+use sea_orm::{ColumnTrait, Condition, EntityTrait, Order, QueryFilter, QueryOrder};
+
+use crate::{
+    docker_vm::{create_container::IMAGE, exec::exec_streaming, provision::ProgressSubscribers},
+    entities::{users, workspace_hooks},
+    events,
+    state::AppState,
+    types::{HookOutputPayload, HookStatusPayload},
+};
+
+/// Named after devcontainer.json's `onCreateCommand` and friends.
+#[allow(clippy::enum_variant_names)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookStage {
+    /// Once, right after `create_container` made a fresh container.
+    OnCreate,
+    /// Every time `load_terminal` brings a stopped container back up.
+    OnStart,
+    /// Every time a terminal attaches to the container.
+    OnAttach,
+}
+
+impl HookStage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HookStage::OnCreate => "on_create",
+            HookStage::OnStart => "on_start",
+            HookStage::OnAttach => "on_attach",
+        }
+    }
+}
+
+async fn load_hooks(
+    state: &AppState,
+    email: &str,
+    stage: HookStage,
+) -> Result<Vec<workspace_hooks::Model>, sea_orm::DbErr> {
+    let user = users::Entity::find()
+        .filter(users::Column::Email.eq(email))
+        .one(&*state.db)
+        .await?;
+
+    let mut scope = Condition::any().add(
+        Condition::all()
+            .add(workspace_hooks::Column::UserId.is_null())
+            .add(workspace_hooks::Column::Template.eq(IMAGE)),
+    );
+    if let Some(user) = user {
+        scope = scope.add(workspace_hooks::Column::UserId.eq(user.id));
+    }
+
+    let mut hooks = workspace_hooks::Entity::find()
+        .filter(workspace_hooks::Column::Stage.eq(stage.as_str()))
+        .filter(workspace_hooks::Column::DeletedAt.is_null())
+        .filter(scope)
+        .order_by(workspace_hooks::Column::Position, Order::Asc)
+        .all(&*state.db)
+        .await?;
+
+    // Template hooks lay the groundwork, so they always run before workspace hooks.
+    hooks.sort_by_key(|h| h.user_id.is_some());
+    Ok(hooks)
+}
+
+/// Runs the hooks for `stage` inside the container, streaming their output to
+/// `progress`. Stops at the first failing hook and reports it; never panics or
+/// blocks the caller's terminal.
+pub async fn run_hooks(
+    state: &AppState,
+    container_id: &str,
+    email: &str,
+    workdir: Option<&str>,
+    stage: HookStage,
+    progress: &ProgressSubscribers,
+) {
+    let hooks = match load_hooks(state, email, stage).await {
+        Ok(hooks) => hooks,
+        Err(e) => {
+            eprintln!("[hooks] failed to load {} hooks for {}: {}", stage.as_str(), email, e);
+            return;
+        }
+    };
+
+    for hook in hooks {
+        println!("[hooks] {} running `{}` for {}", stage.as_str(), hook.command, email);
+        progress.emit_with(events::outgoing::HOOK_STATUS, |terminal_id| HookStatusPayload {
+            terminal_id: terminal_id.to_string(),
+            stage: stage.as_str().to_string(),
+            command: hook.command.clone(),
+            status: "running".to_string(),
+            exit_code: None,
+            message: format!("Running {} hook", stage.as_str()),
+        });
+
+        let result = exec_streaming(
+            container_id,
+            &["/bin/sh", "-lc", &hook.command],
+            workdir,
+            |data| {
+                progress.emit_with(events::outgoing::HOOK_OUTPUT, |terminal_id| HookOutputPayload {
+                    terminal_id: terminal_id.to_string(),
+                    stage: stage.as_str().to_string(),
+                    command: hook.command.clone(),
+                    data: data.to_string(),
+                });
+            },
+        )
+        .await;
+
+        let (status, exit_code, message) = match result {
+            Ok(0) => ("succeeded", Some(0), format!("{} hook finished", stage.as_str())),
+            Ok(code) => (
+                "failed",
+                Some(code),
+                format!("{} hook exited with status {}", stage.as_str(), code),
+            ),
+            Err(e) => ("failed", None, format!("Failed to run {} hook: {}", stage.as_str(), e)),
+        };
+
+        progress.emit_with(events::outgoing::HOOK_STATUS, |terminal_id| HookStatusPayload {
+            terminal_id: terminal_id.to_string(),
+            stage: stage.as_str().to_string(),
+            command: hook.command.clone(),
+            status: status.to_string(),
+            exit_code,
+            message: message.clone(),
+        });
+
+        if status == "failed" {
+            eprintln!("[hooks] {} for {}: {}", stage.as_str(), email, message);
+            break;
+        }
+    }
+}
@@ -1 +1,7 @@
-pub mod create_container;
\ No newline at end of file
+pub mod create_container;
+pub mod exec;
+pub mod exec_command;
+pub mod file_ops;
+pub mod git_clone;
+pub mod lifecycle_hooks;
+pub mod provision;
\ No newline at end of file
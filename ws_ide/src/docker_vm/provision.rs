@@ -0,0 +1,341 @@
+use bollard::container::{InspectContainerOptions, StartContainerOptions};
+use bollard::Docker;
+use dashmap::mapref::entry::Entry;
+use sea_orm::{
+    ColumnTrait, ConnectionTrait, DatabaseBackend, EntityTrait, Order, QueryFilter, QueryOrder,
+    Statement, TransactionTrait,
+};
+use serde::Serialize;
+use socketioxide::extract::SocketRef;
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+
+use crate::{
+    docker_vm::{
+        create_container::create_container,
+        lifecycle_hooks::{run_hooks, HookStage},
+    },
+    entities::{users, workspace_containers},
+    events,
+    state::AppState,
+    types::TerminalStatusPayload,
+};
+
+/// Every socket waiting on a provisioning run, paired with the terminal tab that asked for it.
+#[derive(Clone, Default)]
+pub struct ProgressSubscribers {
+    inner: Arc<Mutex<Vec<(SocketRef, String)>>>,
+}
+
+impl ProgressSubscribers {
+    pub fn for_socket(s: &SocketRef, terminal_id: &str) -> Self {
+        let progress = Self::default();
+        progress.subscribe(s, terminal_id);
+        progress
+    }
+
+    pub fn subscribe(&self, s: &SocketRef, terminal_id: &str) {
+        let mut subs = self.inner.lock().unwrap();
+        if !subs.iter().any(|(sock, tid)| sock.id == s.id && tid == terminal_id) {
+            subs.push((s.clone(), terminal_id.to_string()));
+        }
+    }
+
+    pub fn emit_with<T, F>(&self, event: &'static str, payload: F)
+    where
+        T: Serialize,
+        F: Fn(&str) -> T,
+    {
+        let subs = self.inner.lock().unwrap().clone();
+        for (s, terminal_id) in subs {
+            s.emit(event, &payload(&terminal_id)).ok();
+        }
+    }
+
+    pub fn emit_status(&self, event: &'static str, message: impl Into<String>) {
+        let message = message.into();
+        self.emit_with(event, |terminal_id| TerminalStatusPayload {
+            terminal_id: terminal_id.to_string(),
+            message: message.clone(),
+        });
+    }
+}
+
+/// How the workspace container came to be running, which decides the lifecycle hooks to fire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContainerOrigin {
+    Created,
+    Started,
+    AlreadyRunning,
+}
+
+/// A workspace container that is up and ready for terminals.
+#[derive(Debug, Clone)]
+pub struct ProvisionedWorkspace {
+    pub container_id: String,
+    /// Directory the workspace opens on, if one was recorded (e.g. a cloned repo).
+    pub root_dir: Option<String>,
+}
+
+impl ProvisionedWorkspace {
+    fn new(container_id: String, root_dir: Option<String>) -> Self {
+        Self { container_id, root_dir }
+    }
+}
+
+/// One in-flight provisioning run for a workspace. Late callers subscribe to its
+/// progress and wait on `result` instead of racing into `create_container`.
+pub struct ProvisionFlight {
+    pub progress: ProgressSubscribers,
+    result: watch::Sender<Option<Option<ProvisionedWorkspace>>>,
+}
+
+impl ProvisionFlight {
+    fn new() -> Self {
+        Self {
+            progress: ProgressSubscribers::default(),
+            result: watch::channel(None).0,
+        }
+    }
+}
+
+/// Held by the leader of a provisioning run. However the leader ends, a
+/// panic included, the flight leaves `state.provisioning` and its waiters are
+/// released, with a failure if no result was sent.
+struct FlightGuard {
+    state: AppState,
+    email: String,
+    flight: Arc<ProvisionFlight>,
+}
+
+impl FlightGuard {
+    fn release(&self) {
+        self.flight.result.send_if_modified(|result| {
+            if result.is_some() {
+                return false;
+            }
+            *result = Some(None);
+            true
+        });
+        self.state
+            .provisioning
+            .remove_if(&self.email, |_, flight| Arc::ptr_eq(flight, &self.flight));
+    }
+}
+
+impl Drop for FlightGuard {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+/// Returns the running container for `email`, creating it if needed. Concurrent
+/// calls for the same workspace share a single run and all receive its progress.
+pub async fn ensure_workspace_container(
+    s: &SocketRef,
+    state: AppState,
+    email: String,
+    terminal_id: String,
+) -> Option<ProvisionedWorkspace> {
+    let (flight, leader) = match state.provisioning.entry(email.clone()) {
+        Entry::Occupied(entry) => (entry.get().clone(), false),
+        Entry::Vacant(entry) => {
+            let flight = Arc::new(ProvisionFlight::new());
+            entry.insert(flight.clone());
+            (flight, true)
+        }
+    };
+    flight.progress.subscribe(s, &terminal_id);
+    let mut rx = flight.result.subscribe();
+
+    if leader {
+        let flight = flight.clone();
+        tokio::spawn(async move {
+            let guard = FlightGuard { state: state.clone(), email: email.clone(), flight: flight.clone() };
+            let result = provision_locked(&flight.progress, state.clone(), &email).await;
+            let stages: &[HookStage] = match result.as_ref().map(|(_, origin)| *origin) {
+                Some(ContainerOrigin::Created) => &[HookStage::OnCreate, HookStage::OnStart],
+                Some(ContainerOrigin::Started) => &[HookStage::OnStart],
+                _ => &[],
+            };
+            // Registered before waiters are released, so their onAttach
+            // hooks see it and wait for setup to finish.
+            let setup_done = (!stages.is_empty()).then(|| {
+                let (tx, rx) = watch::channel(false);
+                state.setup_hooks.insert(email.clone(), rx);
+                tx
+            });
+            flight
+                .result
+                .send_replace(Some(result.as_ref().map(|(ws, _)| ws.clone())));
+            guard.release();
+
+            if let Some((ws, _)) = result {
+                for stage in stages {
+                    run_hooks(
+                        &state,
+                        &ws.container_id,
+                        &email,
+                        ws.root_dir.as_deref(),
+                        *stage,
+                        &flight.progress,
+                    )
+                    .await;
+                }
+            }
+            if let Some(tx) = setup_done {
+                state
+                    .setup_hooks
+                    .remove_if(&email, |_, rx| rx.same_channel(&tx.subscribe()));
+                tx.send_replace(true);
+            }
+        });
+    } else {
+        println!("[provision] joining in-flight provisioning for {}", email);
+        s.emit(events::outgoing::TERMINAL_INFO, &TerminalStatusPayload {
+            terminal_id: terminal_id.clone(),
+            message: "Workspace is already being prepared, waiting for it".to_string(),
+        })
+        .ok();
+    }
+
+    let result = match rx.wait_for(|r| r.is_some()).await {
+        Ok(result) => result.clone().flatten(),
+        Err(_) => None,
+    };
+    result
+}
+
+/// Waits until the workspace's onCreate / onStart hooks have finished, if
+/// any are running, so onAttach never races the setup it depends on.
+pub async fn wait_for_setup_hooks(state: &AppState, email: &str) {
+    let Some(mut rx) = state.setup_hooks.get(email).map(|r| r.clone()) else {
+        return;
+    };
+    rx.wait_for(|done| *done).await.ok();
+}
+
+/// Serialises provisioning across ws_ide instances with a Postgres advisory lock
+/// held for the lifetime of a transaction.
+async fn provision_locked(
+    progress: &ProgressSubscribers,
+    state: AppState,
+    email: &str,
+) -> Option<(ProvisionedWorkspace, ContainerOrigin)> {
+    let txn = match state.db.begin().await {
+        Ok(txn) => Some(txn),
+        Err(e) => {
+            eprintln!("[provision] could not open lock transaction for {}: {}", email, e);
+            None
+        }
+    };
+
+    if let Some(ref txn) = txn {
+        let lock = Statement::from_sql_and_values(
+            DatabaseBackend::Postgres,
+            "SELECT pg_advisory_xact_lock(hashtext($1))",
+            [format!("workspace:{}", email).into()],
+        );
+        if let Err(e) = txn.execute(lock).await {
+            eprintln!("[provision] advisory lock failed for {}: {}", email, e);
+        }
+    }
+
+    let result = find_or_create_container(progress, state, email).await;
+
+    if let Some(txn) = txn {
+        if let Err(e) = txn.commit().await {
+            eprintln!("[provision] failed to release advisory lock for {}: {}", email, e);
+        }
+    }
+
+    result
+}
+
+async fn find_or_create_container(
+    progress: &ProgressSubscribers,
+    state: AppState,
+    email: &str,
+) -> Option<(ProvisionedWorkspace, ContainerOrigin)> {
+    let user = match users::Entity::find()
+        .filter(users::Column::Email.eq(email))
+        .one(&*state.db)
+        .await
+    {
+        Ok(Some(u)) => u,
+        Ok(None) => {
+            eprintln!("[terminal] No user found for email={}", email);
+            progress.emit_status(
+                events::outgoing::TERMINAL_ERROR,
+                format!("No user found with email: {}", email),
+            );
+            return None;
+        }
+        Err(e) => {
+            eprintln!("[terminal] DB error: {}", e);
+            progress.emit_status(events::outgoing::TERMINAL_ERROR, format!("Database error: {}", e));
+            return None;
+        }
+    };
+
+    let container_row = match workspace_containers::Entity::find()
+        .filter(workspace_containers::Column::UserId.eq(user.id))
+        .filter(workspace_containers::Column::DeletedAt.is_null())
+        .order_by(workspace_containers::Column::CreatedAt, Order::Desc)
+        .one(&*state.db)
+        .await
+    {
+        Ok(row) => row,
+        Err(e) => {
+            eprintln!("[terminal] DB error querying workspace_containers: {}", e);
+            progress.emit_status(events::outgoing::TERMINAL_ERROR, format!("Database error: {}", e));
+            return None;
+        }
+    };
+
+    match container_row {
+        Some(row) => {
+            let docker = match Docker::connect_with_socket_defaults() {
+                Ok(client) => client,
+                Err(e) => {
+                    progress.emit_status(
+                        events::outgoing::TERMINAL_ERROR,
+                        format!("Failed to connect to Docker: {}", e),
+                    );
+                    return None;
+                }
+            };
+
+            let running = match docker
+                .inspect_container(&row.container_id, None::<InspectContainerOptions>)
+                .await
+            {
+                Ok(info) => info.state.and_then(|s| s.running).unwrap_or(false),
+                Err(_) => return create_fresh(progress, state, email).await,
+            };
+            let workspace = ProvisionedWorkspace::new(row.container_id.clone(), row.workspace_dir);
+            if running {
+                return Some((workspace, ContainerOrigin::AlreadyRunning));
+            }
+
+            match docker
+                .start_container(&row.container_id, None::<StartContainerOptions<String>>)
+                .await
+            {
+                Ok(_) => Some((workspace, ContainerOrigin::Started)),
+                Err(_) => create_fresh(progress, state, email).await,
+            }
+        }
+        None => create_fresh(progress, state, email).await,
+    }
+}
+
+async fn create_fresh(
+    progress: &ProgressSubscribers,
+    state: AppState,
+    email: &str,
+) -> Option<(ProvisionedWorkspace, ContainerOrigin)> {
+    create_container(progress, state, email.to_string())
+        .await
+        .map(|(cid, origin)| (ProvisionedWorkspace::new(cid, None), origin))
+}
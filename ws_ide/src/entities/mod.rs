@@ -5,6 +5,9 @@ pub mod prelude;
 pub mod profiles;
 pub mod providers;
 pub mod refresh_tokens;
+pub mod terminal_preferences;
 pub mod users;
 pub mod workspace_containers;
+pub mod workspace_hooks;
 pub mod workspace_session;
+pub mod workspace_tasks;
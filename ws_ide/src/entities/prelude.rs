@@ -1,8 +1,13 @@
 //! `SeaORM` Entity, @generated by sea-orm-codegen 2.0
 
+#![allow(unused_imports)]
+
 pub use super::profiles::Entity as Profiles;
 pub use super::providers::Entity as Providers;
 pub use super::refresh_tokens::Entity as RefreshTokens;
+pub use super::terminal_preferences::Entity as TerminalPreferences;
 pub use super::users::Entity as Users;
 pub use super::workspace_containers::Entity as WorkspaceContainers;
+pub use super::workspace_hooks::Entity as WorkspaceHooks;
 pub use super::workspace_session::Entity as WorkspaceSession;
+pub use super::workspace_tasks::Entity as WorkspaceTasks;
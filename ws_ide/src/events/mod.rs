@@ -6,7 +6,42 @@ pub mod incoming {
     pub const REPO_TREE: &str = "repo_tree";
     pub const GET_FILES_DATA: &str = "get_files_data";
     pub const SAVE_DATA: &str = "save_data";
+    pub const PATCH_FILE: &str = "patch_file";
+    pub const CREATE_FILE: &str = "create_file";
+    pub const CREATE_DIRECTORY: &str = "create_directory";
+    pub const DELETE_PATH: &str = "delete_path";
+    pub const RENAME_PATH: &str = "rename_path";
+    pub const COPY_PATH: &str = "copy_path";
+    pub const START_UPLOAD: &str = "start_upload";
+    pub const UPLOAD_CHUNK: &str = "upload_chunk";
+    pub const CANCEL_UPLOAD: &str = "cancel_upload";
+    pub const DOWNLOAD_FILE: &str = "download_file";
+    pub const DOWNLOAD_ACK: &str = "download_ack";
+    pub const CANCEL_DOWNLOAD: &str = "cancel_download";
+    pub const WATCH_DIRECTORY: &str = "watch_directory";
+    pub const UNWATCH_DIRECTORY: &str = "unwatch_directory";
     pub const CLOSE_TERMINAL: &str = "close_terminal";
+    pub const TERMINAL_SIGNAL: &str = "terminal_signal";
+    pub const ATTACH_TERMINAL: &str = "attach_terminal";
+    pub const JOIN_TERMINAL: &str = "join_terminal";
+    pub const SHARE_TERMINAL: &str = "share_terminal";
+    pub const REVOKE_TERMINAL_ACCESS: &str = "revoke_terminal_access";
+    pub const TERMINAL_ACK: &str = "terminal_ack";
+    pub const GET_TERMINAL_STATS: &str = "get_terminal_stats";
+    pub const EXEC_COMMAND: &str = "exec_command";
+    pub const LIST_TASKS: &str = "list_tasks";
+    pub const RUN_TASK: &str = "run_task";
+    pub const START_JOB: &str = "start_job";
+    pub const LIST_JOBS: &str = "list_jobs";
+    pub const TAIL_JOB: &str = "tail_job";
+    pub const STOP_JOB: &str = "stop_job";
+    pub const RESTART_JOB: &str = "restart_job";
+    pub const REMOVE_JOB: &str = "remove_job";
+    pub const START_RECORDING: &str = "start_recording";
+    pub const STOP_RECORDING: &str = "stop_recording";
+    pub const GET_TERMINAL_DEFAULTS: &str = "get_terminal_defaults";
+    pub const SET_TERMINAL_DEFAULTS: &str = "set_terminal_defaults";
+    pub const LIST_SHELLS: &str = "list_shells";
     pub const CODE_COMPLETION: &str = "code_completion";
 }
 
@@ -17,11 +52,52 @@ pub mod outgoing {
     pub const TERMINAL_INFO: &str = "terminal_info";
     pub const TERMINAL_SUCCESS: &str = "terminal_success";
     pub const TERMINAL_CLOSED: &str = "terminal_closed";
+    pub const TERMINAL_ATTACHED: &str = "terminal_attached";
+    pub const TERMINAL_JOINED: &str = "terminal_joined";
+    pub const TERMINAL_ACCESS: &str = "terminal_access";
+    pub const TERMINAL_ACCESS_REVOKED: &str = "terminal_access_revoked";
+    pub const TERMINAL_STATS: &str = "terminal_stats";
+    pub const EXEC_OUTPUT: &str = "exec_output";
+    pub const EXEC_RESULT: &str = "exec_result";
+    pub const EXEC_ERROR: &str = "exec_error";
+    pub const TASKS: &str = "tasks";
+    pub const TASK_STARTED: &str = "task_started";
+    pub const TASK_DIAGNOSTICS: &str = "task_diagnostics";
+    pub const TASK_FINISHED: &str = "task_finished";
+    pub const TASK_ERROR: &str = "task_error";
+    pub const JOBS: &str = "jobs";
+    pub const JOB_STATUS: &str = "job_status";
+    pub const JOB_OUTPUT: &str = "job_output";
+    pub const JOB_ERROR: &str = "job_error";
+    pub const RECORDING_STARTED: &str = "recording_started";
+    pub const RECORDING_STOPPED: &str = "recording_stopped";
+    pub const TERMINAL_DEFAULTS: &str = "terminal_defaults";
+    pub const SHELLS: &str = "shells";
+    pub const SETTINGS_ERROR: &str = "settings_error";
     pub const TERMINAL_DATA: &str = "terminal_data";
+    pub const TERMINAL_BINARY: &str = "terminal_binary";
     pub const TERMINAL_CWD: &str = "terminal_cwd";
+    pub const TERMINAL_LINKS: &str = "terminal_links";
+    pub const COMMAND_STARTED: &str = "command_started";
+    pub const COMMAND_FINISHED: &str = "command_finished";
+    pub const TERMINAL_PROCESS: &str = "terminal_process";
+    pub const TERMINAL_BUSY: &str = "terminal_busy";
+    pub const TERMINAL_SIGNAL_RESULT: &str = "terminal_signal_result";
+    pub const CLONE_PROGRESS: &str = "clone_progress";
+    pub const WORKSPACE_READY: &str = "workspace_ready";
+    pub const HOOK_OUTPUT: &str = "hook_output";
+    pub const HOOK_STATUS: &str = "hook_status";
     pub const FILE_ERROR: &str = "file_error";
     pub const FILES_DATA: &str = "files_data";
     pub const FILE_SAVED: &str = "file_saved";
+    pub const FILE_CONFLICT: &str = "file_conflict";
+    pub const PATCH_REJECTED: &str = "patch_rejected";
+    pub const FILE_OP_RESULT: &str = "file_op_result";
+    pub const UPLOAD_STATUS: &str = "upload_status";
+    pub const DOWNLOAD_STARTED: &str = "download_started";
+    pub const DOWNLOAD_CHUNK: &str = "download_chunk";
+    pub const DOWNLOAD_COMPLETE: &str = "download_complete";
+    pub const FS_CHANGED: &str = "fs_changed";
     pub const REPO_STRUCTURE: &str = "repo_structure";
     pub const COMPLETION_RESULT: &str = "completion_result";
     pub const COMPLETION_ERROR: &str = "completion_error";
@@ -0,0 +1,97 @@
+use bytes::Bytes;
+
+use crate::types::FileDataPayload;
+
+/// Bytes looked at for a NUL when deciding whether a file is text, as git does.
+const SNIFF_BYTES: usize = 8000;
+
+/// Whether `data` should be treated as binary: it has a NUL early on or
+/// isn't valid UTF-8, so decoding it as text would lose bytes.
+pub fn is_binary(data: &[u8]) -> bool {
+    data[..data.len().min(SNIFF_BYTES)].contains(&0) || std::str::from_utf8(data).is_err()
+}
+
+/// Known file signatures, checked before the extension.
+const MAGIC: &[(&[u8], &str)] = &[
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"GIF87a", "image/gif"),
+    (b"GIF89a", "image/gif"),
+    (b"BM", "image/bmp"),
+    (b"\x00\x00\x01\x00", "image/x-icon"),
+    (b"%PDF-", "application/pdf"),
+    (b"PK\x03\x04", "application/zip"),
+    (b"\x1f\x8b", "application/gzip"),
+    (b"\0asm", "application/wasm"),
+    (b"\x7fELF", "application/x-executable"),
+];
+
+fn by_extension(path: &str) -> Option<&'static str> {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    let (_, ext) = name.rsplit_once('.')?;
+    Some(match ext.to_ascii_lowercase().as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        "ico" => "image/x-icon",
+        "bmp" => "image/bmp",
+        "pdf" => "application/pdf",
+        "zip" | "jar" => "application/zip",
+        "gz" | "tgz" => "application/gzip",
+        "wasm" => "application/wasm",
+        "json" => "application/json",
+        "js" | "mjs" | "cjs" | "jsx" => "text/javascript",
+        "ts" | "tsx" => "text/typescript",
+        "html" | "htm" => "text/html",
+        "css" => "text/css",
+        "md" => "text/markdown",
+        "xml" => "application/xml",
+        "mp3" => "audio/mpeg",
+        "wav" => "audio/wav",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        _ => return None,
+    })
+}
+
+/// MIME type of the file at `path`, from the leading bytes of a binary file
+/// or else the extension. Unknown files are `text/plain` or
+/// `application/octet-stream` depending on `binary`.
+pub fn mime_type(path: &str, data: &[u8], binary: bool) -> &'static str {
+    if binary {
+        if data.len() >= 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+            return "image/webp";
+        }
+        if let Some((_, mime)) = MAGIC.iter().find(|(magic, _)| data.starts_with(magic)) {
+            return mime;
+        }
+    }
+    by_extension(path).unwrap_or(if binary { "application/octet-stream" } else { "text/plain" })
+}
+
+/// `files_data` for `data` read from `path`: text as `content`, anything
+/// else as raw bytes.
+pub fn file_data(path: String, data: Vec<u8>, version: String) -> FileDataPayload {
+    let binary = is_binary(&data);
+    let mime_type = mime_type(&path, &data, binary).to_string();
+    let size = data.len() as u64;
+    let (content, data) = if binary {
+        (None, Some(Bytes::from(data)))
+    } else {
+        (String::from_utf8(data).ok(), None)
+    };
+    FileDataPayload {
+        path,
+        content,
+        data,
+        mime_type,
+        size,
+        is_binary: binary,
+        version,
+    }
+}
@@ -0,0 +1,411 @@
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashSet};
+
+use crate::docker_vm::exec::{exec_output, exec_raw};
+
+/// Per-workspace settings file, relative to the workspace root.
+pub const SETTINGS_FILE: &str = ".aks/settings.json";
+
+/// Excluded unless the settings file turns them off with `false`, as VS
+/// Code's `files.exclude` does.
+const DEFAULT_EXCLUDES: &[&str] = &["**/.svn", "**/.hg", "**/CVS", "**/.DS_Store", "**/Thumbs.db"];
+
+#[derive(Debug, Default, Deserialize)]
+struct SettingsFile {
+    /// Glob → whether it's excluded. Globs are relative to the workspace root.
+    #[serde(default, rename = "files.exclude")]
+    exclude: BTreeMap<String, bool>,
+    /// Also exclude whatever git ignores in the workspace repo.
+    #[serde(default, rename = "files.useGitignore")]
+    use_gitignore: bool,
+}
+
+/// Matches `path` against a glob: `*` and `?` stay within one path segment,
+/// `**` spans any number of them, `[a-z]` / `[!a-z]` is a character class
+/// and `{a,b}` a list of alternatives.
+pub fn glob_match(pattern: &str, path: &str) -> bool {
+    if let Some(start) = pattern.find('{') {
+        if let Some(len) = pattern[start..].find('}') {
+            let end = start + len;
+            return pattern[start + 1..end].split(',').any(|alt| {
+                glob_match(&format!("{}{}{}", &pattern[..start], alt, &pattern[end + 1..]), path)
+            });
+        }
+    }
+    let pattern: Vec<char> = pattern.chars().collect();
+    let path: Vec<char> = path.chars().collect();
+    match_from(&pattern, &path)
+}
+
+fn match_from(p: &[char], s: &[char]) -> bool {
+    match p.first() {
+        None => s.is_empty(),
+        Some('*') if p.get(1) == Some(&'*') => {
+            let rest = &p[2..];
+            match rest.first() {
+                None => true,
+                // `**/` stands for zero or more whole segments.
+                Some('/') => {
+                    let rest = &rest[1..];
+                    match_from(rest, s)
+                        || s.iter().enumerate().any(|(i, c)| *c == '/' && match_from(rest, &s[i + 1..]))
+                }
+                Some(_) => (0..=s.len()).any(|i| match_from(rest, &s[i..])),
+            }
+        }
+        Some('*') => (0..=s.len())
+            .take_while(|&i| i == 0 || s[i - 1] != '/')
+            .any(|i| match_from(&p[1..], &s[i..])),
+        Some('?') => s.first().is_some_and(|c| *c != '/') && match_from(&p[1..], &s[1..]),
+        Some('[') => match p.iter().skip(2).position(|c| *c == ']') {
+            Some(len) => {
+                let Some(&c) = s.first().filter(|c| **c != '/') else {
+                    return false;
+                };
+                let class = &p[1..len + 2];
+                let (negated, class) = match class.first() {
+                    Some('!' | '^') => (true, &class[1..]),
+                    _ => (false, class),
+                };
+                let mut found = false;
+                let mut i = 0;
+                while i < class.len() {
+                    if i + 2 < class.len() && class[i + 1] == '-' {
+                        found |= (class[i]..=class[i + 2]).contains(&c);
+                        i += 3;
+                    } else {
+                        found |= class[i] == c;
+                        i += 1;
+                    }
+                }
+                found != negated && match_from(&p[len + 3..], &s[1..])
+            }
+            None => s.first() == Some(&'[') && match_from(&p[1..], &s[1..]),
+        },
+        Some(c) => s.first() == Some(c) && match_from(&p[1..], &s[1..]),
+    }
+}
+
+/// `pattern` with its first `{a,b}` list expanded, as `glob_match` reads it.
+fn expand_braces(pattern: &str) -> Vec<String> {
+    if let Some(start) = pattern.find('{') {
+        if let Some(len) = pattern[start..].find('}') {
+            let end = start + len;
+            return pattern[start + 1..end]
+                .split(',')
+                .flat_map(|alt| expand_braces(&format!("{}{}{}", &pattern[..start], alt, &pattern[end + 1..])))
+                .collect();
+        }
+    }
+    vec![pattern.to_string()]
+}
+
+fn push_literal(out: &mut String, c: char) {
+    if ".^$+(){}|\\[]*?".contains(c) {
+        out.push('\\');
+    }
+    out.push(c);
+}
+
+/// The POSIX extended regex matching exactly what `glob_match` does for a
+/// brace-free glob. `None` for character classes that don't carry over.
+fn glob_regex(pattern: &str) -> Option<String> {
+    let p: Vec<char> = pattern.chars().collect();
+    let mut out = String::new();
+    let mut i = 0;
+    while i < p.len() {
+        match p[i] {
+            '*' if p.get(i + 1) == Some(&'*') => {
+                if p.get(i + 2) == Some(&'/') {
+                    out.push_str("(.*/)?");
+                    i += 3;
+                } else {
+                    out.push_str(".*");
+                    i += 2;
+                }
+            }
+            '*' => {
+                out.push_str("[^/]*");
+                i += 1;
+            }
+            '?' => {
+                out.push_str("[^/]");
+                i += 1;
+            }
+            '[' => match p.iter().skip(i + 2).position(|c| *c == ']') {
+                Some(len) => {
+                    let class = &p[i + 1..i + len + 2];
+                    let (negated, class) = match class.first() {
+                        Some('!' | '^') => (true, &class[1..]),
+                        _ => (false, class),
+                    };
+                    if class.is_empty()
+                        || class.iter().any(|c| matches!(c, '[' | ']' | '\\' | '/' | '^'))
+                        || class.last() == Some(&'-')
+                        || class.iter().filter(|c| **c == '-').count() > 1
+                        || class.windows(3).any(|w| w[1] == '-' && (w[0]..=w[2]).contains(&'/'))
+                    {
+                        return None;
+                    }
+                    out.push('[');
+                    if negated {
+                        out.push_str("^/");
+                    }
+                    out.extend(class);
+                    out.push(']');
+                    i += len + 3;
+                }
+                None => {
+                    push_literal(&mut out, '[');
+                    i += 1;
+                }
+            },
+            c => {
+                push_literal(&mut out, c);
+                i += 1;
+            }
+        }
+    }
+    Some(out)
+}
+
+/// A workspace's exclude rules: `files.exclude` globs, plus `.gitignore`
+/// when `files.useGitignore` is on. The file tree and the watcher both go
+/// through these; so should anything else that walks the workspace, like
+/// search.
+#[derive(Debug, Clone)]
+pub struct ExcludeRules {
+    root: Option<String>,
+    globs: Vec<String>,
+    use_gitignore: bool,
+}
+
+impl ExcludeRules {
+    fn new(root: Option<&str>, settings: SettingsFile) -> Self {
+        let mut exclude: BTreeMap<String, bool> = DEFAULT_EXCLUDES.iter().map(|g| (g.to_string(), true)).collect();
+        exclude.extend(settings.exclude);
+        Self {
+            root: root.map(|r| r.trim_end_matches('/').to_string()),
+            globs: exclude.into_iter().filter(|(_, on)| *on).map(|(glob, _)| glob).collect(),
+            use_gitignore: settings.use_gitignore,
+        }
+    }
+
+    /// Loads the rules from `.aks/settings.json`; the defaults when the
+    /// workspace has none.
+    pub async fn load(container_id: &str, root: Option<&str>) -> Result<Self, String> {
+        let Some(root) = root else {
+            return Ok(Self::new(None, SettingsFile::default()));
+        };
+        let path = format!("{}/{}", root.trim_end_matches('/'), SETTINGS_FILE);
+        let (code, content) = exec_output(container_id, &["cat", &path], None)
+            .await
+            .map_err(|e| format!("Failed to read {}: {}", SETTINGS_FILE, e))?;
+        let settings = match code {
+            0 => serde_json::from_str(&content).map_err(|e| format!("Invalid {}: {}", SETTINGS_FILE, e))?,
+            _ => SettingsFile::default(),
+        };
+        Ok(Self::new(Some(root), settings))
+    }
+
+    /// The defaults alone, for when the settings file can't be used.
+    pub fn defaults(root: Option<&str>) -> Self {
+        Self::new(root, SettingsFile::default())
+    }
+
+    /// Whether a change to `path` can change the rules themselves.
+    pub fn is_rules_file(&self, path: &str) -> bool {
+        path.ends_with("/.gitignore")
+            || self
+                .root
+                .as_ref()
+                .is_some_and(|root| path == format!("{}/{}", root, SETTINGS_FILE))
+    }
+
+    /// `path` relative to the workspace root, or without its leading `/`
+    /// when it's outside the workspace.
+    fn relative<'a>(&self, path: &'a str) -> &'a str {
+        self.root
+            .as_deref()
+            .and_then(|root| path.strip_prefix(root))
+            .and_then(|rest| rest.strip_prefix('/'))
+            .unwrap_or_else(|| path.trim_start_matches('/'))
+    }
+
+    /// Whether a glob excludes `path` or one of the directories above it.
+    pub fn glob_excluded(&self, path: &str) -> bool {
+        let rel = self.relative(path);
+        !rel.is_empty()
+            && rel
+                .match_indices('/')
+                .map(|(i, _)| &rel[..i])
+                .chain([rel])
+                .any(|prefix| self.globs.iter().any(|glob| glob_match(glob, prefix)))
+    }
+
+    /// One regex for `find -regex` matching the paths under the workspace
+    /// root that the globs exclude, so a listing can prune them instead of
+    /// walking them. Globs it can't express are left to `glob_excluded`.
+    pub fn prune_regex(&self) -> Option<String> {
+        let alternatives: Vec<String> = self
+            .globs
+            .iter()
+            .flat_map(|glob| expand_braces(glob))
+            .filter_map(|glob| glob_regex(&glob))
+            .collect();
+        if alternatives.is_empty() {
+            return None;
+        }
+        let mut regex = String::new();
+        for c in self.root.as_deref().unwrap_or("").chars() {
+            push_literal(&mut regex, c);
+        }
+        Some(format!("{}/({})", regex, alternatives.join("|")))
+    }
+
+    /// The `paths` that are excluded. A failing `git check-ignore` is
+    /// logged and only the globs apply.
+    pub async fn excluded(&self, container_id: &str, paths: &[&str]) -> HashSet<String> {
+        let mut excluded: HashSet<String> = paths
+            .iter()
+            .filter(|path| self.glob_excluded(path))
+            .map(|path| path.to_string())
+            .collect();
+        if let (true, Some(root)) = (self.use_gitignore, &self.root) {
+            let prefix = format!("{}/", root);
+            let candidates: Vec<&str> = paths
+                .iter()
+                .copied()
+                .filter(|path| path.starts_with(&prefix) && !excluded.contains(*path))
+                .collect();
+            match git_ignored(container_id, root, &candidates).await {
+                Ok(ignored) => excluded.extend(ignored),
+                Err(e) => eprintln!("[files] .gitignore check in {} failed: {}", root, e),
+            }
+        }
+        excluded
+    }
+}
+
+/// The `paths` git ignores in the repo at `root`, inside ignored
+/// directories included. Tracked files are never ignored.
+async fn git_ignored(container_id: &str, root: &str, paths: &[&str]) -> Result<Vec<String>, String> {
+    if paths.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut input = Vec::new();
+    for path in paths {
+        input.extend_from_slice(path.as_bytes());
+        input.push(0);
+    }
+    let (code, stdout, stderr) = exec_raw(container_id, &["git", "-C", root, "check-ignore", "-z", "--stdin"], &input)
+        .await
+        .map_err(|e| format!("Failed to execute docker command: {}", e))?;
+    match code {
+        // 1: nothing is ignored.
+        0 | 1 => Ok(stdout
+            .split(|b| *b == 0)
+            .filter(|path| !path.is_empty())
+            .map(|path| String::from_utf8_lossy(path).into_owned())
+            .collect()),
+        _ => Err(stderr.trim().to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(root: Option<&str>, globs: &[&str]) -> ExcludeRules {
+        ExcludeRules::new(root, SettingsFile {
+            exclude: globs.iter().map(|g| (g.to_string(), true)).collect(),
+            use_gitignore: false,
+        })
+    }
+
+    #[test]
+    fn double_star_slash_spans_whole_segments() {
+        assert!(glob_match("**/node_modules", "node_modules"));
+        assert!(glob_match("**/node_modules", "a/b/node_modules"));
+        assert!(!glob_match("**/node_modules", "a/xnode_modules"));
+        assert!(glob_match("src/**/*.rs", "src/main.rs"));
+        assert!(glob_match("src/**/*.rs", "src/a/b/lib.rs"));
+        assert!(glob_match("target/**", "target/debug/build"));
+    }
+
+    #[test]
+    fn single_star_stays_in_its_segment() {
+        assert!(glob_match("*.log", "out.log"));
+        assert!(!glob_match("*.log", "logs/out.log"));
+        assert!(glob_match("build?", "build2"));
+        assert!(!glob_match("a?b", "a/b"));
+    }
+
+    #[test]
+    fn braces_are_alternatives() {
+        assert!(glob_match("**/*.{js,ts}", "src/index.ts"));
+        assert!(glob_match("**/*.{js,ts}", "index.js"));
+        assert!(!glob_match("**/*.{js,ts}", "index.rs"));
+        assert!(glob_match("{dist,out}/**", "out/main.js"));
+    }
+
+    #[test]
+    fn character_classes() {
+        assert!(glob_match("file[0-9].txt", "file7.txt"));
+        assert!(!glob_match("file[0-9].txt", "fileA.txt"));
+        assert!(glob_match("[!.]*", "visible"));
+        assert!(!glob_match("[!.]*", ".hidden"));
+        assert!(!glob_match("a[!x]b", "a/b"));
+        assert!(glob_match("a[b", "a[b"));
+    }
+
+    #[test]
+    fn excluded_directories_cover_their_contents() {
+        let rules = rules(Some("/workspace/"), &["**/node_modules", "dist"]);
+        assert!(rules.glob_excluded("/workspace/node_modules"));
+        assert!(rules.glob_excluded("/workspace/app/node_modules/react/index.js"));
+        assert!(rules.glob_excluded("/workspace/dist/main.js"));
+        assert!(!rules.glob_excluded("/workspace/src/dist.rs"));
+        assert!(!rules.glob_excluded("/workspace/src/main.rs"));
+        assert!(!rules.glob_excluded("/workspace"));
+        assert!(rules.glob_excluded("/workspace/.DS_Store"));
+    }
+
+    #[test]
+    fn globs_apply_from_the_container_root_without_a_workspace() {
+        let rules = rules(None, &["tmp/*.log"]);
+        assert!(rules.glob_excluded("/tmp/a.log"));
+        assert!(!rules.glob_excluded("/var/tmp/a.log"));
+    }
+
+    #[test]
+    fn prune_regex_agrees_with_glob_match() {
+        let globs = ["**/node_modules", "{dist,out}/**", "*.{log,tmp}", "build[0-9]", "[!.]x?", "a.b+c", "**"];
+        let paths = [
+            "node_modules", "a/node_modules", "a/node_modulesx", "dist", "dist/x", "out/a/b", "outx/a", "a.log",
+            "a/b.log", "c.tmp", "build7", "buildx", "bx1", ".x1", "b/x1", "a.b+c", "axb+c",
+        ];
+        for glob in globs {
+            let rules = rules(Some("/w.s"), &[glob]);
+            let regex = regex::Regex::new(&format!("^{}$", rules.prune_regex().unwrap())).unwrap();
+            for path in paths {
+                let defaults = DEFAULT_EXCLUDES.iter().any(|g| glob_match(g, path));
+                assert_eq!(
+                    regex.is_match(&format!("/w.s/{}", path)),
+                    glob_match(glob, path) || defaults,
+                    "{} against {}",
+                    glob,
+                    path
+                );
+            }
+            assert!(!regex.is_match(&format!("/wxs/{}", paths[0])));
+        }
+    }
+
+    #[test]
+    fn classes_that_do_not_translate_are_skipped() {
+        assert_eq!(glob_regex("[a-/]"), None);
+        assert_eq!(glob_regex("[a-]"), None);
+        assert_eq!(glob_regex("[!x]").as_deref(), Some("[^/x]"));
+    }
+}
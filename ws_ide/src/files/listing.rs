@@ -0,0 +1,148 @@
+use crate::{
+    docker_vm::exec::exec_raw,
+    types::{DirEntry, EntryKind},
+};
+
+pub const DEFAULT_DEPTH: u32 = 1;
+pub const MAX_DEPTH: u32 = 8;
+pub const DEFAULT_PAGE: usize = 1000;
+pub const MAX_PAGE: usize = 5000;
+
+/// Entries read from one listing at most; a deeper or bigger tree is cut
+/// off here and reported as truncated.
+const MAX_ENTRIES: usize = 100_000;
+
+/// Virtual filesystems at the container root, never listed or descended.
+const PRUNED: &[&str] = &["/proc", "/sys", "/dev", "/run"];
+
+/// Fields `find -printf` writes per entry, each ended by a NUL: type, type
+/// of the link target, size, mtime, mode, link target and relative path.
+/// NULs can't occur in names, so any name comes through intact.
+const FIELDS: usize = 7;
+
+const FORMAT: &str = r"%y\0%Y\0%s\0%T@\0%m\0%l\0%P\0";
+
+/// Runs `find` with the expression built by `list_directory` as its
+/// arguments after the first three.
+const LIST_SCRIPT: &str = r#"if [ ! -d "$1" ]; then echo "$1 is not a directory" >&2; exit 1; fi
+dir=$1; depth=$2; max=$3; shift 3
+find -H "$dir" -mindepth 1 -maxdepth "$depth" -regextype posix-extended "$@" 2>/dev/null | head -z -n "$max""#;
+
+fn kind(t: &str) -> EntryKind {
+    match t {
+        "f" => EntryKind::File,
+        "d" => EntryKind::Dir,
+        "l" => EntryKind::Symlink,
+        _ => EntryKind::Other,
+    }
+}
+
+/// `rwxr-xr-x` for `0o755`, with setuid, setgid and sticky bits as `ls`
+/// shows them.
+fn permissions(mode: u32) -> String {
+    let mut out = String::with_capacity(9);
+    for (shift, special, set, unset) in [(6, 0o4000, 's', 'S'), (3, 0o2000, 's', 'S'), (0, 0o1000, 't', 'T')] {
+        let bits = (mode >> shift) & 7;
+        out.push(if bits & 4 != 0 { 'r' } else { '-' });
+        out.push(if bits & 2 != 0 { 'w' } else { '-' });
+        out.push(match (bits & 1 != 0, mode & special != 0) {
+            (true, true) => set,
+            (false, true) => unset,
+            (true, false) => 'x',
+            (false, false) => '-',
+        });
+    }
+    out
+}
+
+fn parse_entry(dir: &str, fields: &[&[u8]]) -> Option<DirEntry> {
+    let text = |i: usize| String::from_utf8_lossy(fields[i]).into_owned();
+    let rel = text(6);
+    if rel.is_empty() {
+        return None;
+    }
+    let kind = kind(&text(0));
+    let mode = u32::from_str_radix(&text(4), 8).ok()?;
+    Some(DirEntry {
+        name: rel.rsplit('/').next().unwrap_or(&rel).to_string(),
+        path: format!("{}/{}", dir.trim_end_matches('/'), rel),
+        is_dir: kind == EntryKind::Dir || (kind == EntryKind::Symlink && text(1) == "d"),
+        size: text(2).parse().unwrap_or(0),
+        mtime: text(3).split('.').next().and_then(|s| s.parse().ok()).unwrap_or(0),
+        mode,
+        permissions: permissions(mode),
+        symlink_target: (kind == EntryKind::Symlink).then(|| text(5)),
+        depth: rel.split('/').count() as u32,
+        excluded: false,
+        kind,
+    })
+}
+
+/// Parses `find -printf` records, skipping malformed ones.
+pub fn parse_listing(dir: &str, output: &[u8]) -> Vec<DirEntry> {
+    let fields: Vec<&[u8]> = output.split(|b| *b == 0).collect();
+    fields
+        .chunks_exact(FIELDS)
+        .filter_map(|fields| parse_entry(dir, fields))
+        .collect()
+}
+
+/// Orders a tree listing so every entry follows its parent, with
+/// directories before files among siblings and names in byte order.
+pub fn sort_listing(entries: &mut [DirEntry]) {
+    entries.sort_by_cached_key(|entry| {
+        let parts: Vec<&str> = entry.path.rsplitn(entry.depth as usize + 1, '/').collect();
+        parts[..entry.depth as usize]
+            .iter()
+            .rev()
+            .enumerate()
+            .map(|(i, part)| (i + 1 == entry.depth as usize && !entry.is_dir, part.to_string()))
+            .collect::<Vec<_>>()
+    });
+}
+
+/// Lists `dir` down to `depth` levels in the container, sorted as
+/// `sort_listing` does. Paths matching the `prune` regex (as from
+/// `ExcludeRules::prune_regex`) are never descended into, and only listed
+/// themselves with `list_pruned`. The flag is set when the tree had more
+/// than `MAX_ENTRIES` entries and the rest were dropped.
+pub async fn list_directory(
+    container_id: &str,
+    dir: &str,
+    depth: u32,
+    prune: Option<&str>,
+    list_pruned: bool,
+) -> Result<(Vec<DirEntry>, bool), String> {
+    let depth = depth.clamp(1, MAX_DEPTH).to_string();
+    let max = ((MAX_ENTRIES + 1) * FIELDS).to_string();
+    let mut argv = vec!["/bin/sh", "-c", LIST_SCRIPT, "sh", dir, depth.as_str(), max.as_str(), "("];
+    for (i, path) in PRUNED.iter().enumerate() {
+        if i > 0 {
+            argv.push("-o");
+        }
+        argv.extend(["-path", path]);
+    }
+    argv.extend([")", "-prune", "-o"]);
+    if let Some(regex) = prune {
+        argv.extend(["-regex", regex, "-prune"]);
+        if list_pruned {
+            argv.extend(["-printf", FORMAT]);
+        }
+        argv.push("-o");
+    }
+    argv.extend(["-printf", FORMAT]);
+    let (code, stdout, stderr) = exec_raw(container_id, &argv, &[])
+        .await
+        .map_err(|e| format!("Failed to execute docker command: {}", e))?;
+    if code != 0 {
+        return Err(match stderr.trim() {
+            "" => format!("Listing {} exited with {}", dir, code),
+            reason => reason.to_string(),
+        });
+    }
+    let mut entries = parse_listing(dir, &stdout);
+    let truncated = entries.len() > MAX_ENTRIES;
+    entries.truncate(MAX_ENTRIES);
+    sort_listing(&mut entries);
+    Ok((entries, truncated))
+}
@@ -0,0 +1,6 @@
+pub mod content;
+pub mod exclude;
+pub mod listing;
+pub mod patch;
+pub mod transfer;
+pub mod watcher;
@@ -0,0 +1,92 @@
+use crate::types::TextEdit;
+
+/// Most edits one `patch_file` may carry; past that a full save is cheaper
+/// than replaying them. Each edit rescans the text, so with files capped at
+/// `MAX_INLINE_BYTES` this bounds a patch to a few billion char steps.
+pub const MAX_EDITS: usize = 1_000;
+
+/// Byte index of the `units`-th UTF-16 code unit in `text`. Errors when it
+/// is past the end or splits a surrogate pair.
+fn byte_index(text: &str, units: usize) -> Result<usize, String> {
+    let mut seen = 0;
+    for (i, c) in text.char_indices() {
+        if seen == units {
+            return Ok(i);
+        }
+        seen += c.len_utf16();
+        if seen > units {
+            return Err(format!("Offset {} splits a character", units));
+        }
+    }
+    if seen == units {
+        Ok(text.len())
+    } else {
+        Err(format!("Offset {} is past the end of the file ({} characters)", units, seen))
+    }
+}
+
+/// Applies `edits` to `text` one after another.
+pub fn apply_edits(text: &str, edits: &[TextEdit]) -> Result<String, String> {
+    if edits.len() > MAX_EDITS {
+        return Err(format!("{} edits; at most {} are accepted", edits.len(), MAX_EDITS));
+    }
+    let mut text = text.to_string();
+    for edit in edits {
+        let end = edit
+            .offset
+            .checked_add(edit.length)
+            .ok_or_else(|| format!("Edit at {} is out of range", edit.offset))?;
+        let start = byte_index(&text, edit.offset)?;
+        let end = byte_index(&text, end)?;
+        text.replace_range(start..end, &edit.text);
+    }
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edit(offset: usize, length: usize, text: &str) -> TextEdit {
+        TextEdit { offset, length, text: text.to_string() }
+    }
+
+    #[test]
+    fn offsets_count_utf16_units() {
+        // "é" is one unit, "😀" two.
+        let text = "é😀x";
+        assert_eq!(apply_edits(text, &[edit(3, 1, "y")]).unwrap(), "é😀y");
+        assert_eq!(apply_edits(text, &[edit(1, 2, "")]).unwrap(), "éx");
+    }
+
+    #[test]
+    fn splitting_a_surrogate_pair_is_an_error() {
+        let text = "a😀b";
+        assert!(apply_edits(text, &[edit(2, 0, "x")]).unwrap_err().contains("splits a character"));
+        assert!(apply_edits(text, &[edit(1, 1, "")]).unwrap_err().contains("splits a character"));
+    }
+
+    #[test]
+    fn past_the_end_is_an_error() {
+        assert_eq!(apply_edits("abc", &[edit(3, 0, "d")]).unwrap(), "abcd");
+        assert!(apply_edits("abc", &[edit(4, 0, "d")]).unwrap_err().contains("past the end"));
+        assert!(apply_edits("abc", &[edit(2, 2, "")]).unwrap_err().contains("past the end"));
+        assert!(apply_edits("abc", &[edit(usize::MAX, 1, "")]).unwrap_err().contains("out of range"));
+    }
+
+    #[test]
+    fn edits_apply_to_the_text_left_by_earlier_ones() {
+        // Last-first, as the editor sends them, so earlier offsets hold.
+        let edits = [edit(9, 3, "there"), edit(0, 5, "Hi")];
+        assert_eq!(apply_edits("Hello, y'all", &edits).unwrap(), "Hi, y'there");
+        // In order, the second edit sees the first one's result.
+        let edits = [edit(0, 1, "ab"), edit(2, 0, "c")];
+        assert_eq!(apply_edits("x", &edits).unwrap(), "abc");
+    }
+
+    #[test]
+    fn too_many_edits_are_refused() {
+        let edits = vec![edit(0, 0, ""); MAX_EDITS + 1];
+        assert!(apply_edits("", &edits).is_err());
+    }
+}
@@ -0,0 +1,423 @@
+use dashmap::DashMap;
+use socketioxide::{extract::SocketRef, socket::Sid};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::sync::{watch, Mutex};
+use tokio::task::AbortHandle;
+use uuid::Uuid;
+
+use crate::{
+    docker_vm::{exec::exec_reader, file_ops},
+    events,
+    files::content::mime_type,
+    types::{DownloadChunkPayload, DownloadCompletePayload, DownloadStartedPayload, UploadStatusPayload},
+};
+
+/// Largest file uploaded or downloaded, unless `MAX_TRANSFER_BYTES` says
+/// otherwise.
+const DEFAULT_MAX_FILE_BYTES: u64 = 512 * 1024 * 1024;
+
+/// Files up to this size may still be sent whole on `files_data`.
+pub const MAX_INLINE_BYTES: u64 = 5 * 1024 * 1024;
+
+/// Size of each chunk a download is sent in.
+pub const CHUNK_BYTES: usize = 256 * 1024;
+
+/// Largest chunk accepted from a client.
+const MAX_CHUNK_BYTES: usize = 4 * 1024 * 1024;
+
+/// Bytes a download may run ahead of the client's acks.
+const DOWNLOAD_WINDOW: u64 = 4 * CHUNK_BYTES as u64;
+
+/// A download whose client stops acking is dropped after this; it can be
+/// resumed from the last chunk received.
+const ACK_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// An upload nobody has written to for this long is dropped with its part
+/// file.
+const UPLOAD_IDLE: Duration = Duration::from_secs(30 * 60);
+
+/// Uploads a user may have in progress at once.
+const MAX_UPLOADS_PER_USER: usize = 8;
+
+pub fn max_file_bytes() -> u64 {
+    std::env::var("MAX_TRANSFER_BYTES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(DEFAULT_MAX_FILE_BYTES)
+}
+
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 { 0xedb8_8320 ^ (c >> 1) } else { c >> 1 };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+};
+
+/// CRC-32 (IEEE, as zlib computes it) of `data`, continuing from `crc`;
+/// start from 0.
+pub fn crc32(crc: u32, data: &[u8]) -> u32 {
+    let mut c = !crc;
+    for &byte in data {
+        c = CRC_TABLE[((c ^ byte as u32) & 0xff) as usize] ^ (c >> 8);
+    }
+    !c
+}
+
+/// Reads until `buf` is full or the reader ends. Returns the bytes read.
+pub async fn read_chunk<R: AsyncRead + Unpin>(reader: &mut R, buf: &mut [u8]) -> Result<usize, std::io::Error> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]).await? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
+
+struct UploadState {
+    /// Bytes written and acknowledged so far.
+    offset: u64,
+    /// CRC-32 of those bytes.
+    crc: u32,
+    touched: Instant,
+}
+
+/// A file being uploaded in chunks. Chunks go to a hidden part file next to
+/// the destination, which replaces it once every byte is in.
+pub struct Upload {
+    pub id: String,
+    pub email: String,
+    pub container_id: String,
+    pub path: String,
+    pub size: u64,
+    /// CRC-32 of the whole file, when the client sent one.
+    pub checksum: Option<u32>,
+    part: String,
+    /// Held while a chunk is written, so chunks land one at a time.
+    state: Mutex<UploadState>,
+}
+
+impl Upload {
+    pub fn status(&self, offset: u64, complete: bool) -> UploadStatusPayload {
+        UploadStatusPayload {
+            upload_id: self.id.clone(),
+            path: self.path.clone(),
+            size: self.size,
+            offset,
+            complete,
+        }
+    }
+
+    pub async fn offset(&self) -> u64 {
+        self.state.lock().await.offset
+    }
+
+    /// Writes `data` if it starts where the upload left off. A chunk at any
+    /// other offset, e.g. one sent again after a reconnect, is ignored.
+    /// Returns the offset the client should continue from.
+    pub async fn write_chunk(&self, offset: u64, data: &[u8], checksum: u32) -> Result<u64, String> {
+        if data.len() > MAX_CHUNK_BYTES {
+            return Err(format!("Chunks may be at most {} bytes", MAX_CHUNK_BYTES));
+        }
+        if crc32(0, data) != checksum {
+            return Err(format!("Checksum mismatch for the chunk at offset {}", offset));
+        }
+        let mut state = self.state.lock().await;
+        state.touched = Instant::now();
+        if offset != state.offset {
+            return Ok(state.offset);
+        }
+        if offset + data.len() as u64 > self.size {
+            return Err(format!("Chunk runs past the declared size of {} bytes", self.size));
+        }
+        file_ops::append_chunk(&self.container_id, &self.part, offset, data).await?;
+        state.offset += data.len() as u64;
+        state.crc = crc32(state.crc, data);
+        Ok(state.offset)
+    }
+}
+
+/// A download being streamed to a socket.
+struct Download {
+    email: String,
+    sid: Sid,
+    /// Tells apart a restarted download from the run it replaced.
+    run: u64,
+    /// Bytes the client has confirmed; `None` until the download is
+    /// registered, so it can't finish before it is.
+    acked: watch::Sender<Option<u64>>,
+    task: AbortHandle,
+}
+
+/// Chunked uploads and downloads in progress, by id. Uploads outlive the
+/// socket that started them so they can be resumed after a reconnect.
+#[derive(Default)]
+pub struct TransferManager {
+    uploads: DashMap<String, Arc<Upload>>,
+    downloads: DashMap<String, Download>,
+    runs: AtomicU64,
+}
+
+impl TransferManager {
+    /// The upload with `id`, if it belongs to `email`.
+    pub fn upload(&self, email: &str, id: &str) -> Option<Arc<Upload>> {
+        self.uploads.get(id).map(|r| r.clone()).filter(|u| u.email == email)
+    }
+
+    /// Drops uploads nobody has written to in a while, with their part files.
+    fn reap_idle_uploads(&self) {
+        let idle: Vec<String> = self
+            .uploads
+            .iter()
+            .filter(|entry| {
+                entry
+                    .value()
+                    .state
+                    .try_lock()
+                    .is_ok_and(|state| state.touched.elapsed() > UPLOAD_IDLE)
+            })
+            .map(|entry| entry.key().clone())
+            .collect();
+        for id in idle {
+            if let Some((_, upload)) = self.uploads.remove(&id) {
+                println!("[transfer] dropping idle upload {} to {}", upload.id, upload.path);
+                tokio::spawn(async move {
+                    file_ops::remove_file(&upload.container_id, &upload.part).await.ok();
+                });
+            }
+        }
+    }
+
+    /// Starts an upload of `size` bytes to `path`.
+    pub async fn start_upload(
+        &self,
+        email: &str,
+        container_id: &str,
+        path: &str,
+        size: u64,
+        checksum: Option<u32>,
+    ) -> Result<Arc<Upload>, String> {
+        let max = max_file_bytes();
+        if size > max {
+            return Err(format!("File is {} bytes; the limit is {} bytes", size, max));
+        }
+        self.reap_idle_uploads();
+        if self.uploads.iter().filter(|entry| entry.value().email == email).count() >= MAX_UPLOADS_PER_USER {
+            return Err(format!(
+                "Upload limit reached: {} uploads already in progress",
+                MAX_UPLOADS_PER_USER
+            ));
+        }
+
+        let id = Uuid::new_v4().to_string();
+        let (dir, name) = path.rsplit_once('/').unwrap_or(("", path));
+        let part = format!("{}/.{}.{}.part", dir, name, &id[..8]);
+        file_ops::prepare_upload(container_id, &part, path).await?;
+
+        let upload = std::sync::Arc::new(Upload {
+            id: id.clone(),
+            email: email.to_string(),
+            container_id: container_id.to_string(),
+            path: path.to_string(),
+            size,
+            checksum,
+            part,
+            state: Mutex::new(UploadState { offset: 0, crc: 0, touched: Instant::now() }),
+        });
+        self.uploads.insert(id, upload.clone());
+        println!("[transfer] upload {} of {} bytes to {} started for {}", upload.id, size, path, email);
+        Ok(upload)
+    }
+
+    /// Moves a fully written upload into place after checking its checksum.
+    pub async fn finish_upload(&self, upload: &Upload) -> Result<(), String> {
+        let state = upload.state.lock().await;
+        if state.offset != upload.size {
+            return Err(format!("Upload has {} of {} bytes", state.offset, upload.size));
+        }
+        if self.uploads.remove(&upload.id).is_none() {
+            // A resent last chunk; the first one already finished it.
+            return Ok(());
+        }
+        if let Some(expected) = upload.checksum.filter(|expected| *expected != state.crc) {
+            file_ops::remove_file(&upload.container_id, &upload.part).await.ok();
+            return Err(format!(
+                "Checksum mismatch: expected {:08x}, received {:08x}; upload it again",
+                expected, state.crc
+            ));
+        }
+        file_ops::commit_upload(&upload.container_id, &upload.part, &upload.path).await?;
+        println!("[transfer] upload {} to {} finished", upload.id, upload.path);
+        Ok(())
+    }
+
+    /// Abandons an upload and removes its part file.
+    pub async fn cancel_upload(&self, upload: &Upload) -> Result<(), String> {
+        self.uploads.remove(&upload.id);
+        file_ops::remove_file(&upload.container_id, &upload.part).await
+    }
+
+    /// Streams `path` from `offset` to `s` in chunks, each acked by the
+    /// client before the window fills. Reusing an id replaces that download,
+    /// which is how a client resumes one.
+    pub async fn start_download(
+        self: &Arc<Self>,
+        s: &SocketRef,
+        email: &str,
+        container_id: &str,
+        path: &str,
+        offset: u64,
+        id: Option<String>,
+    ) -> Result<(), String> {
+        let size = file_ops::file_size(container_id, path).await?;
+        let max = max_file_bytes();
+        if size > max {
+            return Err(format!("File is {} bytes; the limit is {} bytes", size, max));
+        }
+        if offset > size {
+            return Err(format!("Offset {} is past the end of the file ({} bytes)", offset, size));
+        }
+
+        let id = id.unwrap_or_else(|| Uuid::new_v4().to_string());
+        if let Some((_, previous)) = self.downloads.remove_if(&id, |_, d| d.email == email) {
+            previous.task.abort();
+        } else if self.downloads.contains_key(&id) {
+            return Err(format!("Download id {} is taken", id));
+        }
+        let mut child = exec_reader(container_id, &["tail", "-c", &format!("+{}", offset + 1), path])
+            .map_err(|e| format!("Failed to execute docker command: {}", e))?;
+        let Some(mut stdout) = child.stdout.take() else {
+            return Err("Failed to read the file".to_string());
+        };
+
+        s.emit(events::outgoing::DOWNLOAD_STARTED, &DownloadStartedPayload {
+            download_id: id.clone(),
+            path: path.to_string(),
+            size,
+            offset,
+            mime_type: mime_type(path, &[], true).to_string(),
+        })
+        .ok();
+
+        let run = self.runs.fetch_add(1, Ordering::Relaxed);
+        let (acked, mut acks) = watch::channel(None);
+        let manager = self.clone();
+        let socket = s.clone();
+        let download_id = id.clone();
+        let path = path.to_string();
+        let task = tokio::spawn(async move {
+            let _child = child;
+            let mut sent = offset;
+            let mut buf = vec![0u8; CHUNK_BYTES];
+            let finished = loop {
+                // `wait_for` hands back a guard on the channel; drop it at
+                // once so acks aren't blocked while a chunk is read.
+                let ready = tokio::time::timeout(
+                    ACK_TIMEOUT,
+                    acks.wait_for(|acked| acked.is_some_and(|acked| sent.saturating_sub(acked) < DOWNLOAD_WINDOW)),
+                )
+                .await
+                .is_ok_and(|window| window.is_ok());
+                if !ready {
+                    break false;
+                }
+                let n = match read_chunk(&mut stdout, &mut buf).await {
+                    Ok(0) => break sent == size,
+                    Ok(n) => n,
+                    Err(_) => break false,
+                };
+                socket
+                    .emit(events::outgoing::DOWNLOAD_CHUNK, &DownloadChunkPayload {
+                        download_id: download_id.clone(),
+                        offset: sent,
+                        data: bytes::Bytes::copy_from_slice(&buf[..n]),
+                        checksum: crc32(0, &buf[..n]),
+                    })
+                    .ok();
+                sent += n as u64;
+            };
+            if finished {
+                socket
+                    .emit(events::outgoing::DOWNLOAD_COMPLETE, &DownloadCompletePayload {
+                        download_id: download_id.clone(),
+                        path: path.clone(),
+                        size: sent,
+                    })
+                    .ok();
+            } else {
+                eprintln!("[transfer] download {} of {} stopped at {} of {} bytes", download_id, path, sent, size);
+            }
+            manager.downloads.remove_if(&download_id, |_, d| d.run == run);
+        });
+
+        // Registered before the window opens: a run that finishes at once
+        // must find its own entry to remove, not leave it behind.
+        let open = acked.clone();
+        self.downloads.insert(id, Download {
+            email: email.to_string(),
+            sid: s.id,
+            run,
+            acked,
+            task: task.abort_handle(),
+        });
+        open.send_replace(Some(offset));
+        Ok(())
+    }
+
+    /// Records that the client has every byte of a download before `offset`.
+    pub fn ack_download(&self, email: &str, id: &str, offset: u64) {
+        if let Some(download) = self.downloads.get(id).filter(|d| d.email == email) {
+            download.acked.send_modify(|acked| {
+                if let Some(acked) = acked {
+                    *acked = (*acked).max(offset);
+                }
+            });
+        }
+    }
+
+    pub fn cancel_download(&self, email: &str, id: &str) -> bool {
+        match self.downloads.remove_if(id, |_, d| d.email == email) {
+            Some((_, download)) => {
+                download.task.abort();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Stops the downloads streaming to `sid` once the socket is gone.
+    pub fn forget_socket(&self, sid: Sid) {
+        self.downloads.retain(|_, download| {
+            if download.sid == sid {
+                download.task.abort();
+            }
+            download.sid != sid
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(0, b"123456789"), 0xCBF43926);
+        assert_eq!(crc32(crc32(0, b"1234"), b"56789"), 0xCBF43926);
+        assert_eq!(crc32(0, b""), 0);
+    }
+}
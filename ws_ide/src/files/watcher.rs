@@ -0,0 +1,497 @@
+use dashmap::DashMap;
+use socketioxide::{extract::SocketRef, socket::Sid};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::sync::Notify;
+use tokio::task::AbortHandle;
+use tokio::time::Instant;
+use uuid::Uuid;
+
+use crate::{
+    docker_vm::exec::{exec_output, exec_reader, kill_marked},
+    events,
+    files::exclude::ExcludeRules,
+    types::{FileErrorPayload, FileOp, FsChange, FsChangeKind, FsChangedPayload},
+};
+
+/// Env var tagging a watcher's `inotifywait`, so a restart can kill it.
+pub const WATCH_MARKER: &str = "AKS_WATCH_ID";
+
+/// Events are collected this long after the first one before being sent.
+const DEBOUNCE: Duration = Duration::from_millis(250);
+
+/// A change to the watched set waits this long for more before restarting
+/// `inotifywait`, so expanding a few folders restarts it once.
+const RESTART_DELAY: Duration = Duration::from_millis(150);
+
+/// Wait before restarting an `inotifywait` that exited on its own.
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Directories one user may watch at once.
+const MAX_WATCHED_DIRS: usize = 256;
+
+/// Changes sent in one `fs_changed`; a burst past this is cut off, which
+/// the client treats like any other change by re-listing the directory.
+const MAX_BATCH: usize = 512;
+
+/// `inotifywait` is installed when the container is created; an older
+/// container without it has no live file events.
+const CHECK_SCRIPT: &str = "command -v inotifywait >/dev/null 2>&1 || \
+    { echo 'inotifywait is not available in this workspace; file changes are not watched' >&2; exit 1; }";
+
+/// Watches the directories that still exist among its arguments, printing
+/// `events<TAB>dir<TAB>name` per event.
+const WATCH_SCRIPT: &str = r#"n=$#
+for d; do [ -d "$d" ] && set -- "$@" "$d"; done
+shift "$n"
+[ "$#" -gt 0 ] || exit 0
+exec inotifywait -m -q --format "$(printf '%%e\t%%w\t%%f')" \
+    -e create,delete,close_write,moved_from,moved_to,delete_self,move_self -- "$@""#;
+
+/// One line of `inotifywait` output.
+#[derive(Debug, Clone)]
+struct RawEvent {
+    flags: Vec<String>,
+    path: String,
+    is_dir: bool,
+}
+
+fn parse_event(line: &str) -> Option<RawEvent> {
+    let mut fields = line.splitn(3, '\t');
+    let flags: Vec<String> = fields.next()?.split(',').map(str::to_string).collect();
+    let dir = fields.next()?.trim_end_matches('/');
+    let name = fields.next().unwrap_or("");
+    let path = match name {
+        "" => dir.to_string(),
+        name => format!("{}/{}", dir, name),
+    };
+    let is_dir = flags.iter().any(|f| f == "ISDIR");
+    Some(RawEvent { flags, path, is_dir })
+}
+
+/// Folds a burst of events into one change per path: a file created and
+/// deleted within the window never shows up, one deleted and recreated is
+/// modified, and a `MOVED_FROM` followed by its `MOVED_TO` is a rename.
+fn coalesce(events: Vec<RawEvent>) -> Vec<FsChange> {
+    let mut changes: Vec<Option<FsChange>> = Vec::new();
+    let mut by_path: HashMap<String, usize> = HashMap::new();
+    let mut merge = |change: FsChange| {
+        let Some(&i) = by_path.get(&change.path) else {
+            by_path.insert(change.path.clone(), changes.len());
+            changes.push(Some(change));
+            return;
+        };
+        let merged = match (changes[i].take(), change.kind) {
+            (None, _) => Some(change),
+            (Some(old), FsChangeKind::Modified) if old.kind != FsChangeKind::Deleted => Some(old),
+            (Some(old), FsChangeKind::Deleted) if old.kind == FsChangeKind::Created => None,
+            (Some(old), FsChangeKind::Deleted) if old.kind == FsChangeKind::Renamed => Some(FsChange {
+                path: old.old_path.unwrap_or(old.path),
+                old_path: None,
+                ..change
+            }),
+            (Some(old), FsChangeKind::Created) if old.kind == FsChangeKind::Deleted => {
+                Some(FsChange { kind: FsChangeKind::Modified, ..change })
+            }
+            (Some(_), _) => Some(change),
+        };
+        changes[i] = merged;
+    };
+
+    let mut moved_from: Option<RawEvent> = None;
+    for event in events {
+        let has = |flag: &str| event.flags.iter().any(|f| f == flag);
+        if has("IGNORED") {
+            continue;
+        }
+        let change = |kind: FsChangeKind, event: &RawEvent| FsChange {
+            kind,
+            path: event.path.clone(),
+            old_path: None,
+            is_dir: event.is_dir,
+        };
+        if let Some(from) = moved_from.take() {
+            if has("MOVED_TO") {
+                merge(FsChange {
+                    old_path: Some(from.path.clone()),
+                    ..change(FsChangeKind::Renamed, &event)
+                });
+                continue;
+            }
+            merge(change(FsChangeKind::Deleted, &from));
+        }
+        if has("MOVED_FROM") {
+            moved_from = Some(event);
+        } else if has("MOVED_TO") || has("CREATE") {
+            merge(change(FsChangeKind::Created, &event));
+        } else if has("DELETE") || has("DELETE_SELF") || has("MOVE_SELF") {
+            merge(change(FsChangeKind::Deleted, &event));
+        } else if has("CLOSE_WRITE") {
+            merge(change(FsChangeKind::Modified, &event));
+        }
+    }
+    if let Some(from) = moved_from {
+        merge(FsChange {
+            kind: FsChangeKind::Deleted,
+            path: from.path,
+            old_path: None,
+            is_dir: from.is_dir,
+        });
+    }
+    changes.into_iter().flatten().collect()
+}
+
+/// A user's watched directories and the `inotifywait` covering them. Each
+/// directory remembers which sockets expanded it, so it stays watched until
+/// the last of them collapses it or goes away.
+pub struct DirWatcher {
+    pub email: String,
+    pub container_id: String,
+    root: Option<String>,
+    id: String,
+    dirs: Mutex<HashMap<String, HashSet<Sid>>>,
+    sockets: Mutex<HashMap<Sid, SocketRef>>,
+    changed: Notify,
+    task: Mutex<Option<AbortHandle>>,
+}
+
+impl DirWatcher {
+    fn new(email: &str, container_id: &str, root: Option<&str>) -> Arc<Self> {
+        Arc::new(Self {
+            email: email.to_string(),
+            container_id: container_id.to_string(),
+            root: root.map(str::to_string),
+            id: Uuid::new_v4().to_string(),
+            dirs: Mutex::new(HashMap::new()),
+            sockets: Mutex::new(HashMap::new()),
+            changed: Notify::new(),
+            task: Mutex::new(None),
+        })
+    }
+
+    fn dir_list(&self) -> Vec<String> {
+        let mut dirs: Vec<String> = self.dirs.lock().unwrap().keys().cloned().collect();
+        dirs.sort();
+        dirs
+    }
+
+    fn is_empty(&self) -> bool {
+        self.dirs.lock().unwrap().is_empty()
+    }
+
+    async fn load_rules(&self) -> ExcludeRules {
+        ExcludeRules::load(&self.container_id, self.root.as_deref())
+            .await
+            .unwrap_or_else(|e| {
+                eprintln!("[watch] {} for {}", e, self.email);
+                ExcludeRules::defaults(self.root.as_deref())
+            })
+    }
+
+    /// Sends `events` as changes, leaving out excluded paths. Reloads
+    /// `rules` first when the batch touches one of the files they come from.
+    async fn emit_changes(&self, rules: &mut ExcludeRules, events: Vec<RawEvent>) {
+        let mut changes = coalesce(events);
+        if changes.is_empty() {
+            return;
+        }
+        if changes.iter().any(|change| rules.is_rules_file(&change.path)) {
+            *rules = self.load_rules().await;
+        }
+        let paths: Vec<&str> = changes
+            .iter()
+            .flat_map(|change| std::iter::once(change.path.as_str()).chain(change.old_path.as_deref()))
+            .collect();
+        let excluded = rules.excluded(&self.container_id, &paths).await;
+        changes.retain(|change| {
+            !excluded.contains(&change.path) || change.old_path.as_ref().is_some_and(|old| !excluded.contains(old))
+        });
+        changes.truncate(MAX_BATCH);
+        if changes.is_empty() {
+            return;
+        }
+        let payload = FsChangedPayload { changes };
+        for socket in self.sockets.lock().unwrap().values() {
+            socket.emit(events::outgoing::FS_CHANGED, &payload).ok();
+        }
+    }
+
+    fn emit_error(&self, msg: &str) {
+        let payload = FileErrorPayload {
+            op: FileOp::Watch,
+            path: String::new(),
+            message: msg.to_string(),
+        };
+        for socket in self.sockets.lock().unwrap().values() {
+            socket.emit(events::outgoing::FILE_ERROR, &payload).ok();
+        }
+    }
+
+    /// Drops `sid` from `dirs` (every directory when `None`). Returns
+    /// whether the watched set changed.
+    fn release(&self, sid: Sid, dir: Option<&str>) -> bool {
+        let mut dirs = self.dirs.lock().unwrap();
+        let before = dirs.len();
+        dirs.retain(|path, sids| {
+            if dir.is_none_or(|dir| dir == path) {
+                sids.remove(&sid);
+            }
+            !sids.is_empty()
+        });
+        if !dirs.values().any(|sids| sids.contains(&sid)) {
+            self.sockets.lock().unwrap().remove(&sid);
+        }
+        dirs.len() != before
+    }
+
+    fn stop(&self) {
+        if let Some(task) = self.task.lock().unwrap().take() {
+            task.abort();
+        }
+        let container_id = self.container_id.clone();
+        let id = self.id.clone();
+        tokio::spawn(async move {
+            kill_marked(&container_id, WATCH_MARKER, &id).await.ok();
+        });
+    }
+}
+
+/// Runs `inotifywait` over the watched set until the watcher is stopped,
+/// restarting it whenever the set changes.
+async fn run(watcher: Arc<DirWatcher>) {
+    match exec_output(&watcher.container_id, &["/bin/sh", "-c", CHECK_SCRIPT], None).await {
+        Ok((0, _)) => {}
+        Ok((_, output)) => {
+            watcher.emit_error(output.trim());
+            return;
+        }
+        Err(e) => {
+            watcher.emit_error(&format!("Failed to execute docker command: {}", e));
+            return;
+        }
+    }
+
+    let marker = format!("{}={}", WATCH_MARKER, watcher.id);
+    loop {
+        let dirs = watcher.dir_list();
+        if dirs.is_empty() {
+            watcher.changed.notified().await;
+            tokio::time::sleep(RESTART_DELAY).await;
+            continue;
+        }
+
+        let mut rules = watcher.load_rules().await;
+        let mut argv = vec!["env", marker.as_str(), "/bin/sh", "-c", WATCH_SCRIPT, "sh"];
+        argv.extend(dirs.iter().map(String::as_str));
+        let mut child = match exec_reader(&watcher.container_id, &argv) {
+            Ok(child) => child,
+            Err(e) => {
+                eprintln!("[watch] failed to start inotifywait for {}: {}", watcher.email, e);
+                tokio::time::sleep(RETRY_DELAY).await;
+                continue;
+            }
+        };
+        let Some(stdout) = child.stdout.take() else {
+            continue;
+        };
+        let mut lines = BufReader::new(stdout).lines();
+        let mut pending: Vec<RawEvent> = Vec::new();
+        let mut deadline = Instant::now();
+
+        let restart = loop {
+            tokio::select! {
+                line = lines.next_line() => match line {
+                    Ok(Some(line)) => {
+                        if let Some(event) = parse_event(&line) {
+                            if pending.is_empty() {
+                                deadline = Instant::now() + DEBOUNCE;
+                            }
+                            pending.push(event);
+                        }
+                    }
+                    _ => break false,
+                },
+                _ = tokio::time::sleep_until(deadline), if !pending.is_empty() => {
+                    watcher.emit_changes(&mut rules, std::mem::take(&mut pending)).await;
+                }
+                _ = watcher.changed.notified() => break true,
+            }
+        };
+        watcher.emit_changes(&mut rules, pending).await;
+        drop(child);
+        if let Err(e) = kill_marked(&watcher.container_id, WATCH_MARKER, &watcher.id).await {
+            eprintln!("[watch] failed to stop inotifywait for {}: {}", watcher.email, e);
+        }
+
+        if restart {
+            tokio::time::sleep(RESTART_DELAY).await;
+        } else {
+            // Every watched directory is gone, or inotifywait failed; try
+            // again later or as soon as the set changes.
+            tokio::select! {
+                _ = tokio::time::sleep(RETRY_DELAY) => {}
+                _ = watcher.changed.notified() => {}
+            }
+        }
+    }
+}
+
+/// Each user's directory watcher, by email.
+#[derive(Default)]
+pub struct WatchManager {
+    watchers: DashMap<String, Arc<DirWatcher>>,
+}
+
+impl WatchManager {
+    /// Starts sending `s` changes to `dir`. Starts the user's watcher when
+    /// this is the first directory, or when the container has changed.
+    /// Changes to paths the workspace's exclude rules match under `root`
+    /// are not sent.
+    pub fn watch(
+        &self,
+        s: &SocketRef,
+        email: &str,
+        container_id: &str,
+        root: Option<&str>,
+        dir: &str,
+    ) -> Result<(), String> {
+        let watcher = {
+            let mut entry = self
+                .watchers
+                .entry(email.to_string())
+                .or_insert_with(|| DirWatcher::new(email, container_id, root));
+            if entry.container_id != container_id || entry.root.as_deref() != root {
+                entry.stop();
+                *entry = DirWatcher::new(email, container_id, root);
+            }
+            entry.clone()
+        };
+
+        {
+            let mut dirs = watcher.dirs.lock().unwrap();
+            if !dirs.contains_key(dir) && dirs.len() >= MAX_WATCHED_DIRS {
+                return Err(format!(
+                    "Watch limit reached: {} directories already watched",
+                    MAX_WATCHED_DIRS
+                ));
+            }
+            let sids = dirs.entry(dir.to_string()).or_default();
+            let added = sids.is_empty();
+            sids.insert(s.id);
+            watcher.sockets.lock().unwrap().insert(s.id, s.clone());
+            if added {
+                watcher.changed.notify_one();
+            }
+        }
+
+        let mut task = watcher.task.lock().unwrap();
+        if task.is_none() {
+            println!("[watch] starting watcher for {} in {}", email, container_id);
+            *task = Some(tokio::spawn(run(watcher.clone())).abort_handle());
+        }
+        Ok(())
+    }
+
+    /// Stops sending `s` changes to `dir`.
+    pub fn unwatch(&self, s: &SocketRef, email: &str, dir: &str) {
+        if let Some(watcher) = self.watchers.get(email).map(|r| r.clone()) {
+            if watcher.release(s.id, Some(dir)) {
+                watcher.changed.notify_one();
+            }
+            self.stop_if_idle(&watcher);
+        }
+    }
+
+    /// Drops `sid` from every watcher once the socket is gone.
+    pub fn forget_socket(&self, sid: Sid) {
+        let watchers: Vec<Arc<DirWatcher>> = self.watchers.iter().map(|r| r.value().clone()).collect();
+        for watcher in watchers {
+            if watcher.release(sid, None) {
+                watcher.changed.notify_one();
+            }
+            self.stop_if_idle(&watcher);
+        }
+    }
+
+    fn stop_if_idle(&self, watcher: &Arc<DirWatcher>) {
+        let removed = self
+            .watchers
+            .remove_if(&watcher.email, |_, current| Arc::ptr_eq(current, watcher) && current.is_empty());
+        if let Some((_, watcher)) = removed {
+            println!("[watch] stopping watcher for {}", watcher.email);
+            watcher.stop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn events(lines: &[&str]) -> Vec<RawEvent> {
+        lines.iter().filter_map(|line| parse_event(line)).collect()
+    }
+
+    fn summary(changes: &[FsChange]) -> Vec<(FsChangeKind, &str, Option<&str>)> {
+        changes
+            .iter()
+            .map(|c| (c.kind, c.path.as_str(), c.old_path.as_deref()))
+            .collect()
+    }
+
+    #[test]
+    fn created_then_deleted_is_dropped() {
+        let changes = coalesce(events(&[
+            "CREATE\t/w/\ttmp.txt",
+            "CLOSE_WRITE,CLOSE\t/w/\ttmp.txt",
+            "DELETE\t/w/\ttmp.txt",
+        ]));
+        assert!(changes.is_empty());
+    }
+
+    #[test]
+    fn deleted_then_created_is_modified() {
+        let changes = coalesce(events(&["DELETE\t/w/\ta.rs", "CREATE\t/w/\ta.rs", "CLOSE_WRITE,CLOSE\t/w/\ta.rs"]));
+        assert_eq!(summary(&changes), [(FsChangeKind::Modified, "/w/a.rs", None)]);
+    }
+
+    #[test]
+    fn moved_from_and_to_pair_into_a_rename() {
+        let changes = coalesce(events(&["MOVED_FROM,ISDIR\t/w/\told", "MOVED_TO,ISDIR\t/w/\tnew"]));
+        assert_eq!(summary(&changes), [(FsChangeKind::Renamed, "/w/new", Some("/w/old"))]);
+        assert!(changes[0].is_dir);
+    }
+
+    #[test]
+    fn unpaired_moves_are_delete_and_create() {
+        let changes = coalesce(events(&[
+            "MOVED_FROM\t/w/\tout.txt",
+            "CREATE\t/w/\tother.txt",
+            "MOVED_TO\t/w/\tin.txt",
+        ]));
+        assert_eq!(
+            summary(&changes),
+            [
+                (FsChangeKind::Deleted, "/w/out.txt", None),
+                (FsChangeKind::Created, "/w/other.txt", None),
+                (FsChangeKind::Created, "/w/in.txt", None),
+            ]
+        );
+        let trailing = coalesce(events(&["MOVED_FROM\t/w/\tgone.txt"]));
+        assert_eq!(summary(&trailing), [(FsChangeKind::Deleted, "/w/gone.txt", None)]);
+    }
+
+    #[test]
+    fn renamed_then_deleted_is_the_old_path_deleted() {
+        let changes = coalesce(events(&["MOVED_FROM\t/w/\ta", "MOVED_TO\t/w/\tb", "DELETE\t/w/\tb"]));
+        assert_eq!(summary(&changes), [(FsChangeKind::Deleted, "/w/a", None)]);
+    }
+
+    #[test]
+    fn writes_to_a_new_file_stay_created() {
+        let changes = coalesce(events(&["CREATE\t/w/\tn", "CLOSE_WRITE,CLOSE\t/w/\tn", "IGNORED\t/w/\t"]));
+        assert_eq!(summary(&changes), [(FsChangeKind::Created, "/w/n", None)]);
+    }
+}
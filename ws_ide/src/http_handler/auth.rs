@@ -0,0 +1,42 @@
+use axum::{
+    extract::Request,
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+/// Env var holding the token internal callers send as `Authorization: Bearer
+/// <token>`. The REST routes reach into any user's container, so they're
+/// refused outright while it isn't set.
+pub const API_TOKEN_VAR: &str = "INTERNAL_API_TOKEN";
+
+fn api_token() -> Option<String> {
+    std::env::var(API_TOKEN_VAR).ok().filter(|token| !token.is_empty())
+}
+
+/// Compares without stopping at the first differing byte, so response
+/// timing doesn't leak how much of a guess was right.
+fn same_token(given: &[u8], expected: &[u8]) -> bool {
+    given.len() == expected.len() && given.iter().zip(expected).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+/// Middleware for the internal REST routes: lets a request through only with
+/// the bearer token from `INTERNAL_API_TOKEN`.
+pub async fn require_api_token(request: Request, next: Next) -> Response {
+    let Some(expected) = api_token() else {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!("This API is disabled; set {} to enable it", API_TOKEN_VAR),
+        )
+            .into_response();
+    };
+    let given = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    match given {
+        Some(given) if same_token(given.trim().as_bytes(), expected.as_bytes()) => next.run(request).await,
+        _ => (StatusCode::UNAUTHORIZED, "Missing or invalid API token").into_response(),
+    }
+}
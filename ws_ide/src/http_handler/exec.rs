@@ -0,0 +1,68 @@
+use axum::{
+    body::Body,
+    extract::{Path, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use bytes::Bytes;
+use futures_util::stream;
+use serde_json::json;
+use tokio::sync::mpsc;
+
+use crate::{docker_vm::exec_command::run_exec_command, state::AppState, types::ExecRequest};
+
+fn error_response(e: std::io::Error) -> Response {
+    let status = match e.kind() {
+        std::io::ErrorKind::NotFound => StatusCode::NOT_FOUND,
+        std::io::ErrorKind::InvalidInput => StatusCode::BAD_REQUEST,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    (status, e.to_string()).into_response()
+}
+
+fn ndjson_line(value: serde_json::Value) -> Bytes {
+    let mut line = value.to_string();
+    line.push('\n');
+    Bytes::from(line)
+}
+
+/// `POST /exec/{email}`. Returns the result as JSON, or with `"stream": true`
+/// an NDJSON body of `output` lines followed by one `result` (or `error`) line.
+/// Mounted behind `auth::require_api_token`, since it runs anything as the
+/// container's user.
+pub async fn exec_command(
+    State(state): State<AppState>,
+    Path(email): Path<String>,
+    Json(request): Json<ExecRequest>,
+) -> Response {
+    if !request.stream {
+        return match run_exec_command(&state, &email, &request, |_, _| {}).await {
+            Ok(result) => Json(result).into_response(),
+            Err(e) => error_response(e),
+        };
+    }
+
+    let (tx, rx) = mpsc::unbounded_channel::<Bytes>();
+    tokio::spawn(async move {
+        let result = run_exec_command(&state, &email, &request, |stream, chunk| {
+            tx.send(ndjson_line(json!({ "type": "output", "stream": stream, "data": chunk })))
+                .ok();
+        })
+        .await;
+        let last = match result {
+            Ok(result) => json!({ "type": "result", "result": result }),
+            Err(e) => json!({ "type": "error", "message": e.to_string() }),
+        };
+        tx.send(ndjson_line(last)).ok();
+    });
+
+    let body = stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|line| (Ok::<_, std::io::Error>(line), rx))
+    });
+    (
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(body),
+    )
+        .into_response()
+}
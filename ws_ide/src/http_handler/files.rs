@@ -0,0 +1,161 @@
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use bytes::Bytes;
+use futures_util::stream;
+
+use crate::{
+    docker_vm::{exec::exec_reader, file_ops},
+    files::{
+        content::mime_type,
+        transfer::{max_file_bytes, read_chunk, CHUNK_BYTES},
+    },
+    state::AppState,
+    types::FileDownloadQuery,
+};
+
+/// Start of a `Range: bytes=N-` header. Ranges that stop before the end of
+/// the file aren't supported; the whole file is sent for those.
+fn range_start(value: &str, size: u64) -> Option<u64> {
+    let (start, end) = value.strip_prefix("bytes=")?.split_once('-')?;
+    let start = start.trim().parse().ok()?;
+    match end.trim() {
+        "" => Some(start),
+        end => (end.parse::<u64>().ok()? + 1 == size).then_some(start),
+    }
+}
+
+/// `GET /files/{email}/download?path=...`. Streams a file from the user's
+/// workspace; `Range: bytes=N-` resumes an interrupted download at byte N.
+/// Mounted behind `auth::require_api_token`.
+pub async fn download_file(
+    State(state): State<AppState>,
+    Path(email): Path<String>,
+    Query(query): Query<FileDownloadQuery>,
+    headers: HeaderMap,
+) -> Response {
+    // Confined like the socket download: to the cloned repo, or the
+    // container's own workdir when nothing was cloned.
+    let root = state.workspace_dir(&email);
+    let path = match file_ops::resolve_in_workspace(Some(&root), &query.path) {
+        Ok(path) => path,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+    let Some(container_id) = state.docker_container_id.get(&email).map(|r| r.clone()) else {
+        return (
+            StatusCode::NOT_FOUND,
+            format!("No Docker container found for email: {}", email),
+        )
+            .into_response();
+    };
+    let size = match file_ops::file_size(&container_id, &path).await {
+        Ok(size) => size,
+        Err(e) => return (StatusCode::NOT_FOUND, e).into_response(),
+    };
+    let max = max_file_bytes();
+    if size > max {
+        return (
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!("File is {} bytes; the limit is {} bytes", size, max),
+        )
+            .into_response();
+    }
+
+    let start = headers
+        .get(header::RANGE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| range_start(v, size))
+        .filter(|_| size > 0);
+    if start.is_some_and(|start| start >= size) {
+        return (
+            StatusCode::RANGE_NOT_SATISFIABLE,
+            [(header::CONTENT_RANGE, format!("bytes */{}", size))],
+        )
+            .into_response();
+    }
+    let offset = start.unwrap_or(0);
+
+    let mut child = match exec_reader(&container_id, &["tail", "-c", &format!("+{}", offset + 1), &path]) {
+        Ok(child) => child,
+        Err(e) => {
+            eprintln!("[files] failed to read {} for {}: {}", path, email, e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+        }
+    };
+    let Some(stdout) = child.stdout.take() else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to read the file").into_response();
+    };
+    // The child rides along with the stream so the read stops when the
+    // client goes away.
+    let body = stream::unfold((child, stdout), |(child, mut stdout)| async move {
+        let mut buf = vec![0u8; CHUNK_BYTES];
+        match read_chunk(&mut stdout, &mut buf).await {
+            Ok(0) => None,
+            Ok(n) => {
+                buf.truncate(n);
+                Some((Ok(Bytes::from(buf)), (child, stdout)))
+            }
+            Err(e) => Some((Err(e), (child, stdout))),
+        }
+    });
+
+    let name = path.rsplit('/').next().unwrap_or(&path).replace('"', "_");
+    let mut response = (
+        [
+            (header::CONTENT_TYPE, mime_type(&path, &[], true).to_string()),
+            (header::CONTENT_LENGTH, (size - offset).to_string()),
+            (header::ACCEPT_RANGES, "bytes".to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", name)),
+        ],
+        Body::from_stream(body),
+    )
+        .into_response();
+    if start.is_some() {
+        *response.status_mut() = StatusCode::PARTIAL_CONTENT;
+        if let Ok(value) = format!("bytes {}-{}/{}", offset, size - 1, size).parse() {
+            response.headers_mut().insert(header::CONTENT_RANGE, value);
+        }
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sea_orm::DatabaseConnection;
+
+    async fn download(state: &AppState, path: &str) -> StatusCode {
+        let query = FileDownloadQuery { path: path.to_string() };
+        download_file(State(state.clone()), Path("a@x".to_string()), Query(query), HeaderMap::new())
+            .await
+            .status()
+    }
+
+    #[tokio::test]
+    async fn downloads_are_confined_to_the_cloned_repo() {
+        let state = AppState::new(DatabaseConnection::Disconnected);
+        state.workspace_root.insert("a@x".to_string(), "/home/a/repo".to_string());
+        assert_eq!(download(&state, "../../../etc/passwd").await, StatusCode::BAD_REQUEST);
+        assert_eq!(download(&state, "/etc/passwd").await, StatusCode::BAD_REQUEST);
+        // Past the path check; there's no container to read from.
+        assert_eq!(download(&state, "src/main.rs").await, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn downloads_without_a_clone_resolve_against_the_container_workdir() {
+        let state = AppState::new(DatabaseConnection::Disconnected);
+        assert_eq!(download(&state, "etc/hosts").await, StatusCode::NOT_FOUND);
+        assert_eq!(download(&state, "/tmp/../etc/hosts").await, StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn range_starts() {
+        assert_eq!(range_start("bytes=10-", 100), Some(10));
+        assert_eq!(range_start("bytes=10-99", 100), Some(10));
+        assert_eq!(range_start("bytes=10-50", 100), None);
+        assert_eq!(range_start("items=10-", 100), None);
+    }
+}
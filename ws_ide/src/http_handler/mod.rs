@@ -0,0 +1,4 @@
+pub mod auth;
+pub mod exec;
+pub mod files;
+pub mod recordings;
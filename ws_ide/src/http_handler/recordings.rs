@@ -0,0 +1,77 @@
+use axum::{
+    extract::Path,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use std::time::UNIX_EPOCH;
+
+use crate::terminal_session::recorder::{is_valid_recording_name, workspace_recordings_dir};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RecordingInfo {
+    pub name: String,
+    pub size: u64,
+    pub modified: u64,
+}
+
+pub async fn list_recordings(Path(email): Path<String>) -> Response {
+    let mut entries = match tokio::fs::read_dir(workspace_recordings_dir(&email)).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Json(Vec::<RecordingInfo>::new()).into_response();
+        }
+        Err(e) => {
+            eprintln!("[recordings] failed to list for {}: {}", email, e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+        }
+    };
+
+    let mut recordings = Vec::new();
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if !is_valid_recording_name(&name) {
+            continue;
+        }
+        if let Ok(meta) = entry.metadata().await {
+            let modified = meta
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+            recordings.push(RecordingInfo { name, size: meta.len(), modified });
+        }
+    }
+    recordings.sort_by_key(|r| std::cmp::Reverse(r.modified));
+
+    Json(recordings).into_response()
+}
+
+pub async fn download_recording(Path((email, name)): Path<(String, String)>) -> Response {
+    if !is_valid_recording_name(&name) {
+        return (StatusCode::BAD_REQUEST, "Invalid recording name").into_response();
+    }
+
+    match tokio::fs::read(workspace_recordings_dir(&email).join(&name)).await {
+        Ok(bytes) => (
+            [
+                (header::CONTENT_TYPE, "application/x-asciicast".to_string()),
+                (
+                    header::CONTENT_DISPOSITION,
+                    format!("attachment; filename=\"{}\"", name),
+                ),
+            ],
+            bytes,
+        )
+            .into_response(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            (StatusCode::NOT_FOUND, "Recording not found").into_response()
+        }
+        Err(e) => {
+            eprintln!("[recordings] failed to read {} for {}: {}", name, email, e);
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
+        }
+    }
+}
@@ -0,0 +1,340 @@
+use dashmap::DashMap;
+use socketioxide::{extract::SocketRef, socket::Sid};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+use crate::{
+    docker_vm::exec::{exec_with, kill_marked, ExecOutcome, OutputStream},
+    events,
+    terminal_session::{options::is_valid_env_name, scrollback::Scrollback},
+    types::{JobInfo, JobOutputPayload, JobStatus},
+};
+
+/// Env var tagging every process a job run starts, so stop can kill them.
+pub const JOB_MARKER: &str = "AKS_JOB_ID";
+
+/// Output kept per job for `tail_job`.
+const LOG_BYTES: usize = 256 * 1024;
+
+/// Jobs a user may have running at once.
+const MAX_RUNNING_PER_USER: usize = 16;
+
+fn unix_ms(at: SystemTime) -> u64 {
+    at.duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+/// What a job runs. Kept so the job can be restarted as it was started.
+#[derive(Debug, Clone)]
+pub struct JobSpec {
+    pub name: String,
+    pub command: String,
+    pub cwd: Option<String>,
+    pub env: HashMap<String, String>,
+}
+
+struct JobState {
+    status: JobStatus,
+    exit_code: Option<i32>,
+    started_at: SystemTime,
+    finished_at: Option<SystemTime>,
+    restarts: u32,
+    /// Bumped on every (re)start; an exit from an older run is ignored.
+    run: u64,
+}
+
+/// A detached command running in a user's container. It belongs to the user,
+/// not to a terminal or socket, so it keeps running when tabs close.
+pub struct Job {
+    pub id: String,
+    pub email: String,
+    pub container_id: String,
+    pub spec: JobSpec,
+    state: Mutex<JobState>,
+    log: Mutex<Scrollback>,
+    /// Sockets told about status changes, and whether each follows the
+    /// output as well.
+    watchers: Mutex<HashMap<Sid, (SocketRef, bool)>>,
+}
+
+impl Job {
+    fn marker(&self, run: u64) -> String {
+        format!("{}-{}", self.id, run)
+    }
+
+    pub fn is_active(&self) -> bool {
+        matches!(self.state.lock().unwrap().status, JobStatus::Running | JobStatus::Stopping)
+    }
+
+    pub fn info(&self) -> JobInfo {
+        let state = self.state.lock().unwrap();
+        JobInfo {
+            id: self.id.clone(),
+            name: self.spec.name.clone(),
+            command: self.spec.command.clone(),
+            cwd: self.spec.cwd.clone(),
+            status: state.status,
+            exit_code: state.exit_code,
+            started_at: unix_ms(state.started_at),
+            finished_at: state.finished_at.map(unix_ms),
+            restarts: state.restarts,
+        }
+    }
+
+    fn emit_status(&self) {
+        let info = self.info();
+        for (socket, _) in self.watchers.lock().unwrap().values() {
+            socket.emit(events::outgoing::JOB_STATUS, &info).ok();
+        }
+    }
+
+    /// Subscribes `s` to status changes and, with `follow`, to the output.
+    pub fn watch(&self, s: &SocketRef, follow: bool) {
+        let mut watchers = self.watchers.lock().unwrap();
+        let entry = watchers.entry(s.id).or_insert_with(|| (s.clone(), false));
+        entry.1 |= follow;
+    }
+
+    /// Sends the buffered output to `s` and, with `follow`, keeps streaming
+    /// to it. Both happen under the log lock so no chunk is missed or sent
+    /// twice.
+    pub fn tail(&self, s: &SocketRef, follow: bool) {
+        let log = self.log.lock().unwrap();
+        let replay = log.bytes();
+        if !replay.is_empty() {
+            s.emit(events::outgoing::JOB_OUTPUT, &JobOutputPayload {
+                job_id: self.id.clone(),
+                stream: None,
+                data: String::from_utf8_lossy(&replay).into_owned(),
+                replay: true,
+            })
+            .ok();
+        }
+        let mut watchers = self.watchers.lock().unwrap();
+        let entry = watchers.entry(s.id).or_insert_with(|| (s.clone(), false));
+        entry.1 = follow;
+    }
+
+    fn output(&self, stream: OutputStream, chunk: &str) {
+        let mut log = self.log.lock().unwrap();
+        log.push(chunk.as_bytes());
+        for (socket, follow) in self.watchers.lock().unwrap().values() {
+            if *follow {
+                socket
+                    .emit(events::outgoing::JOB_OUTPUT, &JobOutputPayload {
+                        job_id: self.id.clone(),
+                        stream: Some(stream),
+                        data: chunk.to_string(),
+                        replay: false,
+                    })
+                    .ok();
+            }
+        }
+    }
+
+    fn finished(&self, run: u64, outcome: Result<ExecOutcome, std::io::Error>) {
+        {
+            let mut state = self.state.lock().unwrap();
+            if state.run != run {
+                return;
+            }
+            state.exit_code = outcome.as_ref().ok().and_then(|o| o.exit_code);
+            state.finished_at = Some(SystemTime::now());
+            state.status = match (&outcome, state.status) {
+                (_, JobStatus::Stopping) => JobStatus::Stopped,
+                (Ok(o), _) if o.exit_code == Some(0) => JobStatus::Exited,
+                _ => JobStatus::Failed,
+            };
+        }
+        if let Err(e) = outcome {
+            self.output(OutputStream::Stderr, &format!("Failed to run job: {}\n", e));
+        }
+        println!("[jobs] {} ({}) finished: {:?}", self.spec.name, self.id, self.info().status);
+        self.emit_status();
+    }
+}
+
+/// Every user's background jobs, by job id.
+#[derive(Default)]
+pub struct JobManager {
+    jobs: DashMap<String, Arc<Job>>,
+    /// Held across the checks and the insert so two starts can't both slip
+    /// under the cap or take the same name.
+    admit: Mutex<()>,
+}
+
+impl JobManager {
+    /// `email`'s jobs, oldest first.
+    pub fn list(&self, email: &str) -> Vec<Arc<Job>> {
+        let mut jobs: Vec<Arc<Job>> = self
+            .jobs
+            .iter()
+            .filter(|entry| entry.value().email == email)
+            .map(|entry| entry.value().clone())
+            .collect();
+        jobs.sort_by_key(|job| job.state.lock().unwrap().started_at);
+        jobs
+    }
+
+    /// The job with `id`, if it belongs to `email`.
+    pub fn get(&self, email: &str, id: &str) -> Option<Arc<Job>> {
+        self.jobs.get(id).map(|r| r.clone()).filter(|job| job.email == email)
+    }
+
+    /// Starts `spec` in `container_id`. A finished job with the same name is
+    /// replaced; a running one is an error.
+    pub fn start(&self, email: &str, container_id: &str, spec: JobSpec) -> Result<Arc<Job>, String> {
+        if spec.command.trim().is_empty() {
+            return Err("A job needs a command".to_string());
+        }
+        if let Some(bad) = spec.env.keys().find(|k| !is_valid_env_name(k)) {
+            return Err(format!("Invalid environment variable name: {}", bad));
+        }
+
+        let _admit = self.admit.lock().unwrap();
+        let jobs = self.list(email);
+        if let Some(existing) = jobs.iter().find(|job| job.spec.name == spec.name) {
+            if existing.is_active() {
+                return Err(format!("A job named {} is already running", spec.name));
+            }
+            self.jobs.remove(&existing.id);
+        }
+        if jobs.iter().filter(|job| job.is_active()).count() >= MAX_RUNNING_PER_USER {
+            return Err(format!(
+                "Job limit reached: {} jobs already running; stop one first",
+                MAX_RUNNING_PER_USER
+            ));
+        }
+
+        let job = Arc::new(Job {
+            id: Uuid::new_v4().to_string(),
+            email: email.to_string(),
+            container_id: container_id.to_string(),
+            spec,
+            state: Mutex::new(JobState {
+                status: JobStatus::Running,
+                exit_code: None,
+                started_at: SystemTime::now(),
+                finished_at: None,
+                restarts: 0,
+                run: 0,
+            }),
+            log: Mutex::new(Scrollback::new(LOG_BYTES)),
+            watchers: Mutex::new(HashMap::new()),
+        });
+        self.jobs.insert(job.id.clone(), job.clone());
+        drop(_admit);
+        run(&job);
+        Ok(job)
+    }
+
+    /// Kills the job's processes. Its status turns `stopped` once the run
+    /// has exited, or back to `running` when the kill fails.
+    pub async fn stop(&self, job: &Job) -> Result<(), std::io::Error> {
+        let run = {
+            let mut state = job.state.lock().unwrap();
+            if state.status != JobStatus::Running {
+                return Ok(());
+            }
+            state.status = JobStatus::Stopping;
+            state.run
+        };
+        job.emit_status();
+        let killed = kill_marked(&job.container_id, JOB_MARKER, &job.marker(run)).await;
+        if killed.is_err() {
+            let reverted = {
+                let mut state = job.state.lock().unwrap();
+                let still_stopping = state.run == run && state.status == JobStatus::Stopping;
+                if still_stopping {
+                    state.status = JobStatus::Running;
+                }
+                still_stopping
+            };
+            if reverted {
+                job.emit_status();
+            }
+        }
+        killed
+    }
+
+    /// Kills the current run, if any, and starts the command again.
+    pub async fn restart(&self, job: &Arc<Job>) -> Result<(), std::io::Error> {
+        let current = job.state.lock().unwrap().run;
+        if job.is_active() {
+            kill_marked(&job.container_id, JOB_MARKER, &job.marker(current)).await?;
+        }
+
+        // Admitted like a fresh start: a job that had already finished takes
+        // a slot again, so it has to fit under the cap.
+        let _admit = self.admit.lock().unwrap();
+        if !self.jobs.contains_key(&job.id) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("Job {} was removed", job.spec.name),
+            ));
+        }
+        let others = self
+            .list(&job.email)
+            .into_iter()
+            .filter(|other| other.id != job.id && other.is_active())
+            .count();
+        if others >= MAX_RUNNING_PER_USER {
+            return Err(std::io::Error::other(format!(
+                "Job limit reached: {} jobs already running; stop one first",
+                MAX_RUNNING_PER_USER
+            )));
+        }
+        job.state.lock().unwrap().restarts += 1;
+        run(job);
+        Ok(())
+    }
+
+    /// Forgets a job that is no longer running.
+    pub fn remove(&self, job: &Job) -> Result<(), String> {
+        if job.is_active() {
+            return Err(format!("Job {} is still running; stop it first", job.spec.name));
+        }
+        self.jobs.remove(&job.id);
+        Ok(())
+    }
+
+    /// Drops `sid` from every job's watchers once the socket is gone.
+    pub fn forget_socket(&self, sid: Sid) {
+        for entry in self.jobs.iter() {
+            entry.value().watchers.lock().unwrap().remove(&sid);
+        }
+    }
+}
+
+/// Starts a new run of `job` in the background.
+fn run(job: &Arc<Job>) {
+    let run = {
+        let mut state = job.state.lock().unwrap();
+        state.run += 1;
+        state.status = JobStatus::Running;
+        state.exit_code = None;
+        state.started_at = SystemTime::now();
+        state.finished_at = None;
+        state.run
+    };
+    job.emit_status();
+    println!("[jobs] {} ({}) started for {}: {}", job.spec.name, job.id, job.email, job.spec.command);
+
+    let job = job.clone();
+    tokio::spawn(async move {
+        let mut env = job.spec.env.clone();
+        env.insert(JOB_MARKER.to_string(), job.marker(run));
+        let argv = ["/bin/sh", "-c", job.spec.command.as_str()];
+        let outcome = exec_with(
+            &job.container_id,
+            &argv,
+            job.spec.cwd.as_deref(),
+            &env,
+            None,
+            |stream, chunk| job.output(stream, chunk),
+        )
+        .await;
+        job.finished(run, outcome);
+    });
+}
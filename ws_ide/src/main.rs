@@ -1,20 +1,26 @@
 use axum::{
     http::{
         header::{AUTHORIZATION, CONTENT_TYPE},
-        Method,
+        HeaderValue, Method,
     },
-    routing::get,
+    middleware,
+    routing::{get, post},
 };
 use socketioxide::SocketIo;
 use std::env;
-use tower_http::cors::{Any, CorsLayer};
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
 
 mod db;
 mod docker_vm;
 mod entities;
 mod events;
+mod files;
+mod http_handler;
+mod jobs;
 mod socket_handler;
 mod state;
+mod tasks;
+mod terminal_session;
 mod types;
 
 use state::AppState;
@@ -23,19 +29,44 @@ use state::AppState;
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let port = env::var("PORT").unwrap_or_else(|_| "8084".to_string());
 
+    // ALLOWED_ORIGIN is a comma-separated list; any origin when it's unset.
+    let origins: Vec<HeaderValue> = env::var("ALLOWED_ORIGIN")
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|origin| HeaderValue::from_str(origin.trim()).ok())
+        .filter(|origin| !origin.is_empty())
+        .collect();
+    let allow_origin = if origins.is_empty() {
+        AllowOrigin::from(Any)
+    } else {
+        AllowOrigin::list(origins)
+    };
+
     let cors = CorsLayer::new()
         .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
-        .allow_origin(Any)
+        .allow_origin(allow_origin)
         .allow_headers([AUTHORIZATION, CONTENT_TYPE]);
 
     let db = db::connect_db().await;
     let app_state = AppState::new(db);
 
     let (layer, io) = SocketIo::new_layer();
-    socket_handler::register_handlers(&io, app_state);
+    socket_handler::register_handlers(&io, app_state.clone());
 
     let app = axum::Router::new()
         .route("/health", get(|| async { "OK" }))
+        .merge(
+            axum::Router::new()
+                .route("/recordings/{email}", get(http_handler::recordings::list_recordings))
+                .route(
+                    "/recordings/{email}/{name}",
+                    get(http_handler::recordings::download_recording),
+                )
+                .route("/exec/{email}", post(http_handler::exec::exec_command))
+                .route("/files/{email}/download", get(http_handler::files::download_file))
+                .route_layer(middleware::from_fn(http_handler::auth::require_api_token)),
+        )
+        .with_state(app_state)
         .layer(layer)
         .layer(cors);
 
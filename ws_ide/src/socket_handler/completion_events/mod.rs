@@ -45,7 +45,7 @@ pub async fn handle_code_completion(s: SocketRef, payload: CompletionPayload) {
             .ok();
         }
         Err(e) => {
-            eprintln!("gemini completion error: {e}");
+            eprintln!("gemini completion error for {}: {e}", payload.email);
             s.emit(events::outgoing::COMPLETION_ERROR, &e.to_string()).ok();
         }
     }
@@ -95,8 +95,7 @@ fn head(s: &str, max_chars: usize) -> &str {
     }
     s.char_indices()
         .map(|(i, _)| i)
-        .filter(|&i| i <= max_chars)
-        .last()
+        .rfind(|&i| i <= max_chars)
         .map(|i| &s[..i])
         .unwrap_or(s)
 }
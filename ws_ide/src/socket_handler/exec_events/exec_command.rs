@@ -0,0 +1,41 @@
+use socketioxide::extract::SocketRef;
+
+use crate::{
+    docker_vm::exec_command::run_exec_command,
+    events,
+    state::AppState,
+    types::{ExecCommandPayload, ExecErrorPayload, ExecOutputPayload, ExecResultPayload},
+};
+
+pub async fn handle_exec_command(
+    s: &SocketRef,
+    state: AppState,
+    data: ExecCommandPayload,
+) -> Result<(), std::io::Error> {
+    let request_id = data.request_id.clone();
+
+    let result = run_exec_command(&state, &data.email, &data.request, |stream, chunk| {
+        s.emit(events::outgoing::EXEC_OUTPUT, &ExecOutputPayload {
+            request_id: request_id.clone(),
+            stream,
+            data: chunk.to_string(),
+        })
+        .ok();
+    })
+    .await;
+
+    match result {
+        Ok(result) => {
+            s.emit(events::outgoing::EXEC_RESULT, &ExecResultPayload { request_id, result }).ok();
+            Ok(())
+        }
+        Err(e) => {
+            s.emit(events::outgoing::EXEC_ERROR, &ExecErrorPayload {
+                request_id,
+                message: e.to_string(),
+            })
+            .ok();
+            Err(e)
+        }
+    }
+}
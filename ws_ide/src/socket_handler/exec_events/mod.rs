@@ -0,0 +1,2 @@
+pub mod exec_command;
+pub use exec_command::handle_exec_command;
@@ -0,0 +1,194 @@
+use socketioxide::extract::SocketRef;
+
+use crate::{
+    docker_vm::file_ops::{self, ensure_removable, resolve_in_workspace, resolve_path},
+    events,
+    state::AppState,
+    types::{
+        CreatePathPayload, DeletePathPayload, FileErrorPayload, FileOp, FileOpResultPayload,
+        TransferPathPayload,
+    },
+};
+
+/// Reports a failed file operation on `file_error` and returns it as an error.
+pub fn file_error(s: &SocketRef, op: FileOp, path: &str, msg: String) -> std::io::Error {
+    s.emit(events::outgoing::FILE_ERROR, &FileErrorPayload {
+        op,
+        path: path.to_string(),
+        message: msg.clone(),
+    })
+    .ok();
+    std::io::Error::other(msg)
+}
+
+/// The user's container and workspace root.
+pub fn workspace(
+    s: &SocketRef,
+    state: &AppState,
+    email: &str,
+    op: FileOp,
+    path: &str,
+) -> Result<(String, Option<String>), std::io::Error> {
+    let container_id = state
+        .docker_container_id
+        .get(email)
+        .map(|r| r.clone())
+        .ok_or_else(|| file_error(s, op, path, format!("No Docker container found for email: {}", email)))?;
+    let root = state.workspace_root.get(email).map(|r| r.clone());
+    Ok((container_id, root))
+}
+
+/// The user's container and `path` resolved against their workspace root:
+/// the file an editor read, save or patch of `path` touches.
+pub fn editor_file(state: &AppState, email: &str, path: &str) -> Result<(String, String), String> {
+    let container_id = state
+        .docker_container_id
+        .get(email)
+        .map(|r| r.clone())
+        .ok_or_else(|| format!("No Docker container found for email: {}", email))?;
+    let root = state.workspace_root.get(email).map(|r| r.clone());
+    Ok((container_id, resolve_path(root.as_deref(), path)?))
+}
+
+/// The user's container and the directory changes are confined to: the
+/// cloned repo, or the container's own workdir when nothing was cloned.
+pub fn confined_workspace(
+    s: &SocketRef,
+    state: &AppState,
+    email: &str,
+    op: FileOp,
+    path: &str,
+) -> Result<(String, String), std::io::Error> {
+    let (container_id, _) = workspace(s, state, email, op, path)?;
+    Ok((container_id, state.workspace_dir(email)))
+}
+
+fn op_done(s: &SocketRef, op: FileOp, path: String, new_path: Option<String>) {
+    match &new_path {
+        Some(to) => println!("[files] {:?} {} -> {}", op, path, to),
+        None => println!("[files] {:?} {}", op, path),
+    }
+    s.emit(events::outgoing::FILE_OP_RESULT, &FileOpResultPayload { op, path, new_path }).ok();
+}
+
+async fn create(
+    s: &SocketRef,
+    state: AppState,
+    data: CreatePathPayload,
+    op: FileOp,
+) -> Result<(), std::io::Error> {
+    let (container_id, root) = confined_workspace(s, &state, &data.email, op, &data.path)?;
+    let path = resolve_in_workspace(Some(&root), &data.path).map_err(|e| file_error(s, op, &data.path, e))?;
+    let result = match op {
+        FileOp::CreateDirectory => file_ops::create_directory(&container_id, &path).await,
+        _ => file_ops::create_file(&container_id, &path, data.overwrite).await,
+    };
+    result.map_err(|e| file_error(s, op, &path, e))?;
+    op_done(s, op, path, None);
+    Ok(())
+}
+
+/// Creates an empty file, and any missing parent directories.
+pub async fn handle_create_file(
+    s: &SocketRef,
+    state: AppState,
+    data: CreatePathPayload,
+) -> Result<(), std::io::Error> {
+    create(s, state, data, FileOp::CreateFile).await
+}
+
+pub async fn handle_create_directory(
+    s: &SocketRef,
+    state: AppState,
+    data: CreatePathPayload,
+) -> Result<(), std::io::Error> {
+    create(s, state, data, FileOp::CreateDirectory).await
+}
+
+/// Deletes a file or directory. Deleting a directory with contents needs
+/// both `recursive` and `confirm`, so a stray click can't wipe a tree.
+pub async fn handle_delete_path(
+    s: &SocketRef,
+    state: AppState,
+    data: DeletePathPayload,
+) -> Result<(), std::io::Error> {
+    let op = FileOp::Delete;
+    let (container_id, root) = confined_workspace(s, &state, &data.email, op, &data.path)?;
+    let path = resolve_in_workspace(Some(&root), &data.path).map_err(|e| file_error(s, op, &data.path, e))?;
+    ensure_removable(Some(&root), &path).map_err(|e| file_error(s, op, &path, e))?;
+    if data.recursive && !data.confirm {
+        return Err(file_error(
+            s,
+            op,
+            &path,
+            format!("Deleting {} recursively needs confirmation", path),
+        ));
+    }
+
+    file_ops::delete_path(&container_id, &path, data.recursive)
+        .await
+        .map_err(|e| file_error(s, op, &path, e))?;
+    op_done(s, op, path, None);
+    Ok(())
+}
+
+async fn transfer(
+    s: &SocketRef,
+    state: AppState,
+    data: TransferPathPayload,
+    op: FileOp,
+) -> Result<(), std::io::Error> {
+    let (container_id, root) = confined_workspace(s, &state, &data.email, op, &data.from)?;
+    let from = resolve_in_workspace(Some(&root), &data.from).map_err(|e| file_error(s, op, &data.from, e))?;
+    let to = resolve_in_workspace(Some(&root), &data.to).map_err(|e| file_error(s, op, &from, e))?;
+    if op == FileOp::Rename {
+        ensure_removable(Some(&root), &from).map_err(|e| file_error(s, op, &from, e))?;
+    }
+    if from == to {
+        return Err(file_error(s, op, &from, "Source and destination are the same".to_string()));
+    }
+
+    let result = match op {
+        FileOp::Copy => file_ops::copy_path(&container_id, &from, &to, data.overwrite).await,
+        _ => file_ops::move_path(&container_id, &from, &to, data.overwrite).await,
+    };
+    result.map_err(|e| file_error(s, op, &from, e))?;
+    op_done(s, op, from, Some(to));
+    Ok(())
+}
+
+/// Renames or moves a file or directory.
+pub async fn handle_rename_path(
+    s: &SocketRef,
+    state: AppState,
+    data: TransferPathPayload,
+) -> Result<(), std::io::Error> {
+    transfer(s, state, data, FileOp::Rename).await
+}
+
+pub async fn handle_copy_path(
+    s: &SocketRef,
+    state: AppState,
+    data: TransferPathPayload,
+) -> Result<(), std::io::Error> {
+    transfer(s, state, data, FileOp::Copy).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sea_orm::DatabaseConnection;
+
+    #[test]
+    fn read_save_and_patch_resolve_a_path_alike() {
+        let state = AppState::new(DatabaseConnection::Disconnected);
+        state.docker_container_id.insert("a@x".to_string(), "c1".to_string());
+        state.workspace_root.insert("a@x".to_string(), "/home/a/repo".to_string());
+
+        let expected = ("c1".to_string(), "/home/a/repo/src/main.rs".to_string());
+        assert_eq!(editor_file(&state, "a@x", "src/main.rs"), Ok(expected.clone()));
+        assert_eq!(editor_file(&state, "a@x", "./src/../src/main.rs"), Ok(expected.clone()));
+        assert_eq!(editor_file(&state, "a@x", "/home/a/repo/src/main.rs"), Ok(expected));
+        assert!(editor_file(&state, "b@x", "src/main.rs").is_err());
+    }
+}
@@ -1,12 +1,15 @@
 use socketioxide::extract::SocketRef;
-use std::process::Command;
 
 use crate::{
+    docker_vm::file_ops::read_file,
     events,
+    files::{content::file_data, transfer::MAX_INLINE_BYTES},
     state::AppState,
-    types::FileContentPayload,
+    types::{FileContentPayload, FileOp},
 };
 
+use super::file_ops::{editor_file, file_error};
+
 pub async fn get_file_data(
     s: SocketRef,
     state: AppState,
@@ -15,44 +18,18 @@ pub async fn get_file_data(
     let email = payload.email;
     let file_path = payload.path;
 
-    let container_id = state
-        .docker_container_id
-        .get(&email)
-        .map(|r| r.clone())
-        .ok_or_else(|| {
-            let msg = format!("No Docker container found for email: {}", email);
-            s.emit(events::outgoing::FILE_ERROR, &msg).ok();
-            std::io::Error::new(std::io::ErrorKind::NotFound, msg)
-        })?;
+    // Resolved like a save or patch, so a relative path reads the file they
+    // would write; the reply carries the resolved path.
+    let (container_id, path) =
+        editor_file(&state, &email, &file_path).map_err(|e| file_error(&s, FileOp::Read, &file_path, e))?;
+
+    let (data, version) = read_file(&container_id, &path, MAX_INLINE_BYTES)
+        .await
+        .and_then(|file| file.ok_or_else(|| format!("{} does not exist", path)))
+        .map_err(|e| file_error(&s, FileOp::Read, &file_path, format!("Failed to read file '{}': {}", path, e)))?;
 
-    match Command::new("docker")
-        .arg("exec")
-        .arg(&container_id)
-        .arg("cat")
-        .arg(&file_path)
-        .output()
-    {
-        Ok(result) if result.status.success() => {
-            let content = String::from_utf8_lossy(&result.stdout).to_string();
-            s.emit(events::outgoing::FILES_DATA, &content).map_err(|e| {
-                std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to emit: {}", e))
-            })?;
-        }
-        Ok(result) => {
-            let msg = format!(
-                "Failed to read file '{}': {}",
-                file_path,
-                String::from_utf8_lossy(&result.stderr)
-            );
-            s.emit(events::outgoing::FILE_ERROR, &msg).ok();
-            return Err(std::io::Error::new(std::io::ErrorKind::Other, msg));
-        }
-        Err(e) => {
-            let msg = format!("Failed to execute docker command: {}", e);
-            s.emit(events::outgoing::FILE_ERROR, &msg).ok();
-            return Err(std::io::Error::new(std::io::ErrorKind::Other, msg));
-        }
-    }
+    s.emit(events::outgoing::FILES_DATA, &file_data(path, data, version))
+        .map_err(|e| std::io::Error::other(format!("Failed to emit: {}", e)))?;
 
     Ok(())
 }
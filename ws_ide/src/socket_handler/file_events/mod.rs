@@ -1,5 +1,19 @@
+pub mod file_ops;
 pub mod get_file_data;
+pub mod patch_file_data;
 pub mod save_file_data;
+pub mod transfer;
+pub mod watch;
 
+pub use file_ops::{
+    handle_copy_path, handle_create_directory, handle_create_file, handle_delete_path,
+    handle_rename_path,
+};
 pub use get_file_data::get_file_data;
+pub use patch_file_data::patch_file_data;
 pub use save_file_data::save_file_data;
+pub use transfer::{
+    handle_cancel_download, handle_cancel_upload, handle_download_ack, handle_download_file,
+    handle_start_upload, handle_upload_chunk,
+};
+pub use watch::{handle_unwatch_directory, handle_watch_directory};
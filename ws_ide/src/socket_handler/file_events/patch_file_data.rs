@@ -0,0 +1,81 @@
+use socketioxide::extract::SocketRef;
+
+use crate::{
+    docker_vm::file_ops::{content_hash, read_file, write_file, WriteOutcome},
+    events,
+    files::{content::is_binary, patch::apply_edits, transfer::MAX_INLINE_BYTES},
+    state::AppState,
+    types::{FileOp, FileSavedPayload, PatchFilePayload, PatchRejectedPayload},
+};
+
+use super::file_ops::{editor_file, file_error};
+
+fn reject(s: &SocketRef, path: String, base_version: String, reason: String) {
+    println!("[files] patch of {} rejected: {}", path, reason);
+    s.emit(events::outgoing::PATCH_REJECTED, &PatchRejectedPayload {
+        path,
+        base_version,
+        reason,
+    })
+    .ok();
+}
+
+/// Applies text edits made against `base_version` and writes the result
+/// atomically, so autosave doesn't resend the whole file. When the file has
+/// moved on from that version, or the edits don't fit it, `patch_rejected`
+/// asks the client for the full content instead.
+pub async fn patch_file_data(
+    s: SocketRef,
+    state: AppState,
+    payload: PatchFilePayload,
+) -> Result<(), std::io::Error> {
+    let email = payload.email;
+    let file_path = payload.path;
+    let base = payload.base_version;
+
+    let (container_id, path) =
+        editor_file(&state, &email, &file_path).map_err(|e| file_error(&s, FileOp::Patch, &file_path, e))?;
+
+    let current = read_file(&container_id, &path, MAX_INLINE_BYTES)
+        .await
+        .map_err(|e| file_error(&s, FileOp::Patch, &file_path, format!("Failed to read file '{}': {}", file_path, e)))?;
+    let Some((data, version)) = current else {
+        reject(&s, file_path, base, "the file no longer exists".to_string());
+        return Ok(());
+    };
+    if content_hash(&version) != content_hash(&base) {
+        reject(&s, file_path, base, format!("the file is at version {}", version));
+        return Ok(());
+    }
+    if is_binary(&data) {
+        reject(&s, file_path, base, "the file is not text".to_string());
+        return Ok(());
+    }
+    let edits = payload.edits;
+    let patched = tokio::task::spawn_blocking(move || apply_edits(&String::from_utf8_lossy(&data), &edits))
+        .await
+        .map_err(std::io::Error::other)?;
+    let patched = match patched {
+        Ok(patched) => patched,
+        Err(e) => {
+            reject(&s, file_path, base, e);
+            return Ok(());
+        }
+    };
+
+    let outcome = write_file(&container_id, &path, patched.as_bytes(), Some(&base))
+        .await
+        .map_err(|e| file_error(&s, FileOp::Patch, &file_path, format!("Failed to save file '{}': {}", file_path, e)))?;
+    match outcome {
+        WriteOutcome::Written(version) => {
+            s.emit(events::outgoing::FILE_SAVED, &FileSavedPayload {
+                path: file_path,
+                version,
+            })
+            .map_err(|e| std::io::Error::other(format!("Failed to emit: {}", e)))?;
+        }
+        WriteOutcome::Conflict => reject(&s, file_path, base, "the file changed while it was patched".to_string()),
+    }
+
+    Ok(())
+}
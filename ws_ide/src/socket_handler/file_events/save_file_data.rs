@@ -1,12 +1,19 @@
+use bytes::Bytes;
 use socketioxide::extract::SocketRef;
-use std::process::Command;
 
 use crate::{
+    docker_vm::file_ops::{read_file, write_file, WriteOutcome},
     events,
+    files::{content::file_data, transfer::MAX_INLINE_BYTES},
     state::AppState,
-    types::SaveFileContentPayload,
+    types::{FileConflictPayload, FileOp, FileSavedPayload, SaveFileContentPayload},
 };
 
+use super::file_ops::{editor_file, file_error};
+
+/// Writes the file atomically. With `expected_version` (and no `force`) the
+/// save only goes through while the file is still at that version; otherwise
+/// `file_conflict` carries what is on disk now.
 pub async fn save_file_data(
     s: SocketRef,
     state: AppState,
@@ -14,96 +21,39 @@ pub async fn save_file_data(
 ) -> Result<(), std::io::Error> {
     let email = payload.email;
     let file_path = payload.path;
-    let content = payload.content;
-
-    let container_id = state
-        .docker_container_id
-        .get(&email)
-        .map(|r| r.clone())
-        .ok_or_else(|| {
-            let msg = format!("No Docker container found for email: {}", email);
-            s.emit(events::outgoing::FILE_ERROR, &msg).ok();
-            std::io::Error::new(std::io::ErrorKind::NotFound, msg)
-        })?;
-
-    if let Some(parent_dir) = std::path::Path::new(&file_path).parent() {
-        let _ = Command::new("docker")
-            .arg("exec")
-            .arg(&container_id)
-            .arg("mkdir")
-            .arg("-p")
-            .arg(parent_dir.to_string_lossy().as_ref())
-            .output();
-    }
-
-    let temp_path = format!("{}.tmp", file_path);
-
-    let mut child = Command::new("docker")
-        .arg("exec")
-        .arg("-i")
-        .arg(&container_id)
-        .arg("tee")
-        .arg(&temp_path)
-        .stdin(std::process::Stdio::piped())
-        .stdout(std::process::Stdio::piped())
-        .stderr(std::process::Stdio::piped())
-        .spawn()
-        .map_err(|e| {
-            std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("Failed to spawn docker: {}", e),
-            )
-        })?;
-
-    if let Some(mut stdin) = child.stdin.take() {
-        use std::io::Write;
-        stdin.write_all(content.as_bytes())?;
-    }
-
-    let result = child.wait_with_output()?;
-    if !result.status.success() {
-        let msg = format!(
-            "Failed to write temp file '{}': {}",
-            temp_path,
-            String::from_utf8_lossy(&result.stderr)
-        );
-        s.emit(events::outgoing::FILE_ERROR, &msg).ok();
-        return Err(std::io::Error::new(std::io::ErrorKind::Other, msg));
-    }
-
-    match Command::new("docker")
-        .arg("exec")
-        .arg(&container_id)
-        .arg("mv")
-        .arg(&temp_path)
-        .arg(&file_path)
-        .output()
-    {
-        Ok(result) if result.status.success() => {
-            s.emit(
-                events::outgoing::FILE_SAVED,
-                &format!("File '{}' saved successfully", file_path),
-            )
-            .map_err(|e| {
-                std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    format!("Failed to emit: {}", e),
-                )
-            })?;
-        }
-        Ok(result) => {
-            let msg = format!(
-                "Failed to move temp file to '{}': {}",
-                file_path,
-                String::from_utf8_lossy(&result.stderr)
-            );
-            s.emit(events::outgoing::FILE_ERROR, &msg).ok();
-            return Err(std::io::Error::new(std::io::ErrorKind::Other, msg));
+    let content = match payload.data {
+        Some(data) => data,
+        None => Bytes::from(payload.content),
+    };
+
+    let (container_id, path) =
+        editor_file(&state, &email, &file_path).map_err(|e| file_error(&s, FileOp::Save, &file_path, e))?;
+
+    let expected = payload.expected_version.filter(|_| !payload.force);
+    let outcome = write_file(&container_id, &path, &content, expected.as_deref())
+        .await
+        .map_err(|e| file_error(&s, FileOp::Save, &file_path, format!("Failed to save file '{}': {}", file_path, e)))?;
+
+    match outcome {
+        WriteOutcome::Written(version) => {
+            s.emit(events::outgoing::FILE_SAVED, &FileSavedPayload {
+                path: file_path,
+                version,
+            })
+            .map_err(|e| std::io::Error::other(format!("Failed to emit: {}", e)))?;
         }
-        Err(e) => {
-            let msg = format!("Failed to execute mv command: {}", e);
-            s.emit(events::outgoing::FILE_ERROR, &msg).ok();
-            return Err(std::io::Error::new(std::io::ErrorKind::Other, msg));
+        WriteOutcome::Conflict => {
+            let current = read_file(&container_id, &path, MAX_INLINE_BYTES)
+                .await
+                .map_err(|e| file_error(&s, FileOp::Save, &file_path, e))?
+                .map(|(data, version)| file_data(file_path.clone(), data, version));
+            println!("[files] save of {} for {} refused: changed on disk", file_path, email);
+            s.emit(events::outgoing::FILE_CONFLICT, &FileConflictPayload {
+                path: file_path,
+                expected_version: expected.unwrap_or_default(),
+                current,
+            })
+            .ok();
         }
     }
 
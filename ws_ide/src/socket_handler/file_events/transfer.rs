@@ -0,0 +1,131 @@
+use socketioxide::extract::SocketRef;
+
+use crate::{
+    docker_vm::file_ops::resolve_in_workspace,
+    events,
+    state::AppState,
+    types::{
+        DownloadAckPayload, DownloadFilePayload, DownloadPayload, FileOp, StartUploadPayload,
+        UploadChunkPayload, UploadPayload,
+    },
+};
+
+use super::file_ops::{confined_workspace, file_error};
+
+/// Starts an upload, or reports where an existing one left off so the
+/// client can resume it.
+pub async fn handle_start_upload(
+    s: &SocketRef,
+    state: AppState,
+    data: StartUploadPayload,
+) -> Result<(), std::io::Error> {
+    let op = FileOp::Upload;
+    if let Some(id) = &data.upload_id {
+        let upload = state
+            .transfers
+            .upload(&data.email, id)
+            .ok_or_else(|| file_error(s, op, &data.path, format!("No upload with id {}; start it again", id)))?;
+        let offset = upload.offset().await;
+        s.emit(events::outgoing::UPLOAD_STATUS, &upload.status(offset, false)).ok();
+        return Ok(());
+    }
+
+    let (container_id, root) = confined_workspace(s, &state, &data.email, op, &data.path)?;
+    let path = resolve_in_workspace(Some(&root), &data.path).map_err(|e| file_error(s, op, &data.path, e))?;
+    let upload = state
+        .transfers
+        .start_upload(&data.email, &container_id, &path, data.size, data.checksum)
+        .await
+        .map_err(|e| file_error(s, op, &path, e))?;
+    if upload.size == 0 {
+        state
+            .transfers
+            .finish_upload(&upload)
+            .await
+            .map_err(|e| file_error(s, op, &path, e))?;
+        s.emit(events::outgoing::UPLOAD_STATUS, &upload.status(0, true)).ok();
+        return Ok(());
+    }
+    s.emit(events::outgoing::UPLOAD_STATUS, &upload.status(0, false)).ok();
+    Ok(())
+}
+
+/// Writes one chunk and acks the offset to continue from. The last chunk
+/// moves the file into place.
+pub async fn handle_upload_chunk(
+    s: &SocketRef,
+    state: AppState,
+    data: UploadChunkPayload,
+) -> Result<(), std::io::Error> {
+    let op = FileOp::Upload;
+    let upload = state
+        .transfers
+        .upload(&data.email, &data.upload_id)
+        .ok_or_else(|| file_error(s, op, "", format!("No upload with id {}; start it again", data.upload_id)))?;
+
+    let offset = upload
+        .write_chunk(data.offset, &data.data, data.checksum)
+        .await
+        .map_err(|e| file_error(s, op, &upload.path, e))?;
+    let complete = offset == upload.size;
+    if complete {
+        state
+            .transfers
+            .finish_upload(&upload)
+            .await
+            .map_err(|e| file_error(s, op, &upload.path, e))?;
+    }
+    s.emit(events::outgoing::UPLOAD_STATUS, &upload.status(offset, complete)).ok();
+    Ok(())
+}
+
+pub async fn handle_cancel_upload(
+    s: &SocketRef,
+    state: AppState,
+    data: UploadPayload,
+) -> Result<(), std::io::Error> {
+    let op = FileOp::Upload;
+    let upload = state
+        .transfers
+        .upload(&data.email, &data.upload_id)
+        .ok_or_else(|| file_error(s, op, "", format!("No upload with id {}", data.upload_id)))?;
+    state
+        .transfers
+        .cancel_upload(&upload)
+        .await
+        .map_err(|e| file_error(s, op, &upload.path, e))
+}
+
+/// Streams a file to the client in acked chunks, from `offset` onwards.
+pub async fn handle_download_file(
+    s: &SocketRef,
+    state: AppState,
+    data: DownloadFilePayload,
+) -> Result<(), std::io::Error> {
+    let op = FileOp::Download;
+    let (container_id, root) = confined_workspace(s, &state, &data.email, op, &data.path)?;
+    let path = resolve_in_workspace(Some(&root), &data.path).map_err(|e| file_error(s, op, &data.path, e))?;
+    state
+        .transfers
+        .start_download(s, &data.email, &container_id, &path, data.offset, data.download_id)
+        .await
+        .map_err(|e| file_error(s, op, &path, e))
+}
+
+pub async fn handle_download_ack(
+    _s: &SocketRef,
+    state: AppState,
+    data: DownloadAckPayload,
+) -> Result<(), std::io::Error> {
+    state.transfers.ack_download(&data.email, &data.download_id, data.offset);
+    Ok(())
+}
+
+pub async fn handle_cancel_download(
+    _s: &SocketRef,
+    state: AppState,
+    data: DownloadPayload,
+) -> Result<(), std::io::Error> {
+    state.transfers.cancel_download(&data.email, &data.download_id);
+    Ok(())
+}
@@ -0,0 +1,35 @@
+use socketioxide::extract::SocketRef;
+
+use crate::{
+    docker_vm::file_ops::resolve_path,
+    state::AppState,
+    types::{FileOp, WatchDirectoryPayload},
+};
+
+use super::file_ops::{file_error, workspace};
+
+/// Pushes `fs_changed` for a directory the client has expanded.
+pub async fn handle_watch_directory(
+    s: &SocketRef,
+    state: AppState,
+    data: WatchDirectoryPayload,
+) -> Result<(), std::io::Error> {
+    let op = FileOp::Watch;
+    let (container_id, root) = workspace(s, &state, &data.email, op, &data.path)?;
+    let path = resolve_path(root.as_deref(), &data.path).map_err(|e| file_error(s, op, &data.path, e))?;
+    state
+        .watchers
+        .watch(s, &data.email, &container_id, root.as_deref(), &path)
+        .map_err(|e| file_error(s, op, &path, e))
+}
+
+pub async fn handle_unwatch_directory(
+    s: &SocketRef,
+    state: AppState,
+    data: WatchDirectoryPayload,
+) -> Result<(), std::io::Error> {
+    let root = state.workspace_root.get(&data.email).map(|r| r.clone());
+    let path = resolve_path(root.as_deref(), &data.path).map_err(|e| file_error(s, FileOp::Watch, &data.path, e))?;
+    state.watchers.unwatch(s, &data.email, &path);
+    Ok(())
+}
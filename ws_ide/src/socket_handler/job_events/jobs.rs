@@ -0,0 +1,133 @@
+use socketioxide::extract::SocketRef;
+use std::sync::Arc;
+
+use crate::{
+    events,
+    jobs::{Job, JobSpec},
+    state::AppState,
+    types::{
+        EmailPayload, JobErrorPayload, JobPayload, JobsPayload, StartJobPayload, TailJobPayload,
+    },
+};
+
+fn job_error(s: &SocketRef, job_id: Option<&str>, msg: String) -> std::io::Error {
+    s.emit(events::outgoing::JOB_ERROR, &JobErrorPayload {
+        job_id: job_id.map(str::to_string),
+        message: msg.clone(),
+    })
+    .ok();
+    std::io::Error::other(msg)
+}
+
+fn find_job(s: &SocketRef, state: &AppState, email: &str, job_id: &str) -> Result<Arc<Job>, std::io::Error> {
+    state
+        .jobs
+        .get(email, job_id)
+        .ok_or_else(|| job_error(s, Some(job_id), format!("No job with id {}", job_id)))
+}
+
+/// Starts a detached command in the user's container. The caller follows
+/// its output from the start.
+pub async fn handle_start_job(
+    s: &SocketRef,
+    state: AppState,
+    data: StartJobPayload,
+) -> Result<(), std::io::Error> {
+    let container_id = state
+        .docker_container_id
+        .get(&data.email)
+        .map(|r| r.clone())
+        .ok_or_else(|| job_error(s, None, format!("No Docker container found for email: {}", data.email)))?;
+
+    let cwd = data
+        .cwd
+        .clone()
+        .or_else(|| state.workspace_root.get(&data.email).map(|r| r.clone()));
+    let spec = JobSpec {
+        name: data.name.clone().unwrap_or_else(|| data.command.clone()),
+        command: data.command.clone(),
+        cwd,
+        env: data.env.clone(),
+    };
+
+    let job = state
+        .jobs
+        .start(&data.email, &container_id, spec)
+        .map_err(|e| job_error(s, None, e))?;
+    job.watch(s, true);
+    s.emit(events::outgoing::JOB_STATUS, &job.info()).ok();
+    Ok(())
+}
+
+/// Lists the user's jobs and subscribes the socket to their status changes.
+pub async fn handle_list_jobs(
+    s: &SocketRef,
+    state: AppState,
+    data: EmailPayload,
+) -> Result<(), std::io::Error> {
+    let jobs = state.jobs.list(&data.email);
+    for job in &jobs {
+        job.watch(s, false);
+    }
+    s.emit(events::outgoing::JOBS, &JobsPayload {
+        jobs: jobs.iter().map(|job| job.info()).collect(),
+    })
+    .ok();
+    Ok(())
+}
+
+pub async fn handle_tail_job(
+    s: &SocketRef,
+    state: AppState,
+    data: TailJobPayload,
+) -> Result<(), std::io::Error> {
+    let job = find_job(s, &state, &data.email, &data.job_id)?;
+    job.tail(s, data.follow);
+    Ok(())
+}
+
+pub async fn handle_stop_job(
+    s: &SocketRef,
+    state: AppState,
+    data: JobPayload,
+) -> Result<(), std::io::Error> {
+    let job = find_job(s, &state, &data.email, &data.job_id)?;
+    job.watch(s, false);
+    state
+        .jobs
+        .stop(&job)
+        .await
+        .map_err(|e| job_error(s, Some(&data.job_id), format!("Failed to stop {}: {}", job.spec.name, e)))
+}
+
+pub async fn handle_restart_job(
+    s: &SocketRef,
+    state: AppState,
+    data: JobPayload,
+) -> Result<(), std::io::Error> {
+    let job = find_job(s, &state, &data.email, &data.job_id)?;
+    job.watch(s, false);
+    state
+        .jobs
+        .restart(&job)
+        .await
+        .map_err(|e| job_error(s, Some(&data.job_id), format!("Failed to restart {}: {}", job.spec.name, e)))
+}
+
+/// Forgets a finished job and its log.
+pub async fn handle_remove_job(
+    s: &SocketRef,
+    state: AppState,
+    data: JobPayload,
+) -> Result<(), std::io::Error> {
+    let job = find_job(s, &state, &data.email, &data.job_id)?;
+    state
+        .jobs
+        .remove(&job)
+        .map_err(|e| job_error(s, Some(&data.job_id), e))?;
+    s.emit(events::outgoing::JOBS, &JobsPayload {
+        jobs: state.jobs.list(&data.email).iter().map(|job| job.info()).collect(),
+    })
+    .ok();
+    Ok(())
+}
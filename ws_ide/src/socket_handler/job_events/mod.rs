@@ -0,0 +1,5 @@
+pub mod jobs;
+pub use jobs::{
+    handle_list_jobs, handle_remove_job, handle_restart_job, handle_start_job, handle_stop_job,
+    handle_tail_job,
+};
@@ -1,101 +1,59 @@
-use bollard::container::StartContainerOptions;
-use bollard::Docker;
-use sea_orm::{ColumnTrait, EntityTrait, Order, QueryFilter, QueryOrder};
-use socketioxide::{extract::SocketRef, socket::Sid};
+use socketioxide::extract::SocketRef;
 
 use crate::{
-    docker_vm::create_container::create_container,
-    entities::{users, workspace_containers},
+    docker_vm::{
+        git_clone::{clone_into_workspace, GitSource},
+        lifecycle_hooks::{run_hooks, HookStage},
+        provision::{ensure_workspace_container, wait_for_setup_hooks, ProgressSubscribers},
+    },
     events,
     socket_handler::pseudo_terminal::pseudo_terminal,
     state::AppState,
-    types::TerminalStatusPayload,
+    terminal_session::options::resolve_terminal_options,
+    types::{LoadTerminalPayload, TerminalStatusPayload, WorkspaceReadyPayload},
 };
 
-pub async fn load_terminal(
-    s: &SocketRef,
-    id: Sid,
-    state: AppState,
-    email: String,
-    terminal_id: String,
-) {
+pub async fn load_terminal(s: &SocketRef, state: AppState, payload: LoadTerminalPayload) {
+    let email = payload.email.clone();
+    let terminal_id = payload.terminal_id.clone();
+    let git = payload.git_url.clone().map(|url| GitSource {
+        url,
+        branch: payload.git_branch.clone(),
+    });
+
     s.emit(events::outgoing::TERMINAL_LOADING, &TerminalStatusPayload {
         terminal_id: terminal_id.clone(),
         message: "Connecting to your development environment".to_string(),
     })
     .ok();
 
-    let user = match users::Entity::find()
-        .filter(users::Column::Email.eq(email.clone()))
-        .one(&*state.db)
-        .await
-    {
-        Ok(Some(u)) => u,
-        Ok(None) => {
-            eprintln!("[terminal] No user found for email={}", email);
-            s.emit(events::outgoing::TERMINAL_ERROR, &TerminalStatusPayload {
-                terminal_id: terminal_id.clone(),
-                message: format!("No user found with email: {}", email),
-            })
-            .ok();
-            return;
-        }
-        Err(e) => {
-            eprintln!("[terminal] DB error: {}", e);
-            s.emit(events::outgoing::TERMINAL_ERROR, &TerminalStatusPayload {
-                terminal_id: terminal_id.clone(),
-                message: format!("Database error: {}", e),
-            })
-            .ok();
-            return;
-        }
-    };
+    let workspace =
+        ensure_workspace_container(s, state.clone(), email.clone(), terminal_id.clone()).await;
 
-    let container_row = match workspace_containers::Entity::find()
-        .filter(workspace_containers::Column::UserId.eq(user.id))
-        .filter(workspace_containers::Column::DeletedAt.is_null())
-        .order_by(workspace_containers::Column::CreatedAt, Order::Desc)
-        .one(&*state.db)
-        .await
-    {
-        Ok(row) => row,
-        Err(e) => {
-            eprintln!("[terminal] DB error querying workspace_containers: {}", e);
-            s.emit(events::outgoing::TERMINAL_ERROR, &TerminalStatusPayload {
+    if let Some(workspace) = workspace {
+        let cid = workspace.container_id;
+        let progress = ProgressSubscribers::for_socket(s, &terminal_id);
+        let mut root_dir = workspace.root_dir;
+
+        if let Some(ref source) = git {
+            s.emit(events::outgoing::TERMINAL_INFO, &TerminalStatusPayload {
                 terminal_id: terminal_id.clone(),
-                message: format!("Database error: {}", e),
+                message: format!("Cloning {}", source.url),
             })
             .ok();
-            return;
-        }
-    };
-
-    let docker_container_id: Option<String> = match container_row {
-        Some(row) => {
-            let docker = match Docker::connect_with_socket_defaults() {
-                Ok(client) => client,
+            match clone_into_workspace(&state, &cid, source, &progress).await {
+                Ok(dir) => root_dir = Some(dir),
                 Err(e) => {
+                    eprintln!("[terminal] clone failed for {}: {}", email, e);
                     s.emit(events::outgoing::TERMINAL_ERROR, &TerminalStatusPayload {
                         terminal_id: terminal_id.clone(),
-                        message: format!("Failed to connect to Docker: {}", e),
+                        message: format!("Failed to clone repository: {}", e),
                     })
                     .ok();
-                    return;
                 }
-            };
-
-            match docker
-                .start_container(&row.container_id, None::<StartContainerOptions<String>>)
-                .await
-            {
-                Ok(_) => Some(row.container_id),
-                Err(_) => create_container(s, id, state.clone(), email.clone(), terminal_id.clone()).await,
             }
         }
-        None => create_container(s, id, state.clone(), email.clone(), terminal_id.clone()).await,
-    };
 
-    if let Some(ref cid) = docker_container_id {
         s.emit(events::outgoing::TERMINAL_INFO, &TerminalStatusPayload {
             terminal_id: terminal_id.clone(),
             message: format!("Container {} ready. Starting terminal session", cid),
@@ -103,14 +61,46 @@ pub async fn load_terminal(
         .ok();
 
         state.docker_container_id.insert(email.clone(), cid.clone());
+        if let Some(ref root) = root_dir {
+            state.workspace_root.insert(email.clone(), root.clone());
+        }
+        s.emit(events::outgoing::WORKSPACE_READY, &WorkspaceReadyPayload {
+            terminal_id: terminal_id.clone(),
+            root: root_dir.clone().unwrap_or_else(|| "/".to_string()),
+        })
+        .ok();
 
-        if let Err(e) = pseudo_terminal(s, docker_container_id, state, email, terminal_id.clone()).await {
-            eprintln!("[terminal] pseudo_terminal error: {}", e);
-            s.emit(events::outgoing::TERMINAL_ERROR, &TerminalStatusPayload {
-                terminal_id: terminal_id.clone(),
-                message: format!("Failed to start terminal: {}", e),
-            })
-            .ok();
+        let options = match resolve_terminal_options(&state, &cid, &payload, root_dir.clone()).await {
+            Ok(options) => options,
+            Err(e) => {
+                s.emit(events::outgoing::TERMINAL_ERROR, &TerminalStatusPayload {
+                    terminal_id: terminal_id.clone(),
+                    message: e,
+                })
+                .ok();
+                return;
+            }
+        };
+        let workdir = options.workdir.clone();
+
+        match pseudo_terminal(s, Some(cid.clone()), state.clone(), email.clone(), terminal_id.clone(), options)
+            .await
+        {
+            Ok(()) => {
+                tokio::spawn(async move {
+                    wait_for_setup_hooks(&state, &email).await;
+                    run_hooks(&state, &cid, &email, workdir.as_deref(), HookStage::OnAttach, &progress)
+                        .await;
+                });
+            }
+            Err(e) => {
+                eprintln!("[terminal] pseudo_terminal error: {}", e);
+                s.emit(events::outgoing::TERMINAL_ERROR, &TerminalStatusPayload {
+                    terminal_id: terminal_id.clone(),
+                    message: format!("Failed to start terminal: {}", e),
+                })
+                .ok();
+            }
         }
     } else {
         s.emit(events::outgoing::TERMINAL_ERROR, &TerminalStatusPayload {
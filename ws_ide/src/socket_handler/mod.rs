@@ -1,8 +1,11 @@
 pub mod completion_events;
+pub mod exec_events;
 pub mod file_events;
+pub mod job_events;
 pub mod load_terminal;
 pub mod pseudo_terminal;
 pub mod repo_events;
+pub mod task_events;
 pub mod terminal_events;
 
 use socketioxide::{
@@ -13,18 +16,43 @@ use socketioxide::{
 use crate::{
     events,
     state::AppState,
+    terminal_session::detach_socket,
     types::{
-        CloseTerminalPayload, CompletionPayload, FileContentPayload, LoadTerminalPayload,
-        RepoTreePayload, SaveFileContentPayload, TerminalInputPayload, TerminalResizePayload,
+        AttachTerminalPayload, CloseTerminalPayload, CompletionPayload, CreatePathPayload,
+        DeletePathPayload, DownloadAckPayload, DownloadFilePayload, DownloadPayload, EmailPayload,
+        ExecCommandPayload, FileContentPayload, GetTerminalStatsPayload, JobPayload,
+        JoinTerminalPayload, LoadTerminalPayload, PatchFilePayload, RecordingPayload,
+        RepoTreePayload, RevokeTerminalAccessPayload, RunTaskPayload, SaveFileContentPayload,
+        SetTerminalDefaultsPayload, ShareTerminalPayload, StartJobPayload, StartUploadPayload,
+        TailJobPayload, TerminalAckPayload, TerminalInputPayload, TerminalResizePayload,
+        TerminalSignalPayload, TransferPathPayload, UploadChunkPayload, UploadPayload,
+        WatchDirectoryPayload,
     },
 };
 
 use self::{
     completion_events::handle_code_completion,
-    file_events::{get_file_data, save_file_data},
+    exec_events::handle_exec_command,
+    file_events::{
+        get_file_data, handle_cancel_download, handle_cancel_upload, handle_copy_path,
+        handle_create_directory, handle_create_file, handle_delete_path, handle_download_ack,
+        handle_download_file, handle_rename_path, handle_start_upload, handle_unwatch_directory,
+        handle_upload_chunk, handle_watch_directory, patch_file_data, save_file_data,
+    },
+    job_events::{
+        handle_list_jobs, handle_remove_job, handle_restart_job, handle_start_job, handle_stop_job,
+        handle_tail_job,
+    },
     load_terminal::load_terminal,
     repo_events::get_repo_structure,
-    terminal_events::{handle_close_terminal, handle_terminal_input, handle_terminal_resize},
+    task_events::{handle_list_tasks, handle_run_task},
+    terminal_events::{
+        handle_attach_terminal, handle_close_terminal, handle_get_terminal_defaults,
+        handle_join_terminal, handle_list_shells, handle_revoke_terminal_access,
+        handle_set_terminal_defaults, handle_share_terminal, handle_start_recording,
+        handle_stop_recording, handle_terminal_ack, handle_terminal_input,
+        handle_terminal_resize, handle_terminal_signal, handle_terminal_stats,
+    },
 };
 
 pub fn register_handlers(io: &SocketIo, state: AppState) {
@@ -44,11 +72,10 @@ pub fn register_handlers(io: &SocketIo, state: AppState) {
             let st = st.clone();
             move |s: SocketRef, Data::<LoadTerminalPayload>(p): Data<LoadTerminalPayload>| {
                 let st = st.clone();
-                let socket_id = s.id;
                 st.socket_mapping.insert(s.id, p.email.clone());
                 st.email_mapping.insert(p.email.clone(), s.id);
                 Box::pin(async move {
-                    load_terminal(&s, socket_id, st, p.email, p.terminal_id).await;
+                    load_terminal(&s, st, p).await;
                 })
             }
         });
@@ -66,6 +93,19 @@ pub fn register_handlers(io: &SocketIo, state: AppState) {
             }
         });
 
+        let st = state.clone();
+        s.on(events::incoming::TERMINAL_SIGNAL, {
+            let st = st.clone();
+            move |s: SocketRef, Data::<TerminalSignalPayload>(p): Data<TerminalSignalPayload>| {
+                let st = st.clone();
+                Box::pin(async move {
+                    if let Err(e) = handle_terminal_signal(&s, st, p).await {
+                        eprintln!("terminal_signal: {}", e);
+                    }
+                })
+            }
+        });
+
         let st = state.clone();
         s.on(events::incoming::TERMINAL_RESIZE, {
             let st = st.clone();
@@ -79,13 +119,39 @@ pub fn register_handlers(io: &SocketIo, state: AppState) {
             }
         });
 
+        let st = state.clone();
+        s.on(events::incoming::TERMINAL_ACK, {
+            let st = st.clone();
+            move |s: SocketRef, Data::<TerminalAckPayload>(p): Data<TerminalAckPayload>| {
+                let st = st.clone();
+                Box::pin(async move {
+                    if let Err(e) = handle_terminal_ack(&s, st, p).await {
+                        eprintln!("terminal_ack: {}", e);
+                    }
+                })
+            }
+        });
+
+        let st = state.clone();
+        s.on(events::incoming::GET_TERMINAL_STATS, {
+            let st = st.clone();
+            move |s: SocketRef, Data::<GetTerminalStatsPayload>(p): Data<GetTerminalStatsPayload>| {
+                let st = st.clone();
+                Box::pin(async move {
+                    if let Err(e) = handle_terminal_stats(&s, st, p).await {
+                        eprintln!("get_terminal_stats: {}", e);
+                    }
+                })
+            }
+        });
+
         let st = state.clone();
         s.on(events::incoming::REPO_TREE, {
             let st = st.clone();
             move |s: SocketRef, Data::<RepoTreePayload>(p): Data<RepoTreePayload>| {
                 let st = st.clone();
                 Box::pin(async move {
-                    if let Err(e) = get_repo_structure(&s, st, p.email, p.path).await {
+                    if let Err(e) = get_repo_structure(&s, st, p).await {
                         eprintln!("repo_tree: {}", e);
                     }
                 })
@@ -118,6 +184,188 @@ pub fn register_handlers(io: &SocketIo, state: AppState) {
             }
         });
 
+        let st = state.clone();
+        s.on(events::incoming::PATCH_FILE, {
+            let st = st.clone();
+            move |s: SocketRef, Data::<PatchFilePayload>(p): Data<PatchFilePayload>| {
+                let st = st.clone();
+                Box::pin(async move {
+                    if let Err(e) = patch_file_data(s, st, p).await {
+                        eprintln!("patch_file: {}", e);
+                    }
+                })
+            }
+        });
+
+        let st = state.clone();
+        s.on(events::incoming::CREATE_FILE, {
+            let st = st.clone();
+            move |s: SocketRef, Data::<CreatePathPayload>(p): Data<CreatePathPayload>| {
+                let st = st.clone();
+                Box::pin(async move {
+                    if let Err(e) = handle_create_file(&s, st, p).await {
+                        eprintln!("create_file: {}", e);
+                    }
+                })
+            }
+        });
+
+        let st = state.clone();
+        s.on(events::incoming::CREATE_DIRECTORY, {
+            let st = st.clone();
+            move |s: SocketRef, Data::<CreatePathPayload>(p): Data<CreatePathPayload>| {
+                let st = st.clone();
+                Box::pin(async move {
+                    if let Err(e) = handle_create_directory(&s, st, p).await {
+                        eprintln!("create_directory: {}", e);
+                    }
+                })
+            }
+        });
+
+        let st = state.clone();
+        s.on(events::incoming::DELETE_PATH, {
+            let st = st.clone();
+            move |s: SocketRef, Data::<DeletePathPayload>(p): Data<DeletePathPayload>| {
+                let st = st.clone();
+                Box::pin(async move {
+                    if let Err(e) = handle_delete_path(&s, st, p).await {
+                        eprintln!("delete_path: {}", e);
+                    }
+                })
+            }
+        });
+
+        let st = state.clone();
+        s.on(events::incoming::RENAME_PATH, {
+            let st = st.clone();
+            move |s: SocketRef, Data::<TransferPathPayload>(p): Data<TransferPathPayload>| {
+                let st = st.clone();
+                Box::pin(async move {
+                    if let Err(e) = handle_rename_path(&s, st, p).await {
+                        eprintln!("rename_path: {}", e);
+                    }
+                })
+            }
+        });
+
+        let st = state.clone();
+        s.on(events::incoming::COPY_PATH, {
+            let st = st.clone();
+            move |s: SocketRef, Data::<TransferPathPayload>(p): Data<TransferPathPayload>| {
+                let st = st.clone();
+                Box::pin(async move {
+                    if let Err(e) = handle_copy_path(&s, st, p).await {
+                        eprintln!("copy_path: {}", e);
+                    }
+                })
+            }
+        });
+
+        let st = state.clone();
+        s.on(events::incoming::START_UPLOAD, {
+            let st = st.clone();
+            move |s: SocketRef, Data::<StartUploadPayload>(p): Data<StartUploadPayload>| {
+                let st = st.clone();
+                Box::pin(async move {
+                    if let Err(e) = handle_start_upload(&s, st, p).await {
+                        eprintln!("start_upload: {}", e);
+                    }
+                })
+            }
+        });
+
+        let st = state.clone();
+        s.on(events::incoming::UPLOAD_CHUNK, {
+            let st = st.clone();
+            move |s: SocketRef, Data::<UploadChunkPayload>(p): Data<UploadChunkPayload>| {
+                let st = st.clone();
+                Box::pin(async move {
+                    if let Err(e) = handle_upload_chunk(&s, st, p).await {
+                        eprintln!("upload_chunk: {}", e);
+                    }
+                })
+            }
+        });
+
+        let st = state.clone();
+        s.on(events::incoming::CANCEL_UPLOAD, {
+            let st = st.clone();
+            move |s: SocketRef, Data::<UploadPayload>(p): Data<UploadPayload>| {
+                let st = st.clone();
+                Box::pin(async move {
+                    if let Err(e) = handle_cancel_upload(&s, st, p).await {
+                        eprintln!("cancel_upload: {}", e);
+                    }
+                })
+            }
+        });
+
+        let st = state.clone();
+        s.on(events::incoming::DOWNLOAD_FILE, {
+            let st = st.clone();
+            move |s: SocketRef, Data::<DownloadFilePayload>(p): Data<DownloadFilePayload>| {
+                let st = st.clone();
+                Box::pin(async move {
+                    if let Err(e) = handle_download_file(&s, st, p).await {
+                        eprintln!("download_file: {}", e);
+                    }
+                })
+            }
+        });
+
+        let st = state.clone();
+        s.on(events::incoming::DOWNLOAD_ACK, {
+            let st = st.clone();
+            move |s: SocketRef, Data::<DownloadAckPayload>(p): Data<DownloadAckPayload>| {
+                let st = st.clone();
+                Box::pin(async move {
+                    if let Err(e) = handle_download_ack(&s, st, p).await {
+                        eprintln!("download_ack: {}", e);
+                    }
+                })
+            }
+        });
+
+        let st = state.clone();
+        s.on(events::incoming::CANCEL_DOWNLOAD, {
+            let st = st.clone();
+            move |s: SocketRef, Data::<DownloadPayload>(p): Data<DownloadPayload>| {
+                let st = st.clone();
+                Box::pin(async move {
+                    if let Err(e) = handle_cancel_download(&s, st, p).await {
+                        eprintln!("cancel_download: {}", e);
+                    }
+                })
+            }
+        });
+
+        let st = state.clone();
+        s.on(events::incoming::WATCH_DIRECTORY, {
+            let st = st.clone();
+            move |s: SocketRef, Data::<WatchDirectoryPayload>(p): Data<WatchDirectoryPayload>| {
+                let st = st.clone();
+                Box::pin(async move {
+                    if let Err(e) = handle_watch_directory(&s, st, p).await {
+                        eprintln!("watch_directory: {}", e);
+                    }
+                })
+            }
+        });
+
+        let st = state.clone();
+        s.on(events::incoming::UNWATCH_DIRECTORY, {
+            let st = st.clone();
+            move |s: SocketRef, Data::<WatchDirectoryPayload>(p): Data<WatchDirectoryPayload>| {
+                let st = st.clone();
+                Box::pin(async move {
+                    if let Err(e) = handle_unwatch_directory(&s, st, p).await {
+                        eprintln!("unwatch_directory: {}", e);
+                    }
+                })
+            }
+        });
+
         let st = state.clone();
         s.on(events::incoming::CLOSE_TERMINAL, {
             let st = st.clone();
@@ -131,6 +379,240 @@ pub fn register_handlers(io: &SocketIo, state: AppState) {
             }
         });
 
+        let st = state.clone();
+        s.on(events::incoming::ATTACH_TERMINAL, {
+            let st = st.clone();
+            move |s: SocketRef, Data::<AttachTerminalPayload>(p): Data<AttachTerminalPayload>| {
+                let st = st.clone();
+                Box::pin(async move {
+                    if let Err(e) = handle_attach_terminal(&s, st, p).await {
+                        eprintln!("attach_terminal: {}", e);
+                    }
+                })
+            }
+        });
+
+        let st = state.clone();
+        s.on(events::incoming::JOIN_TERMINAL, {
+            let st = st.clone();
+            move |s: SocketRef, Data::<JoinTerminalPayload>(p): Data<JoinTerminalPayload>| {
+                let st = st.clone();
+                Box::pin(async move {
+                    if let Err(e) = handle_join_terminal(&s, st, p).await {
+                        eprintln!("join_terminal: {}", e);
+                    }
+                })
+            }
+        });
+
+        let st = state.clone();
+        s.on(events::incoming::SHARE_TERMINAL, {
+            let st = st.clone();
+            move |s: SocketRef, Data::<ShareTerminalPayload>(p): Data<ShareTerminalPayload>| {
+                let st = st.clone();
+                Box::pin(async move {
+                    if let Err(e) = handle_share_terminal(&s, st, p).await {
+                        eprintln!("share_terminal: {}", e);
+                    }
+                })
+            }
+        });
+
+        let st = state.clone();
+        s.on(events::incoming::REVOKE_TERMINAL_ACCESS, {
+            let st = st.clone();
+            move |s: SocketRef, Data::<RevokeTerminalAccessPayload>(p): Data<RevokeTerminalAccessPayload>| {
+                let st = st.clone();
+                Box::pin(async move {
+                    if let Err(e) = handle_revoke_terminal_access(&s, st, p).await {
+                        eprintln!("revoke_terminal_access: {}", e);
+                    }
+                })
+            }
+        });
+
+        let st = state.clone();
+        s.on(events::incoming::START_RECORDING, {
+            let st = st.clone();
+            move |s: SocketRef, Data::<RecordingPayload>(p): Data<RecordingPayload>| {
+                let st = st.clone();
+                Box::pin(async move {
+                    if let Err(e) = handle_start_recording(&s, st, p).await {
+                        eprintln!("start_recording: {}", e);
+                    }
+                })
+            }
+        });
+
+        let st = state.clone();
+        s.on(events::incoming::STOP_RECORDING, {
+            let st = st.clone();
+            move |s: SocketRef, Data::<RecordingPayload>(p): Data<RecordingPayload>| {
+                let st = st.clone();
+                Box::pin(async move {
+                    if let Err(e) = handle_stop_recording(&s, st, p).await {
+                        eprintln!("stop_recording: {}", e);
+                    }
+                })
+            }
+        });
+
+        let st = state.clone();
+        s.on(events::incoming::GET_TERMINAL_DEFAULTS, {
+            let st = st.clone();
+            move |s: SocketRef, Data::<EmailPayload>(p): Data<EmailPayload>| {
+                let st = st.clone();
+                Box::pin(async move {
+                    if let Err(e) = handle_get_terminal_defaults(&s, st, p).await {
+                        eprintln!("get_terminal_defaults: {}", e);
+                    }
+                })
+            }
+        });
+
+        let st = state.clone();
+        s.on(events::incoming::SET_TERMINAL_DEFAULTS, {
+            let st = st.clone();
+            move |s: SocketRef, Data::<SetTerminalDefaultsPayload>(p): Data<SetTerminalDefaultsPayload>| {
+                let st = st.clone();
+                Box::pin(async move {
+                    if let Err(e) = handle_set_terminal_defaults(&s, st, p).await {
+                        eprintln!("set_terminal_defaults: {}", e);
+                    }
+                })
+            }
+        });
+
+        let st = state.clone();
+        s.on(events::incoming::LIST_SHELLS, {
+            let st = st.clone();
+            move |s: SocketRef, Data::<EmailPayload>(p): Data<EmailPayload>| {
+                let st = st.clone();
+                Box::pin(async move {
+                    if let Err(e) = handle_list_shells(&s, st, p).await {
+                        eprintln!("list_shells: {}", e);
+                    }
+                })
+            }
+        });
+
+        let st = state.clone();
+        s.on(events::incoming::EXEC_COMMAND, {
+            let st = st.clone();
+            move |s: SocketRef, Data::<ExecCommandPayload>(p): Data<ExecCommandPayload>| {
+                let st = st.clone();
+                Box::pin(async move {
+                    if let Err(e) = handle_exec_command(&s, st, p).await {
+                        eprintln!("exec_command: {}", e);
+                    }
+                })
+            }
+        });
+
+        let st = state.clone();
+        s.on(events::incoming::LIST_TASKS, {
+            let st = st.clone();
+            move |s: SocketRef, Data::<EmailPayload>(p): Data<EmailPayload>| {
+                let st = st.clone();
+                Box::pin(async move {
+                    if let Err(e) = handle_list_tasks(&s, st, p).await {
+                        eprintln!("list_tasks: {}", e);
+                    }
+                })
+            }
+        });
+
+        let st = state.clone();
+        s.on(events::incoming::RUN_TASK, {
+            let st = st.clone();
+            move |s: SocketRef, Data::<RunTaskPayload>(p): Data<RunTaskPayload>| {
+                let st = st.clone();
+                Box::pin(async move {
+                    if let Err(e) = handle_run_task(&s, st, p).await {
+                        eprintln!("run_task: {}", e);
+                    }
+                })
+            }
+        });
+
+        let st = state.clone();
+        s.on(events::incoming::START_JOB, {
+            let st = st.clone();
+            move |s: SocketRef, Data::<StartJobPayload>(p): Data<StartJobPayload>| {
+                let st = st.clone();
+                Box::pin(async move {
+                    if let Err(e) = handle_start_job(&s, st, p).await {
+                        eprintln!("start_job: {}", e);
+                    }
+                })
+            }
+        });
+
+        let st = state.clone();
+        s.on(events::incoming::LIST_JOBS, {
+            let st = st.clone();
+            move |s: SocketRef, Data::<EmailPayload>(p): Data<EmailPayload>| {
+                let st = st.clone();
+                Box::pin(async move {
+                    if let Err(e) = handle_list_jobs(&s, st, p).await {
+                        eprintln!("list_jobs: {}", e);
+                    }
+                })
+            }
+        });
+
+        let st = state.clone();
+        s.on(events::incoming::TAIL_JOB, {
+            let st = st.clone();
+            move |s: SocketRef, Data::<TailJobPayload>(p): Data<TailJobPayload>| {
+                let st = st.clone();
+                Box::pin(async move {
+                    if let Err(e) = handle_tail_job(&s, st, p).await {
+                        eprintln!("tail_job: {}", e);
+                    }
+                })
+            }
+        });
+
+        let st = state.clone();
+        s.on(events::incoming::STOP_JOB, {
+            let st = st.clone();
+            move |s: SocketRef, Data::<JobPayload>(p): Data<JobPayload>| {
+                let st = st.clone();
+                Box::pin(async move {
+                    if let Err(e) = handle_stop_job(&s, st, p).await {
+                        eprintln!("stop_job: {}", e);
+                    }
+                })
+            }
+        });
+
+        let st = state.clone();
+        s.on(events::incoming::RESTART_JOB, {
+            let st = st.clone();
+            move |s: SocketRef, Data::<JobPayload>(p): Data<JobPayload>| {
+                let st = st.clone();
+                Box::pin(async move {
+                    if let Err(e) = handle_restart_job(&s, st, p).await {
+                        eprintln!("restart_job: {}", e);
+                    }
+                })
+            }
+        });
+
+        let st = state.clone();
+        s.on(events::incoming::REMOVE_JOB, {
+            let st = st.clone();
+            move |s: SocketRef, Data::<JobPayload>(p): Data<JobPayload>| {
+                let st = st.clone();
+                Box::pin(async move {
+                    if let Err(e) = handle_remove_job(&s, st, p).await {
+                        eprintln!("remove_job: {}", e);
+                    }
+                })
+            }
+        });
+
         s.on(events::incoming::CODE_COMPLETION, {
             move |s: SocketRef, Data::<CompletionPayload>(p): Data<CompletionPayload>| {
                 Box::pin(async move {
@@ -142,12 +624,14 @@ pub fn register_handlers(io: &SocketIo, state: AppState) {
         let st = state.clone();
         s.on_disconnect(move |s: SocketRef| {
             let socket_id = s.id;
+            detach_socket(&st, socket_id);
+            st.jobs.forget_socket(socket_id);
+            st.transfers.forget_socket(socket_id);
+            st.watchers.forget_socket(socket_id);
             if let Some((_, email)) = st.socket_mapping.remove(&socket_id) {
                 st.email_mapping.remove(&email);
-                let prefix = format!("{}:", email);
-                st.terminal_mapping.retain(|k, _| !k.starts_with(&prefix));
-                st.back_terminal_mapping.remove(&email);
                 st.docker_container_id.remove(&email);
+                st.workspace_root.remove(&email);
             }
             println!("Socket disconnected: {:?}", socket_id);
         });
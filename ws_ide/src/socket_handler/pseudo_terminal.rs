@@ -3,24 +3,48 @@ use nix::pty::{openpty, Winsize};
 use nix::sys::termios::{
     self, InputFlags, LocalFlags, OutputFlags, SetArg, SpecialCharacterIndices,
 };
-use regex::Regex;
 use socketioxide::extract::SocketRef;
 
 use std::fs::File;
 use std::os::unix::io::FromRawFd;
 use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::time::Instant;
 use tokio::io::AsyncReadExt;
+use tokio::sync::{mpsc, oneshot};
 use tokio::task;
+use tokio::time::{sleep_until, Instant as TokioInstant};
+use uuid::Uuid;
 
 use crate::{
     events,
     state::{terminal_key, AppState},
-    types::{TerminalDataPayload, TerminalStatusPayload},
+    tasks::{problem_matcher::ProblemMatcher, TaskRun},
+    terminal_session::{
+        flow::{BATCH_BYTES, BATCH_WINDOW},
+        options::TerminalOptions,
+        foreground::{self, TERMINAL_MARKER},
+        links::{resolve_links, ExistenceCache, LinkCandidate, LinkDetector},
+        shell_integration::{self, ShellEvent, ShellIntegrationParser},
+        utf8_decoder::Utf8StreamDecoder,
+        TerminalSession,
+    },
+    types::{
+        CommandFinishedPayload, CommandStartedPayload, Diagnostic, DiagnosticSeverity,
+        TaskDiagnosticsPayload, TaskFinishedPayload, TerminalLinksPayload, TerminalStatusPayload,
+    },
 };
 
+fn is_pty_closed(read: &Result<usize, std::io::Error>) -> bool {
+    match read {
+        Ok(n) => *n == 0,
+        Err(e) => e.raw_os_error() == Some(libc::EIO),
+    }
+}
+
 fn configure_pty(slave_fd: i32) -> Result<(), std::io::Error> {
     let mut termios = termios::tcgetattr(slave_fd)
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        .map_err(std::io::Error::other)?;
 
     termios.input_flags.remove(
         InputFlags::ICRNL
@@ -37,47 +61,266 @@ fn configure_pty(slave_fd: i32) -> Result<(), std::io::Error> {
     termios.control_chars[SpecialCharacterIndices::VTIME as usize] = 0;
 
     termios::tcsetattr(slave_fd, SetArg::TCSANOW, &termios)
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))
+        .map_err(std::io::Error::other)
 }
 
-fn spawn_docker_shell(container_id: &str, slave_fd: i32) -> Result<std::process::Child, std::io::Error> {
-    Command::new("docker")
-        .arg("exec")
-        .arg("-it")
-        .arg(container_id)
-        .env("TERM", "xterm-256color")
-        .env("COLORTERM", "truecolor")
-        .env("LC_ALL", "C.UTF-8")
-        .arg("/bin/bash")
+fn spawn_docker_shell(
+    container_id: &str,
+    options: &TerminalOptions,
+    slave_fd: i32,
+) -> Result<std::process::Child, std::io::Error> {
+    let mut cmd = Command::new("docker");
+    cmd.arg("exec").arg("-it");
+    if let Some(dir) = options.workdir.as_deref() {
+        cmd.arg("-w").arg(dir);
+    }
+    for (name, value) in &options.env {
+        cmd.arg("-e").arg(format!("{}={}", name, value));
+    }
+    cmd.arg(container_id)
+        .arg(&options.shell)
+        .args(&options.shell_args)
         .stdin(unsafe { Stdio::from_raw_fd(libc::dup(slave_fd)) })
         .stdout(unsafe { Stdio::from_raw_fd(libc::dup(slave_fd)) })
         .stderr(unsafe { Stdio::from_raw_fd(libc::dup(slave_fd)) })
         .spawn()
 }
 
+/// Batches of link candidates waiting behind the one being checked.
+const LINK_QUEUE: usize = 1;
+
+/// One task per terminal checks link candidates, one `docker exec` at a time.
+/// It ends when the read loop drops its sender.
+fn spawn_link_checker(
+    session: &Arc<TerminalSession>,
+    root: Option<String>,
+) -> mpsc::Sender<(Option<String>, Vec<LinkCandidate>)> {
+    let (tx, mut rx) = mpsc::channel::<(Option<String>, Vec<LinkCandidate>)>(LINK_QUEUE);
+    let session_links = session.clone();
+    let worker = task::spawn(async move {
+        let session = session_links;
+        let cache = ExistenceCache::default();
+        while let Some((cwd, candidates)) = rx.recv().await {
+            let found = resolve_links(
+                &session.container_id,
+                cwd.as_deref(),
+                root.as_deref(),
+                &cache,
+                candidates,
+            )
+            .await;
+            match found {
+                Ok(links) if !links.is_empty() => {
+                    session.emit(events::outgoing::TERMINAL_LINKS, &TerminalLinksPayload {
+                        terminal_id: session.terminal_id.clone(),
+                        links,
+                    });
+                }
+                Ok(_) => {}
+                Err(e) => eprintln!("[terminal] link check failed for {}: {}", session.room(), e),
+            }
+        }
+    });
+    session.track(worker.abort_handle());
+    tx
+}
+
+/// PTY output waiting to be sent. Reads are coalesced for `BATCH_WINDOW` (or
+/// until `BATCH_BYTES`) so noisy commands produce a few large messages rather
+/// than one per read.
+#[derive(Default)]
+struct OutputBatch {
+    raw: Vec<u8>,
+    deadline: Option<TokioInstant>,
+    decoder: Utf8StreamDecoder,
+    integration: ShellIntegrationParser,
+    command_started: Option<Instant>,
+    /// Set when the terminal runs a task.
+    task: Option<TaskRun>,
+    matcher: Option<ProblemMatcher>,
+    errors: u32,
+    warnings: u32,
+    links: LinkDetector,
+    /// Feeds the terminal's link checker; started with the first candidates.
+    link_checks: Option<mpsc::Sender<(Option<String>, Vec<LinkCandidate>)>>,
+    /// Where relative paths point until the shell reports its cwd.
+    workdir: Option<String>,
+    /// Only files under the workspace root are linked.
+    root: Option<String>,
+}
+
+impl OutputBatch {
+    fn new(task: Option<TaskRun>, workdir: Option<String>, root: Option<String>) -> Self {
+        let matcher = task
+            .as_ref()
+            .and_then(|t| t.matcher.map(|kind| ProblemMatcher::new(kind, t.cwd.clone())));
+        Self { task, matcher, workdir, root, ..Default::default() }
+    }
+
+    fn push(&mut self, bytes: &[u8]) {
+        self.raw.extend_from_slice(bytes);
+        self.deadline.get_or_insert_with(|| TokioInstant::now() + BATCH_WINDOW);
+    }
+
+    fn flush(&mut self, session: &Arc<TerminalSession>) {
+        self.deadline = None;
+        if self.raw.is_empty() {
+            return;
+        }
+        let raw = std::mem::take(&mut self.raw);
+        let text = self.decoder.decode(&raw);
+        session.output(&raw, &text);
+
+        if let Some(matcher) = self.matcher.as_mut() {
+            let found = matcher.feed(&text);
+            self.report(session, found);
+        }
+        self.find_links(session, &text);
+
+        for event in self.integration.feed(&text) {
+            match event {
+                ShellEvent::Cwd(cwd) => session.set_cwd(cwd),
+                ShellEvent::PromptStart => {}
+                ShellEvent::CommandStarted => {
+                    session.hint_foreground();
+                    self.command_started = Some(Instant::now());
+                    session.emit(events::outgoing::COMMAND_STARTED, &CommandStartedPayload {
+                        terminal_id: session.terminal_id.clone(),
+                        cwd: session.cwd(),
+                    });
+                }
+                ShellEvent::CommandFinished(exit_code) => {
+                    session.hint_foreground();
+                    let duration_ms = self
+                        .command_started
+                        .take()
+                        .map(|at| at.elapsed().as_millis() as u64);
+                    session.emit(events::outgoing::COMMAND_FINISHED, &CommandFinishedPayload {
+                        terminal_id: session.terminal_id.clone(),
+                        cwd: session.cwd(),
+                        exit_code,
+                        duration_ms,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Looks for `path:line` references and hands them to the terminal's
+    /// link checker, which emits the ones naming existing workspace files.
+    /// While it is still busy with earlier output, new candidates are dropped.
+    fn find_links(&mut self, session: &Arc<TerminalSession>, text: &str) {
+        let candidates = self.links.feed(text);
+        if candidates.is_empty() {
+            return;
+        }
+        let cwd = session.cwd().or_else(|| self.workdir.clone());
+        let root = &self.root;
+        let checks = self
+            .link_checks
+            .get_or_insert_with(|| spawn_link_checker(session, root.clone()));
+        checks.try_send((cwd, candidates)).ok();
+    }
+
+    fn report(&mut self, session: &TerminalSession, diagnostics: Vec<Diagnostic>) {
+        let Some(task) = self.task.as_ref() else {
+            return;
+        };
+        if diagnostics.is_empty() {
+            return;
+        }
+        for d in &diagnostics {
+            match d.severity {
+                DiagnosticSeverity::Error => self.errors += 1,
+                DiagnosticSeverity::Warning => self.warnings += 1,
+                DiagnosticSeverity::Info => {}
+            }
+        }
+        session.emit(events::outgoing::TASK_DIAGNOSTICS, &TaskDiagnosticsPayload {
+            terminal_id: session.terminal_id.clone(),
+            task: task.name.clone(),
+            diagnostics,
+        });
+    }
+
+    /// Flushes what is left once the PTY has closed and, for a task, reports
+    /// how it ended.
+    async fn finish(&mut self, session: &Arc<TerminalSession>, exit: oneshot::Receiver<Option<i32>>) {
+        self.flush(session);
+        if let Some(matcher) = self.matcher.as_mut() {
+            let found = matcher.finish();
+            self.report(session, found);
+        }
+        if let Some(task) = self.task.as_ref() {
+            let exit_code = exit.await.ok().flatten();
+            session.emit(events::outgoing::TASK_FINISHED, &TaskFinishedPayload {
+                terminal_id: session.terminal_id.clone(),
+                task: task.name.clone(),
+                exit_code,
+                errors: self.errors,
+                warnings: self.warnings,
+            });
+        }
+    }
+}
+
 pub async fn pseudo_terminal(
     s: &SocketRef,
     docker_container_id: Option<String>,
     state: AppState,
     email: String,
     terminal_id: String,
+    mut options: TerminalOptions,
 ) -> Result<(), std::io::Error> {
-    let winsize = Winsize { ws_row: 24, ws_col: 80, ws_xpixel: 0, ws_ypixel: 0 };
+    let key = terminal_key(&email, &terminal_id);
+    state.terminals.check_capacity(&email, &key)?;
+
+    let winsize = Winsize { ws_row: options.rows, ws_col: options.cols, ws_xpixel: 0, ws_ypixel: 0 };
 
     let pty = openpty(Some(&winsize), None)
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        .map_err(std::io::Error::other)?;
 
     configure_pty(pty.slave)?;
 
     let container_id = docker_container_id.unwrap_or_else(|| "default-container".to_string());
-    let key = terminal_key(&email, &terminal_id);
+
+    if let Err(e) = shell_integration::prepare(&container_id, &mut options).await {
+        eprintln!("[terminal] shell integration unavailable for {}: {}", key, e);
+    }
+
+    options
+        .env
+        .insert(TERMINAL_MARKER.to_string(), Uuid::new_v4().to_string());
 
     let master = unsafe { File::from_raw_fd(libc::dup(pty.master)) };
-    state.terminal_mapping.insert(key.clone(), master.try_clone()?);
+    let session = Arc::new(TerminalSession::new(
+        s,
+        email.clone(),
+        terminal_id.clone(),
+        container_id.clone(),
+        master.try_clone()?,
+        &options,
+    ));
+    match state.terminals.insert(session.clone()) {
+        Ok(Some(replaced)) => replaced.terminate().await,
+        Ok(None) => {}
+        Err(e) => {
+            nix::unistd::close(pty.slave).ok();
+            nix::unistd::close(pty.master).ok();
+            return Err(e);
+        }
+    }
+
+    let spawned = spawn_docker_shell(&container_id, &options, pty.slave);
+    // The child has its own copies now. Holding on to the slave would keep
+    // the master from ever seeing EOF once the shell exits.
+    nix::unistd::close(pty.slave).ok();
+    nix::unistd::close(pty.master).ok();
 
-    let mut child = match spawn_docker_shell(&container_id, pty.slave) {
+    let mut child = match spawned {
         Ok(c) => c,
         Err(e) => {
+            state.terminals.remove_if_current(&key, &session);
             s.emit(events::outgoing::TERMINAL_ERROR, &TerminalStatusPayload {
                 terminal_id: terminal_id.clone(),
                 message: format!("Failed to start docker exec: {}", e),
@@ -86,6 +329,7 @@ pub async fn pseudo_terminal(
             return Err(e);
         }
     };
+    session.set_child(Some(child.id()));
 
     s.emit(events::outgoing::TERMINAL_SUCCESS, &TerminalStatusPayload {
         terminal_id: terminal_id.clone(),
@@ -93,132 +337,103 @@ pub async fn pseudo_terminal(
     })
     .ok();
 
-    let socket_read = s.clone();
-    let tid_read = terminal_id.clone();
-    let email_read = email.clone();
+    session.track(foreground::watch(&session).abort_handle());
+
+    let session_read = session.clone();
     let master_read = master.try_clone()?;
+    let (exit_tx, exit_rx) = oneshot::channel();
+    let mut exit_rx = Some(exit_rx);
+    let task_run = options.task.clone();
+    let workdir = options.workdir.clone();
+    let root = state.workspace_root.get(&email).map(|r| r.clone());
 
-    task::spawn(async move {
+    let reader = task::spawn(async move {
         let mut buf = [0u8; 4096];
         let mut reader = tokio::fs::File::from_std(master_read);
-        let ansi_re = Regex::new(r"\x1b\[[0-9;]*[mGKHFJl]|\x1b\][^\x07]*\x07").unwrap();
-        let prompt_re = Regex::new(r"@[^:]+:([^#\$%\r\n]+)[#\$%]").unwrap();
+        let mut pending = OutputBatch::new(task_run, workdir, root);
 
         loop {
-            match reader.read(&mut buf).await {
-                Ok(0) => {
-                    socket_read
-                        .emit(events::outgoing::TERMINAL_CLOSED, &TerminalStatusPayload {
-                            terminal_id: tid_read.clone(),
-                            message: "Terminal session ended".to_string(),
-                        })
-                        .ok();
-                    break;
-                }
-                Ok(n) => {
-                    let text = match std::str::from_utf8(&buf[..n]) {
-                        Ok(t) => t.to_string(),
-                        Err(_) => {
-                            let mut end = n;
-                            while end > 0 {
-                                if std::str::from_utf8(&buf[..end]).is_ok() {
-                                    break;
-                                }
-                                end -= 1;
-                            }
-                            if end == 0 { continue; }
-                            std::str::from_utf8(&buf[..end]).unwrap().to_string()
+            if session_read.over_capacity() {
+                pending.flush(&session_read);
+                session_read.wait_for_capacity().await;
+            }
+
+            let deadline = pending.deadline;
+            tokio::select! {
+                read = reader.read(&mut buf) => match read {
+                    // Linux reports a closed slave side as EIO rather than EOF.
+                    Ok(0) | Err(_) if is_pty_closed(&read) => {
+                        if let Some(exit) = exit_rx.take() {
+                            pending.finish(&session_read, exit).await;
                         }
-                    };
-
-                    socket_read
-                        .emit(events::outgoing::TERMINAL_DATA, &TerminalDataPayload {
-                            terminal_id: tid_read.clone(),
-                            data: text.clone(),
-                        })
-                        .ok();
-
-                    let clean = ansi_re.replace_all(&text, "");
-                    if let Some(caps) = prompt_re.captures(&clean) {
-                        let cwd = caps[1].trim().to_string();
-                        if !cwd.is_empty() {
-                            socket_read.emit(events::outgoing::TERMINAL_CWD, &cwd).ok();
+                        session_read.emit_status(events::outgoing::TERMINAL_CLOSED, "Terminal session ended");
+                        break;
+                    }
+                    Ok(n) => {
+                        pending.push(&buf[..n]);
+                        if pending.raw.len() >= BATCH_BYTES {
+                            pending.flush(&session_read);
                         }
                     }
-                }
-                Err(e) => {
-                    eprintln!("Terminal read error for {}:{}: {}", email_read, tid_read, e);
-                    socket_read
-                        .emit("terminal_error", &TerminalStatusPayload {
-                            terminal_id: tid_read.clone(),
-                            message: format!("Terminal read error: {}", e),
-                        })
-                        .ok();
-                    break;
+                    Err(e) => {
+                        pending.flush(&session_read);
+                        eprintln!(
+                            "Terminal read error for {}:{}: {}",
+                            session_read.email, session_read.terminal_id, e
+                        );
+                        session_read.emit_status(
+                            events::outgoing::TERMINAL_ERROR,
+                            format!("Terminal read error: {}", e),
+                        );
+                        break;
+                    }
+                },
+                _ = sleep_until(deadline.unwrap_or_else(TokioInstant::now)), if deadline.is_some() => {
+                    pending.flush(&session_read);
                 }
             }
         }
     });
+    session.track(reader.abort_handle());
 
-    let socket_exit = s.clone();
-    let tid_exit = terminal_id.clone();
     let state_exit = state.clone();
     let key_exit = key.clone();
+    let session_exit = session.clone();
 
     task::spawn(async move {
         let status = task::spawn_blocking(move || child.wait()).await;
-        match status {
-            Ok(Ok(s)) => println!("Docker process exited: {} for key={}", s, key_exit),
-            Ok(Err(e)) => eprintln!("Error waiting for docker process key={}: {}", key_exit, e),
-            Err(e) => eprintln!("spawn_blocking join error key={}: {}", key_exit, e),
+        let exit_code = match status {
+            Ok(Ok(s)) => {
+                println!("Docker process exited: {} for key={}", s, key_exit);
+                s.code()
+            }
+            Ok(Err(e)) => {
+                eprintln!("Error waiting for docker process key={}: {}", key_exit, e);
+                None
+            }
+            Err(e) => {
+                eprintln!("spawn_blocking join error key={}: {}", key_exit, e);
+                None
+            }
+        };
+        session_exit.set_child(None);
+        exit_tx.send(exit_code).ok();
+        let stats = session_exit.stats();
+        println!(
+            "[session] {} output: {} bytes in {} batches, {} dropped, {} pauses ({} ms), {} B/s",
+            key_exit,
+            stats.bytes_read,
+            stats.batches,
+            stats.bytes_dropped,
+            stats.pauses,
+            stats.paused_ms,
+            stats.throughput_bps
+        );
+        state_exit.terminals.remove_if_current(&key_exit, &session_exit);
+        if !session_exit.is_closed() {
+            session_exit.emit_status(events::outgoing::TERMINAL_CLOSED, "Docker process terminated");
         }
-        state_exit.terminal_mapping.remove(&key_exit);
-        socket_exit
-            .emit(events::outgoing::TERMINAL_CLOSED, &TerminalStatusPayload {
-                terminal_id: tid_exit,
-                message: "Docker process terminated".to_string(),
-            })
-            .ok();
     });
 
-    pseudo_back_terminal(state, email, terminal_id).await?;
-
-    Ok(())
-}
-
-pub async fn pseudo_back_terminal(
-    state: AppState,
-    email: String,
-    terminal_id: String,
-) -> Result<(), std::io::Error> {
-    let winsize = Winsize { ws_row: 24, ws_col: 80, ws_xpixel: 0, ws_ypixel: 0 };
-
-    let container_id = state
-        .docker_container_id
-        .get(&email)
-        .map(|r| r.clone())
-        .ok_or_else(|| {
-            std::io::Error::new(
-                std::io::ErrorKind::NotFound,
-                format!("Docker container not found for email: {}", email),
-            )
-        })?;
-
-    let pty = openpty(Some(&winsize), None)
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
-
-    configure_pty(pty.slave)?;
-
-    match spawn_docker_shell(&container_id, pty.slave) {
-        Ok(_) => println!("Back terminal started for {}:{}", email, terminal_id),
-        Err(e) => {
-            eprintln!("Failed to spawn back terminal for {}: {}", email, e);
-            return Err(e);
-        }
-    }
-
-    let master = unsafe { File::from_raw_fd(libc::dup(pty.master)) };
-    state.back_terminal_mapping.insert(email, master);
-
     Ok(())
 }
@@ -1,89 +1,103 @@
-use crate::{events, state::AppState};
-use serde_json::{json, Value};
 use socketioxide::extract::SocketRef;
-use std::collections::HashMap;
-use std::process::Command;
 
-pub async fn get_repo_structure(
+use crate::{
+    events,
+    files::{
+        exclude::ExcludeRules,
+        listing::{list_directory, DEFAULT_DEPTH, DEFAULT_PAGE, MAX_DEPTH, MAX_PAGE},
+    },
+    state::AppState,
+    types::{DirEntry, RepoStructurePayload, RepoTreePayload},
+};
+
+/// Lists `dir` and applies the workspace's exclude rules: excluded entries
+/// are dropped, or only flagged with `show_excluded`. Directories the globs
+/// exclude are pruned from the walk either way, so they don't count against
+/// the listing's cap. A broken settings file is reported on
+/// `settings_error` and the default rules used.
+async fn list_visible(
     s: &SocketRef,
-    state: AppState,
-    email: String,
-    path: Option<String>,
-) -> Result<(), std::io::Error> {
-    let pwd = path.unwrap_or_else(|| "/".to_string());
-    let mut repo_info: HashMap<String, Value> = HashMap::new();
-    repo_info.insert("current_directory".to_string(), json!(pwd));
-
-    let raw_items = get_directory_items(&email, &state, &pwd).await?;
-
-    let items: Vec<Value> = raw_items
-        .into_iter()
-        .map(|(name, is_dir)| {
-            let item_path = format!("{}/{}", pwd.trim_end_matches('/'), name);
-            json!({ "name": name, "is_dir": is_dir, "path": item_path })
-        })
-        .collect();
-
-    repo_info.insert("items".to_string(), json!(items));
-    s.emit(events::outgoing::REPO_STRUCTURE, &repo_info).ok();
-
-    Ok(())
-}
-
-async fn get_directory_items(
-    email: &str,
     state: &AppState,
-    path: &str,
-) -> Result<Vec<(String, bool)>, std::io::Error> {
-    let container_id = match state.docker_container_id.get(email).map(|r| r.clone()) {
-        Some(id) => id,
-        None => return Ok(Vec::new()),
-    };
-
-    let output = match Command::new("docker")
-        .arg("exec")
-        .arg(&container_id)
-        .arg("ls")
-        .arg("-la")
-        .arg("--color=never")
-        .arg(path)
-        .output()
-    {
-        Ok(o) if o.status.success() => o,
-        _ => return Ok(Vec::new()),
-    };
-
-    let content = String::from_utf8_lossy(&output.stdout);
-    let mut items = Vec::new();
-
-    for line in content.lines() {
-        let line = line.trim();
-        if line.is_empty() || line.starts_with("total ") {
-            continue;
-        }
-
-        let parts: Vec<&str> = line.split_whitespace().collect();
-        if parts.len() < 9 {
-            continue;
+    email: &str,
+    container_id: &str,
+    dir: &str,
+    depth: u32,
+    show_excluded: bool,
+) -> Result<(Vec<DirEntry>, bool), String> {
+    let root = state.workspace_root.get(email).map(|r| r.clone());
+    let rules = match ExcludeRules::load(container_id, root.as_deref()).await {
+        Ok(rules) => rules,
+        Err(e) => {
+            eprintln!("[repo] {} for {}", e, email);
+            s.emit(events::outgoing::SETTINGS_ERROR, &e).ok();
+            ExcludeRules::defaults(root.as_deref())
         }
+    };
+    let prune = rules.prune_regex();
+    let (mut entries, truncated) = list_directory(container_id, dir, depth, prune.as_deref(), show_excluded).await?;
 
-        let permissions = parts[0];
-        let filename = parts[8..].join(" ");
+    let paths: Vec<&str> = entries.iter().map(|entry| entry.path.as_str()).collect();
+    let excluded = rules.excluded(container_id, &paths).await;
+    for entry in &mut entries {
+        entry.excluded = excluded.contains(&entry.path);
+    }
+    if !show_excluded {
+        entries.retain(|entry| !entry.excluded);
+    }
+    Ok((entries, truncated))
+}
 
-        if filename == "." || filename == ".." {
-            continue;
+/// Lists `path` (the workspace root by default) down to `depth` levels,
+/// minus excluded entries, and emits one page of `limit` entries from
+/// `offset`.
+pub async fn get_repo_structure(
+    s: &SocketRef,
+    state: AppState,
+    payload: RepoTreePayload,
+) -> Result<(), std::io::Error> {
+    let email = payload.email;
+    let pwd = payload.path.unwrap_or_else(|| {
+        state
+            .workspace_root
+            .get(&email)
+            .map(|r| r.clone())
+            .unwrap_or_else(|| "/".to_string())
+    });
+    let depth = payload.depth.unwrap_or(DEFAULT_DEPTH).clamp(1, MAX_DEPTH);
+    let limit = payload.limit.unwrap_or(DEFAULT_PAGE).clamp(1, MAX_PAGE);
+
+    let listing = match state.docker_container_id.get(&email).map(|r| r.clone()) {
+        Some(container_id) => {
+            list_visible(s, &state, &email, &container_id, &pwd, depth, payload.show_excluded).await
         }
-
-        // Filter Linux virtual filesystems only - let node_modules, .git etc. through
-        if matches!(filename.as_str(), "proc" | "sys" | "dev" | "run") {
-            continue;
+        None => Err(format!("No Docker container found for email: {}", email)),
+    };
+    let (entries, truncated, error) = match listing {
+        Ok((entries, truncated)) => (entries, truncated, None),
+        Err(e) => {
+            eprintln!("[repo] failed to list {} for {}: {}", pwd, email, e);
+            (Vec::new(), false, Some(e))
         }
+    };
 
-        let is_dir = permissions.starts_with('d');
-        items.push((filename, is_dir));
-    }
-
-    items.sort_by(|(a_name, a_dir), (b_name, b_dir)| b_dir.cmp(a_dir).then(a_name.cmp(b_name)));
+    let total = entries.len();
+    let end = payload.offset.saturating_add(limit).min(total);
+    let items = entries
+        .into_iter()
+        .skip(payload.offset)
+        .take(limit)
+        .collect();
+    s.emit(events::outgoing::REPO_STRUCTURE, &RepoStructurePayload {
+        current_directory: pwd,
+        items,
+        depth,
+        offset: payload.offset,
+        total,
+        next_offset: (end < total).then_some(end),
+        truncated,
+        error,
+    })
+    .ok();
 
-    Ok(items)
+    Ok(())
 }
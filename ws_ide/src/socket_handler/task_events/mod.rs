@@ -0,0 +1,2 @@
+pub mod run_task;
+pub use run_task::{handle_list_tasks, handle_run_task};
@@ -0,0 +1,116 @@
+use socketioxide::extract::SocketRef;
+
+use crate::{
+    events,
+    socket_handler::pseudo_terminal::pseudo_terminal,
+    state::{terminal_key, workspace_slug, AppState},
+    tasks::{load_tasks, TaskDefinition, TaskRun},
+    terminal_session::options::{
+        clamp_dimension, is_valid_env_name, TerminalOptions, DEFAULT_COLS, DEFAULT_ROWS,
+    },
+    types::{EmailPayload, RunTaskPayload, TaskErrorPayload, TaskStartedPayload, TasksPayload},
+};
+
+fn task_error(s: &SocketRef, task: Option<&str>, msg: String) -> std::io::Error {
+    s.emit(events::outgoing::TASK_ERROR, &TaskErrorPayload {
+        task: task.map(str::to_string),
+        message: msg.clone(),
+    })
+    .ok();
+    std::io::Error::other(msg)
+}
+
+async fn workspace_tasks(
+    s: &SocketRef,
+    state: &AppState,
+    email: &str,
+    task: Option<&str>,
+) -> Result<(String, Vec<TaskDefinition>), std::io::Error> {
+    let container_id = state
+        .docker_container_id
+        .get(email)
+        .map(|r| r.clone())
+        .ok_or_else(|| task_error(s, task, format!("No Docker container found for email: {}", email)))?;
+    let root = state.workspace_dir(email);
+
+    let tasks = load_tasks(state, email, &container_id, Some(&root))
+        .await
+        .map_err(|e| task_error(s, task, e))?;
+    Ok((container_id, tasks))
+}
+
+pub async fn handle_list_tasks(
+    s: &SocketRef,
+    state: AppState,
+    data: EmailPayload,
+) -> Result<(), std::io::Error> {
+    let (_, tasks) = workspace_tasks(s, &state, &data.email, None).await?;
+    s.emit(events::outgoing::TASKS, &TasksPayload { tasks }).ok();
+    Ok(())
+}
+
+/// Runs a named task in its own terminal. Output streams like any other
+/// terminal's; the task's problem matcher turns it into `task_diagnostics`.
+pub async fn handle_run_task(
+    s: &SocketRef,
+    state: AppState,
+    data: RunTaskPayload,
+) -> Result<(), std::io::Error> {
+    let (container_id, tasks) = workspace_tasks(s, &state, &data.email, Some(&data.name)).await?;
+    let task = tasks
+        .into_iter()
+        .find(|t| t.name == data.name)
+        .ok_or_else(|| task_error(s, Some(&data.name), format!("No task named {}", data.name)))?;
+
+    let terminal_id = data
+        .terminal_id
+        .clone()
+        .unwrap_or_else(|| format!("task-{}", workspace_slug(&task.name)));
+    // Held until the terminal is registered, so a second run of the same
+    // task can't start alongside it and orphan it.
+    let key = terminal_key(&data.email, &terminal_id);
+    let Some(_reservation) = state.terminals.reserve(&key) else {
+        return Err(task_error(
+            s,
+            Some(&task.name),
+            format!("Task {} is already running in terminal {}", task.name, terminal_id),
+        ));
+    };
+
+    if let Some(bad) = task.env.keys().find(|k| !is_valid_env_name(k)) {
+        return Err(task_error(
+            s,
+            Some(&task.name),
+            format!("Invalid environment variable name: {}", bad),
+        ));
+    }
+    let mut env = TerminalOptions::base_env();
+    env.extend(task.env.clone());
+    let options = TerminalOptions {
+        shell: "/bin/sh".to_string(),
+        shell_args: vec!["-c".to_string(), task.command.clone()],
+        workdir: task.cwd.clone(),
+        env,
+        rows: clamp_dimension(data.rows.map(i64::from), DEFAULT_ROWS),
+        cols: clamp_dimension(data.cols.map(i64::from), DEFAULT_COLS),
+        binary: false,
+        flow_control: data.flow_control,
+        task: Some(TaskRun {
+            name: task.name.clone(),
+            matcher: task.problem_matcher,
+            cwd: task.cwd.clone(),
+        }),
+    };
+
+    s.emit(events::outgoing::TASK_STARTED, &TaskStartedPayload {
+        terminal_id: terminal_id.clone(),
+        task: task.name.clone(),
+        command: task.command.clone(),
+    })
+    .ok();
+    println!("[tasks] {} running {} in {}", data.email, task.name, terminal_id);
+
+    pseudo_terminal(s, Some(container_id), state.clone(), data.email, terminal_id, options)
+        .await
+        .map_err(|e| task_error(s, Some(&task.name), format!("Failed to start task: {}", e)))
+}
@@ -0,0 +1,63 @@
+use socketioxide::extract::SocketRef;
+
+use crate::{
+    events,
+    state::{terminal_key, AppState},
+    types::{AttachTerminalPayload, TerminalRole, TerminalStatusPayload},
+};
+
+pub async fn handle_attach_terminal(
+    s: &SocketRef,
+    state: AppState,
+    data: AttachTerminalPayload,
+) -> Result<(), std::io::Error> {
+    let key = terminal_key(&data.email, &data.terminal_id);
+
+    let session = match state.terminals.get(&key) {
+        Some(session) => session,
+        None => {
+            let msg = format!("No live terminal session for key: {}", key);
+            s.emit(
+                events::outgoing::TERMINAL_ERROR,
+                &TerminalStatusPayload {
+                    terminal_id: data.terminal_id,
+                    message: msg.clone(),
+                },
+            )
+            .ok();
+            return Err(std::io::Error::new(std::io::ErrorKind::NotFound, msg));
+        }
+    };
+
+    // Attaching makes the socket an owner, so it must already be bound to
+    // the terminal's owner by `load_terminal`; the payload email alone
+    // proves nothing.
+    let bound = state.socket_mapping.get(&s.id).map(|email| email.clone());
+    if bound.as_deref() != Some(session.email.as_str()) {
+        let msg = "Only the terminal owner can attach to it; use join_terminal for shared terminals".to_string();
+        s.emit(
+            events::outgoing::TERMINAL_ERROR,
+            &TerminalStatusPayload {
+                terminal_id: data.terminal_id,
+                message: msg.clone(),
+            },
+        )
+        .ok();
+        return Err(std::io::Error::new(std::io::ErrorKind::PermissionDenied, msg));
+    }
+    state
+        .docker_container_id
+        .insert(session.email.clone(), session.container_id.clone());
+
+    s.emit(
+        events::outgoing::TERMINAL_ATTACHED,
+        &TerminalStatusPayload {
+            terminal_id: data.terminal_id,
+            message: "Reattached to running terminal session".to_string(),
+        },
+    )
+    .ok();
+    session.attach(s, &session.email, TerminalRole::Owner, data.flow_control);
+
+    Ok(())
+}
@@ -3,7 +3,7 @@ use socketioxide::extract::SocketRef;
 use crate::{
     events,
     state::{terminal_key, AppState},
-    types::{CloseTerminalPayload, TerminalStatusPayload},
+    types::{CloseTerminalPayload, TerminalProcessPayload, TerminalRole, TerminalStatusPayload},
 };
 
 pub async fn handle_close_terminal(
@@ -12,7 +12,50 @@ pub async fn handle_close_terminal(
     data: CloseTerminalPayload,
 ) -> Result<(), std::io::Error> {
     let key = terminal_key(&data.email, &data.terminal_id);
-    state.terminal_mapping.remove(&key);
+
+    let session = state.terminals.get(&key);
+    if let Some(session) = session.as_ref() {
+        if session.role(s.id) != Some(TerminalRole::Owner) {
+            let msg = "Only the terminal owner can close it".to_string();
+            s.emit(
+                events::outgoing::TERMINAL_ERROR,
+                &TerminalStatusPayload {
+                    terminal_id: data.terminal_id,
+                    message: msg.clone(),
+                },
+            )
+            .ok();
+            return Err(std::io::Error::new(std::io::ErrorKind::PermissionDenied, msg));
+        }
+    }
+
+    // Ask before killing whatever is still running; the client can confirm
+    // with `force`.
+    if let (Some(session), false) = (session.as_ref(), data.force) {
+        match session.refresh_foreground().await {
+            Ok(Some(process)) => {
+                s.emit(
+                    events::outgoing::TERMINAL_BUSY,
+                    &TerminalProcessPayload {
+                        terminal_id: data.terminal_id,
+                        pid: Some(process.pid),
+                        name: Some(process.name),
+                        command: Some(process.command),
+                    },
+                )
+                .ok();
+                return Ok(());
+            }
+            Ok(None) => {}
+            Err(e) => eprintln!("[session] {} foreground probe failed before close: {}", key, e),
+        }
+    }
+
+    // Members hear about the close from the session itself.
+    let is_member = session.is_some_and(|session| session.role(s.id).is_some());
+    if state.terminals.close(&key).await.is_some() && is_member {
+        return Ok(());
+    }
 
     s.emit(
         events::outgoing::TERMINAL_CLOSED,
@@ -0,0 +1,47 @@
+use socketioxide::extract::SocketRef;
+
+use crate::{
+    events,
+    state::{terminal_key, AppState},
+    types::{GetTerminalStatsPayload, TerminalAckPayload, TerminalStatusPayload},
+};
+
+pub async fn handle_terminal_ack(
+    s: &SocketRef,
+    state: AppState,
+    data: TerminalAckPayload,
+) -> Result<(), std::io::Error> {
+    let key = terminal_key(&data.email, &data.terminal_id);
+    // Acks for a terminal that has gone away are harmless; drop them quietly.
+    if let Some(session) = state.terminals.get(&key) {
+        session.ack(s.id, data.bytes);
+    }
+    Ok(())
+}
+
+pub async fn handle_terminal_stats(
+    s: &SocketRef,
+    state: AppState,
+    data: GetTerminalStatsPayload,
+) -> Result<(), std::io::Error> {
+    let key = terminal_key(&data.email, &data.terminal_id);
+
+    match state.terminals.get(&key) {
+        Some(session) => {
+            s.emit(events::outgoing::TERMINAL_STATS, &session.stats()).ok();
+            Ok(())
+        }
+        None => {
+            let msg = format!("No terminal found for key: {}", key);
+            s.emit(
+                events::outgoing::TERMINAL_ERROR,
+                &TerminalStatusPayload {
+                    terminal_id: data.terminal_id,
+                    message: msg.clone(),
+                },
+            )
+            .ok();
+            Err(std::io::Error::new(std::io::ErrorKind::NotFound, msg))
+        }
+    }
+}
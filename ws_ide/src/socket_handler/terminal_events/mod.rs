@@ -1,7 +1,23 @@
+pub mod attach_terminal;
 pub mod close_terminal;
+pub mod flow_control;
+pub mod recording;
+pub mod share_terminal;
+pub mod terminal_defaults;
 pub mod terminal_input;
 pub mod terminal_resize;
+pub mod terminal_signal;
 
+pub use attach_terminal::handle_attach_terminal;
 pub use close_terminal::handle_close_terminal;
+pub use flow_control::{handle_terminal_ack, handle_terminal_stats};
+pub use recording::{handle_start_recording, handle_stop_recording};
+pub use share_terminal::{
+    handle_join_terminal, handle_revoke_terminal_access, handle_share_terminal,
+};
+pub use terminal_defaults::{
+    handle_get_terminal_defaults, handle_list_shells, handle_set_terminal_defaults,
+};
 pub use terminal_input::handle_terminal_input;
 pub use terminal_resize::handle_terminal_resize;
+pub use terminal_signal::handle_terminal_signal;
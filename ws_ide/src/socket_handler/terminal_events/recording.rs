@@ -0,0 +1,96 @@
+use socketioxide::extract::SocketRef;
+use std::sync::Arc;
+
+use crate::{
+    events,
+    state::{terminal_key, AppState},
+    terminal_session::TerminalSession,
+    types::{RecordingPayload, RecordingStatusPayload, TerminalRole, TerminalStatusPayload},
+};
+
+/// The terminal `data` names, provided `s` is its owner.
+fn find_session(
+    s: &SocketRef,
+    state: &AppState,
+    data: &RecordingPayload,
+) -> Result<Arc<TerminalSession>, std::io::Error> {
+    let key = terminal_key(&data.email, &data.terminal_id);
+    let fail = |kind: std::io::ErrorKind, msg: String| {
+        s.emit(
+            events::outgoing::TERMINAL_ERROR,
+            &TerminalStatusPayload {
+                terminal_id: data.terminal_id.clone(),
+                message: msg.clone(),
+            },
+        )
+        .ok();
+        std::io::Error::new(kind, msg)
+    };
+    let session = state
+        .terminals
+        .get(&key)
+        .ok_or_else(|| fail(std::io::ErrorKind::NotFound, format!("No terminal found for key: {}", key)))?;
+    // A recording can hold the owner's keystrokes; viewers and anyone else
+    // naming the terminal may neither start nor stop one.
+    if session.role(s.id) != Some(TerminalRole::Owner) {
+        return Err(fail(
+            std::io::ErrorKind::PermissionDenied,
+            "Only the terminal owner can record it".to_string(),
+        ));
+    }
+    Ok(session)
+}
+
+pub async fn handle_start_recording(
+    s: &SocketRef,
+    state: AppState,
+    data: RecordingPayload,
+) -> Result<(), std::io::Error> {
+    let session = find_session(s, &state, &data)?;
+
+    match session.start_recording(data.record_input) {
+        Ok(name) => {
+            s.emit(
+                events::outgoing::RECORDING_STARTED,
+                &RecordingStatusPayload {
+                    terminal_id: data.terminal_id,
+                    name,
+                },
+            )
+            .ok();
+            Ok(())
+        }
+        Err(e) => {
+            s.emit(
+                events::outgoing::TERMINAL_ERROR,
+                &TerminalStatusPayload {
+                    terminal_id: data.terminal_id,
+                    message: format!("Failed to start recording: {}", e),
+                },
+            )
+            .ok();
+            Err(e)
+        }
+    }
+}
+
+pub async fn handle_stop_recording(
+    s: &SocketRef,
+    state: AppState,
+    data: RecordingPayload,
+) -> Result<(), std::io::Error> {
+    let session = find_session(s, &state, &data)?;
+
+    if let Some(name) = session.stop_recording() {
+        s.emit(
+            events::outgoing::RECORDING_STOPPED,
+            &RecordingStatusPayload {
+                terminal_id: data.terminal_id,
+                name,
+            },
+        )
+        .ok();
+    }
+
+    Ok(())
+}
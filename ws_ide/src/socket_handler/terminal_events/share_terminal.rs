@@ -0,0 +1,177 @@
+use socketioxide::extract::SocketRef;
+use std::sync::Arc;
+
+use crate::{
+    events,
+    state::{terminal_key, AppState},
+    terminal_session::TerminalSession,
+    types::{
+        JoinTerminalPayload, RevokeTerminalAccessPayload, ShareTerminalPayload,
+        TerminalAccessPayload, TerminalJoinedPayload, TerminalParticipantPayload, TerminalRole,
+        TerminalStatusPayload,
+    },
+};
+
+fn find_session(
+    s: &SocketRef,
+    state: &AppState,
+    email: &str,
+    terminal_id: &str,
+) -> Result<Arc<TerminalSession>, std::io::Error> {
+    let key = terminal_key(email, terminal_id);
+    state.terminals.get(&key).ok_or_else(|| {
+        let msg = format!("No live terminal session for key: {}", key);
+        s.emit(
+            events::outgoing::TERMINAL_ERROR,
+            &TerminalStatusPayload {
+                terminal_id: terminal_id.to_string(),
+                message: msg.clone(),
+            },
+        )
+        .ok();
+        std::io::Error::new(std::io::ErrorKind::NotFound, msg)
+    })
+}
+
+fn deny(s: &SocketRef, terminal_id: &str, msg: String) -> std::io::Error {
+    s.emit(
+        events::outgoing::TERMINAL_ERROR,
+        &TerminalStatusPayload {
+            terminal_id: terminal_id.to_string(),
+            message: msg.clone(),
+        },
+    )
+    .ok();
+    std::io::Error::new(std::io::ErrorKind::PermissionDenied, msg)
+}
+
+fn require_owner(s: &SocketRef, session: &TerminalSession) -> Result<(), std::io::Error> {
+    if session.role(s.id) == Some(TerminalRole::Owner) {
+        return Ok(());
+    }
+    Err(deny(
+        s,
+        &session.terminal_id,
+        "Only the terminal owner can change who has access".to_string(),
+    ))
+}
+
+/// Tells everyone in the room who is watching and who has access.
+async fn broadcast_access(s: &SocketRef, session: &TerminalSession) {
+    let to_payload = |(email, role): (String, TerminalRole)| TerminalParticipantPayload { email, role };
+    let payload = TerminalAccessPayload {
+        terminal_id: session.terminal_id.clone(),
+        owner: session.email.clone(),
+        participants: session
+            .members()
+            .into_iter()
+            .map(|m| (m.email, m.role))
+            .map(to_payload)
+            .collect(),
+        grants: session.grants().into_iter().map(to_payload).collect(),
+    };
+    s.within(session.room())
+        .emit(events::outgoing::TERMINAL_ACCESS, &payload)
+        .await
+        .ok();
+}
+
+pub async fn handle_share_terminal(
+    s: &SocketRef,
+    state: AppState,
+    data: ShareTerminalPayload,
+) -> Result<(), std::io::Error> {
+    let session = find_session(s, &state, &data.email, &data.terminal_id)?;
+    require_owner(s, &session)?;
+
+    if data.role == TerminalRole::Owner || data.user == session.email {
+        return Err(deny(
+            s,
+            &data.terminal_id,
+            "A terminal can only be shared as viewer or editor".to_string(),
+        ));
+    }
+
+    session.grant(&data.user, data.role);
+    println!("[session] {} shared with {} as {:?}", session.room(), data.user, data.role);
+    broadcast_access(s, &session).await;
+    Ok(())
+}
+
+pub async fn handle_revoke_terminal_access(
+    s: &SocketRef,
+    state: AppState,
+    data: RevokeTerminalAccessPayload,
+) -> Result<(), std::io::Error> {
+    let session = find_session(s, &state, &data.email, &data.terminal_id)?;
+    require_owner(s, &session)?;
+
+    if data.user == session.email {
+        return Err(deny(
+            s,
+            &data.terminal_id,
+            "The owner's access cannot be revoked".to_string(),
+        ));
+    }
+
+    for removed in session.revoke(&data.user) {
+        removed
+            .emit(
+                events::outgoing::TERMINAL_ACCESS_REVOKED,
+                &TerminalStatusPayload {
+                    terminal_id: data.terminal_id.clone(),
+                    message: format!("{} revoked your access to this terminal", session.email),
+                },
+            )
+            .ok();
+    }
+    println!("[session] {} revoked for {}", session.room(), data.user);
+    broadcast_access(s, &session).await;
+    Ok(())
+}
+
+pub async fn handle_join_terminal(
+    s: &SocketRef,
+    state: AppState,
+    data: JoinTerminalPayload,
+) -> Result<(), std::io::Error> {
+    let session = find_session(s, &state, &data.owner, &data.terminal_id)?;
+
+    // The payload's email is whatever the client claims; grants are checked
+    // against the identity this socket loaded its own workspace with.
+    let email = state
+        .socket_mapping
+        .get(&s.id)
+        .map(|email| email.clone())
+        .ok_or_else(|| {
+            deny(
+                s,
+                &data.terminal_id,
+                "Open your own workspace before joining a shared terminal".to_string(),
+            )
+        })?;
+    if email != data.email {
+        println!("[session] {} tried to join {} as {}", email, session.room(), data.email);
+    }
+
+    let role = session.grant_for(&email).ok_or_else(|| {
+        deny(
+            s,
+            &data.terminal_id,
+            format!("{} has not shared this terminal with you", data.owner),
+        )
+    })?;
+
+    s.emit(
+        events::outgoing::TERMINAL_JOINED,
+        &TerminalJoinedPayload {
+            terminal_id: data.terminal_id.clone(),
+            owner: data.owner.clone(),
+            role,
+        },
+    )
+    .ok();
+    session.attach(s, &email, role, data.flow_control);
+    broadcast_access(s, &session).await;
+    Ok(())
+}
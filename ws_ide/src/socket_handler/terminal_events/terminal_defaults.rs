@@ -0,0 +1,125 @@
+use sea_orm::{sea_query::OnConflict, ColumnTrait, EntityTrait, QueryFilter, Set};
+use socketioxide::extract::SocketRef;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::{
+    entities::{terminal_preferences, users},
+    events,
+    state::AppState,
+    terminal_session::options::{available_shells, is_valid_env_name, load_user_defaults},
+    types::{EmailPayload, SetTerminalDefaultsPayload, ShellsPayload, TerminalDefaultsPayload},
+};
+
+fn to_payload(model: Option<terminal_preferences::Model>) -> TerminalDefaultsPayload {
+    match model {
+        Some(m) => TerminalDefaultsPayload {
+            shell: m.shell,
+            cwd: m.cwd,
+            env: serde_json::from_value(m.env).unwrap_or_default(),
+            rows: m.rows.and_then(|v| u16::try_from(v).ok()),
+            cols: m.cols.and_then(|v| u16::try_from(v).ok()),
+        },
+        None => TerminalDefaultsPayload {
+            shell: None,
+            cwd: None,
+            env: HashMap::new(),
+            rows: None,
+            cols: None,
+        },
+    }
+}
+
+fn db_error(s: &SocketRef, e: sea_orm::DbErr) -> std::io::Error {
+    let msg = format!("Database error: {}", e);
+    s.emit(events::outgoing::SETTINGS_ERROR, &msg).ok();
+    std::io::Error::other(msg)
+}
+
+pub async fn handle_get_terminal_defaults(
+    s: &SocketRef,
+    state: AppState,
+    data: EmailPayload,
+) -> Result<(), std::io::Error> {
+    let defaults = load_user_defaults(&state, &data.email)
+        .await
+        .map_err(|e| db_error(s, e))?;
+    s.emit(events::outgoing::TERMINAL_DEFAULTS, &to_payload(defaults)).ok();
+    Ok(())
+}
+
+pub async fn handle_set_terminal_defaults(
+    s: &SocketRef,
+    state: AppState,
+    data: SetTerminalDefaultsPayload,
+) -> Result<(), std::io::Error> {
+    if let Some(bad) = data.env.keys().find(|k| !is_valid_env_name(k)) {
+        let msg = format!("Invalid environment variable name: {}", bad);
+        s.emit(events::outgoing::SETTINGS_ERROR, &msg).ok();
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, msg));
+    }
+
+    let user = users::Entity::find()
+        .filter(users::Column::Email.eq(data.email.clone()))
+        .one(&*state.db)
+        .await
+        .map_err(|e| db_error(s, e))?
+        .ok_or_else(|| {
+            let msg = format!("No user found with email: {}", data.email);
+            s.emit(events::outgoing::SETTINGS_ERROR, &msg).ok();
+            std::io::Error::new(std::io::ErrorKind::NotFound, msg)
+        })?;
+
+    let row = terminal_preferences::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        user_id: Set(user.id),
+        shell: Set(data.shell),
+        cwd: Set(data.cwd),
+        env: Set(serde_json::json!(data.env)),
+        rows: Set(data.rows.map(i64::from)),
+        cols: Set(data.cols.map(i64::from)),
+        ..Default::default()
+    };
+
+    terminal_preferences::Entity::insert(row)
+        .on_conflict(
+            OnConflict::column(terminal_preferences::Column::UserId)
+                .update_columns([
+                    terminal_preferences::Column::Shell,
+                    terminal_preferences::Column::Cwd,
+                    terminal_preferences::Column::Env,
+                    terminal_preferences::Column::Rows,
+                    terminal_preferences::Column::Cols,
+                ])
+                .to_owned(),
+        )
+        .exec(&*state.db)
+        .await
+        .map_err(|e| db_error(s, e))?;
+
+    let defaults = load_user_defaults(&state, &data.email)
+        .await
+        .map_err(|e| db_error(s, e))?;
+    s.emit(events::outgoing::TERMINAL_DEFAULTS, &to_payload(defaults)).ok();
+    Ok(())
+}
+
+pub async fn handle_list_shells(
+    s: &SocketRef,
+    state: AppState,
+    data: EmailPayload,
+) -> Result<(), std::io::Error> {
+    let container_id = state
+        .docker_container_id
+        .get(&data.email)
+        .map(|r| r.clone())
+        .ok_or_else(|| {
+            let msg = format!("No Docker container found for email: {}", data.email);
+            s.emit(events::outgoing::SETTINGS_ERROR, &msg).ok();
+            std::io::Error::new(std::io::ErrorKind::NotFound, msg)
+        })?;
+
+    let shells = available_shells(&container_id).await?;
+    s.emit(events::outgoing::SHELLS, &ShellsPayload { shells }).ok();
+    Ok(())
+}
@@ -14,21 +14,36 @@ pub async fn handle_terminal_input(
     let key = terminal_key(&data.email, &data.terminal_id);
     let input_data = data.data;
 
-    let file = state
-        .terminal_mapping
-        .get(&key)
-        .map(|f| f.try_clone())
-        .transpose()?;
+    let session = state.terminals.get(&key);
 
-    match file {
-        Some(mut f) => {
+    match session {
+        Some(session) => {
+            if !session.can_write(s.id) {
+                let msg = "This terminal is shared with you read-only".to_string();
+                s.emit(
+                    events::outgoing::TERMINAL_ERROR,
+                    &TerminalStatusPayload {
+                        terminal_id: data.terminal_id,
+                        message: msg.clone(),
+                    },
+                )
+                .ok();
+                return Err(std::io::Error::new(std::io::ErrorKind::PermissionDenied, msg));
+            }
+            let mut f = session.master()?;
+            session.record_input(&input_data);
+            // Enter may start or end a command; shells without the OSC 133
+            // integration give no other sign of it.
+            if input_data.contains('\r') {
+                session.hint_foreground();
+            }
             tokio::task::spawn_blocking(move || {
                 use std::io::Write;
                 f.write_all(input_data.as_bytes())?;
                 f.flush()
             })
             .await
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))??;
+            .map_err(std::io::Error::other)??;
         }
         None => {
             let msg = format!("No terminal found for key: {}", key);
@@ -16,14 +16,15 @@ pub async fn handle_terminal_resize(
 ) -> Result<(), std::io::Error> {
     let key = terminal_key(&data.email, &data.terminal_id);
 
-    let file = state
-        .terminal_mapping
-        .get(&key)
-        .map(|f| f.try_clone())
-        .transpose()?;
+    let session = state.terminals.get(&key);
 
-    match file {
-        Some(f) => {
+    match session {
+        Some(session) => {
+            // Viewers follow the driver's size rather than setting their own.
+            if !session.can_write(s.id) {
+                return Ok(());
+            }
+            let f = session.master()?;
             let winsize = Winsize {
                 ws_row: data.rows,
                 ws_col: data.cols,
@@ -31,21 +32,22 @@ pub async fn handle_terminal_resize(
                 ws_ypixel: 0,
             };
             let fd = f.as_raw_fd();
-            unsafe {
-                let result = libc::ioctl(fd, libc::TIOCSWINSZ, &winsize as *const Winsize);
-                if result != 0 {
-                    let msg = format!("Failed to resize terminal: errno {}", result);
-                    s.emit(
-                        events::outgoing::TERMINAL_ERROR,
-                        &TerminalStatusPayload {
-                            terminal_id: data.terminal_id,
-                            message: msg.clone(),
-                        },
-                    )
-                    .ok();
-                    return Err(std::io::Error::new(std::io::ErrorKind::Other, msg));
-                }
+            let result = unsafe { libc::ioctl(fd, libc::TIOCSWINSZ, &winsize as *const Winsize) };
+            if result != 0 {
+                // The ioctl only returns -1; the reason is in errno.
+                let err = std::io::Error::last_os_error();
+                let msg = format!("Failed to resize terminal: {}", err);
+                s.emit(
+                    events::outgoing::TERMINAL_ERROR,
+                    &TerminalStatusPayload {
+                        terminal_id: data.terminal_id,
+                        message: msg.clone(),
+                    },
+                )
+                .ok();
+                return Err(std::io::Error::new(err.kind(), msg));
             }
+            session.resized(data.cols, data.rows);
         }
         None => {
             let msg = format!("No terminal found for key: {}", key);
@@ -0,0 +1,68 @@
+use socketioxide::extract::SocketRef;
+
+use crate::{
+    events,
+    state::{terminal_key, AppState},
+    types::{TerminalSignal, TerminalSignalPayload, TerminalSignalResultPayload},
+};
+
+pub async fn handle_terminal_signal(
+    s: &SocketRef,
+    state: AppState,
+    data: TerminalSignalPayload,
+) -> Result<(), std::io::Error> {
+    let key = terminal_key(&data.email, &data.terminal_id);
+    let reply = |delivered: bool, pid: Option<i32>, command: Option<String>, message: String| {
+        s.emit(
+            events::outgoing::TERMINAL_SIGNAL_RESULT,
+            &TerminalSignalResultPayload {
+                terminal_id: data.terminal_id.clone(),
+                signal: data.signal.clone(),
+                delivered,
+                pid,
+                command,
+                message,
+            },
+        )
+        .ok();
+    };
+
+    let Some(signal) = TerminalSignal::parse(&data.signal) else {
+        let msg = format!(
+            "Unsupported signal {:?}; use SIGINT, SIGTERM, SIGKILL, SIGTSTP or SIGHUP",
+            data.signal
+        );
+        reply(false, None, None, msg.clone());
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, msg));
+    };
+
+    let Some(session) = state.terminals.get(&key) else {
+        let msg = format!("No terminal found for key: {}", key);
+        reply(false, None, None, msg.clone());
+        return Err(std::io::Error::new(std::io::ErrorKind::NotFound, msg));
+    };
+
+    if !session.can_write(s.id) {
+        let msg = "This terminal is shared with you read-only".to_string();
+        reply(false, None, None, msg.clone());
+        return Err(std::io::Error::new(std::io::ErrorKind::PermissionDenied, msg));
+    }
+
+    match session.signal_foreground(signal).await {
+        Ok(Some(process)) => {
+            println!("[session] {} sent SIG{} to {} ({})", key, signal.name(), process.pid, process.command);
+            reply(
+                true,
+                Some(process.pid),
+                Some(process.command),
+                format!("Sent SIG{} to {}", signal.name(), process.name),
+            );
+        }
+        Ok(None) => reply(false, None, None, "No command is running in this terminal".to_string()),
+        Err(e) => {
+            reply(false, None, None, format!("Failed to send SIG{}: {}", signal.name(), e));
+            return Err(e);
+        }
+    }
+    Ok(())
+}
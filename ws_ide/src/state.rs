@@ -1,32 +1,70 @@
 use dashmap::DashMap;
 use sea_orm::DatabaseConnection;
 use socketioxide::socket::Sid;
-use std::fs::File;
 use std::sync::Arc;
+use tokio::sync::watch;
+
+use crate::docker_vm::provision::ProvisionFlight;
+use crate::files::{transfer::TransferManager, watcher::WatchManager};
+use crate::jobs::JobManager;
+use crate::terminal_session::registry::TerminalRegistry;
 
 pub fn terminal_key(email: &str, terminal_id: &str) -> String {
     format!("{}:{}", email, terminal_id)
 }
 
+/// Filesystem- and Docker-safe form of an identifier, e.g. for container names.
+pub fn workspace_slug(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '-' })
+        .collect()
+}
+
+/// Where a workspace without a cloned repo opens: the container's own
+/// working directory, as the image leaves it.
+pub const DEFAULT_WORKSPACE_DIR: &str = "/";
+
 #[derive(Clone)]
 pub struct AppState {
     pub db: Arc<DatabaseConnection>,
-    pub terminal_mapping: Arc<DashMap<String, File>>,
-    pub back_terminal_mapping: Arc<DashMap<String, File>>,
+    pub terminals: Arc<TerminalRegistry>,
+    pub jobs: Arc<JobManager>,
+    pub transfers: Arc<TransferManager>,
+    pub watchers: Arc<WatchManager>,
     pub socket_mapping: Arc<DashMap<Sid, String>>,
     pub email_mapping: Arc<DashMap<String, Sid>>,
     pub docker_container_id: Arc<DashMap<String, String>>,
+    pub workspace_root: Arc<DashMap<String, String>>,
+    pub provisioning: Arc<DashMap<String, Arc<ProvisionFlight>>>,
+    /// Workspaces whose onCreate / onStart hooks are still running; flips to
+    /// `true` once they're done. `onAttach` waits on it.
+    pub setup_hooks: Arc<DashMap<String, watch::Receiver<bool>>>,
 }
 
 impl AppState {
     pub fn new(db: DatabaseConnection) -> Self {
         Self {
             db: Arc::new(db),
-            terminal_mapping: Arc::new(DashMap::new()),
-            back_terminal_mapping: Arc::new(DashMap::new()),
+            terminals: Arc::new(TerminalRegistry::default()),
+            jobs: Arc::new(JobManager::default()),
+            transfers: Arc::new(TransferManager::default()),
+            watchers: Arc::new(WatchManager::default()),
             socket_mapping: Arc::new(DashMap::new()),
             email_mapping: Arc::new(DashMap::new()),
             docker_container_id: Arc::new(DashMap::new()),
+            workspace_root: Arc::new(DashMap::new()),
+            provisioning: Arc::new(DashMap::new()),
+            setup_hooks: Arc::new(DashMap::new()),
         }
     }
+
+    /// The directory `email`'s workspace opens on: the cloned repo, or
+    /// `DEFAULT_WORKSPACE_DIR` when nothing was cloned.
+    pub fn workspace_dir(&self, email: &str) -> String {
+        self.workspace_root
+            .get(email)
+            .map(|r| r.clone())
+            .unwrap_or_else(|| DEFAULT_WORKSPACE_DIR.to_string())
+    }
 }
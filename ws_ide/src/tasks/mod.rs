@@ -0,0 +1,161 @@
+pub mod problem_matcher;
+
+use sea_orm::{ColumnTrait, EntityTrait, Order, QueryFilter, QueryOrder};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::{
+    docker_vm::exec::exec_output,
+    entities::{users, workspace_tasks},
+    state::AppState,
+};
+
+use self::problem_matcher::MatcherKind;
+
+/// Per-workspace task file, relative to the workspace root.
+pub const TASKS_FILE: &str = ".aks/tasks.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskSource {
+    File,
+    Database,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskDefinition {
+    pub name: String,
+    pub command: String,
+    pub cwd: Option<String>,
+    pub env: HashMap<String, String>,
+    pub problem_matcher: Option<MatcherKind>,
+    pub source: TaskSource,
+}
+
+/// A task running in a terminal, as seen by the terminal's read loop.
+#[derive(Debug, Clone)]
+pub struct TaskRun {
+    pub name: String,
+    pub matcher: Option<MatcherKind>,
+    pub cwd: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TasksFile {
+    #[serde(default)]
+    tasks: Vec<TaskFileEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TaskFileEntry {
+    name: String,
+    command: String,
+    #[serde(default)]
+    cwd: Option<String>,
+    #[serde(default)]
+    env: HashMap<String, String>,
+    #[serde(default, alias = "problemMatcher")]
+    problem_matcher: Option<String>,
+}
+
+fn parse_matcher(task: &str, name: Option<&str>) -> Option<MatcherKind> {
+    let name = name?;
+    let kind = MatcherKind::parse(name);
+    if kind.is_none() {
+        eprintln!("[tasks] unknown problem matcher {:?} for task {}", name, task);
+    }
+    kind
+}
+
+/// Relative task directories are taken from the workspace root.
+fn resolve_cwd(root: Option<&str>, cwd: Option<String>) -> Option<String> {
+    match (root, cwd) {
+        (Some(root), Some(cwd)) if !cwd.starts_with('/') => Some(format!(
+            "{}/{}",
+            root.trim_end_matches('/'),
+            cwd.trim_start_matches("./")
+        )),
+        (root, cwd) => cwd.or_else(|| root.map(str::to_string)),
+    }
+}
+
+async fn load_db_tasks(state: &AppState, email: &str) -> Result<Vec<TaskDefinition>, sea_orm::DbErr> {
+    let user = match users::Entity::find()
+        .filter(users::Column::Email.eq(email))
+        .one(&*state.db)
+        .await?
+    {
+        Some(user) => user,
+        None => return Ok(Vec::new()),
+    };
+
+    let rows = workspace_tasks::Entity::find()
+        .filter(workspace_tasks::Column::UserId.eq(user.id))
+        .filter(workspace_tasks::Column::DeletedAt.is_null())
+        .order_by(workspace_tasks::Column::Position, Order::Asc)
+        .all(&*state.db)
+        .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| TaskDefinition {
+            problem_matcher: parse_matcher(&row.name, row.problem_matcher.as_deref()),
+            name: row.name,
+            command: row.command,
+            cwd: row.cwd,
+            env: HashMap::new(),
+            source: TaskSource::Database,
+        })
+        .collect())
+}
+
+async fn load_file_tasks(container_id: &str, root: &str) -> Result<Vec<TaskDefinition>, String> {
+    let path = format!("{}/{}", root.trim_end_matches('/'), TASKS_FILE);
+    let (code, content) = exec_output(container_id, &["cat", &path], None)
+        .await
+        .map_err(|e| format!("Failed to read {}: {}", TASKS_FILE, e))?;
+    if code != 0 {
+        // No task file in this workspace.
+        return Ok(Vec::new());
+    }
+
+    let file: TasksFile =
+        serde_json::from_str(&content).map_err(|e| format!("Invalid {}: {}", TASKS_FILE, e))?;
+    Ok(file
+        .tasks
+        .into_iter()
+        .map(|task| TaskDefinition {
+            problem_matcher: parse_matcher(&task.name, task.problem_matcher.as_deref()),
+            cwd: resolve_cwd(Some(root), task.cwd),
+            name: task.name,
+            command: task.command,
+            env: task.env,
+            source: TaskSource::File,
+        })
+        .collect())
+}
+
+/// Tasks for `email`'s workspace: those in `.aks/tasks.json` first, then the
+/// ones stored in the DB that the file doesn't override by name.
+pub async fn load_tasks(
+    state: &AppState,
+    email: &str,
+    container_id: &str,
+    workspace_root: Option<&str>,
+) -> Result<Vec<TaskDefinition>, String> {
+    let mut tasks = match workspace_root {
+        Some(root) => load_file_tasks(container_id, root).await?,
+        None => Vec::new(),
+    };
+
+    let stored = load_db_tasks(state, email)
+        .await
+        .map_err(|e| format!("Failed to load tasks: {}", e))?;
+    for mut task in stored {
+        if !tasks.iter().any(|t| t.name == task.name) {
+            task.cwd = resolve_cwd(workspace_root, task.cwd);
+            tasks.push(task);
+        }
+    }
+    Ok(tasks)
+}
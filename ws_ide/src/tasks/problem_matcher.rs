@@ -0,0 +1,259 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::types::{Diagnostic, DiagnosticSeverity};
+
+/// A partial line longer than this is discarded rather than buffered.
+const MAX_LINE_BYTES: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MatcherKind {
+    Rustc,
+    Tsc,
+    Gcc,
+    Eslint,
+}
+
+impl MatcherKind {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().trim_start_matches('$').to_ascii_lowercase().as_str() {
+            "rustc" | "rust" | "cargo" => Some(Self::Rustc),
+            "tsc" | "typescript" => Some(Self::Tsc),
+            "gcc" | "clang" => Some(Self::Gcc),
+            "eslint" | "eslint-stylish" => Some(Self::Eslint),
+            _ => None,
+        }
+    }
+}
+
+fn severity(value: &str) -> DiagnosticSeverity {
+    match value.to_ascii_lowercase().as_str() {
+        "error" | "fatal error" => DiagnosticSeverity::Error,
+        "warning" => DiagnosticSeverity::Warning,
+        _ => DiagnosticSeverity::Info,
+    }
+}
+
+/// Turns a task's terminal output into diagnostics, line by line. Multi-line
+/// formats (rustc's `-->` locations, eslint's per-file blocks) keep their
+/// state between lines.
+pub struct ProblemMatcher {
+    kind: MatcherKind,
+    /// Relative paths in the output are resolved against this.
+    cwd: Option<String>,
+    line: String,
+    ansi: Regex,
+    patterns: Vec<Regex>,
+    /// rustc: the `error[E0308]: message` line waiting for its location.
+    pending: Option<(DiagnosticSeverity, Option<String>, String)>,
+    /// eslint: the file the current block of problems belongs to.
+    current_file: Option<String>,
+}
+
+impl ProblemMatcher {
+    pub fn new(kind: MatcherKind, cwd: Option<String>) -> Self {
+        let patterns: &[&str] = match kind {
+            MatcherKind::Rustc => &[
+                r"^(error|warning)(?:\[(E\d+)\])?: (.+)$",
+                r"^\s*--> (.+?):(\d+):(\d+)$",
+            ],
+            MatcherKind::Tsc => &[
+                r"^(.+?)\((\d+),(\d+)\): (error|warning) (TS\d+): (.+)$",
+                r"^(.+?):(\d+):(\d+) - (error|warning) (TS\d+): (.+)$",
+            ],
+            MatcherKind::Gcc => &[
+                r"^(.+?):(\d+):(\d+): (fatal error|error|warning|note): (.+?)(?: \[(-W[^\]]+)\])?$",
+            ],
+            MatcherKind::Eslint => &[
+                r"^\s+(\d+):(\d+)\s+(error|warning)\s+(.+?)(?:\s{2,}(\S+))?$",
+                r"^(.+?):(\d+):(\d+): (.+) \[(Error|Warning)(?:/(.+))?\]$",
+            ],
+        };
+        Self {
+            kind,
+            cwd,
+            line: String::new(),
+            ansi: Regex::new(r"\x1b\[[0-9;?]*[A-Za-z]|\x1b\][^\x07\x1b]*(?:\x07|\x1b\\)").unwrap(),
+            patterns: patterns.iter().map(|p| Regex::new(p).unwrap()).collect(),
+            pending: None,
+            current_file: None,
+        }
+    }
+
+    /// Feeds decoded output. Returns the diagnostics completed by it.
+    pub fn feed(&mut self, text: &str) -> Vec<Diagnostic> {
+        let mut found = Vec::new();
+        for (i, part) in text.split('\n').enumerate() {
+            if i > 0 {
+                let line = std::mem::take(&mut self.line);
+                found.extend(self.match_line(&line));
+            }
+            if self.line.len() + part.len() > MAX_LINE_BYTES {
+                self.line.clear();
+            } else {
+                self.line.push_str(part);
+            }
+        }
+        found
+    }
+
+    /// Matches whatever is left once the task's output has ended.
+    pub fn finish(&mut self) -> Vec<Diagnostic> {
+        let line = std::mem::take(&mut self.line);
+        self.match_line(&line).into_iter().collect()
+    }
+
+    fn resolve(&self, file: &str) -> String {
+        match &self.cwd {
+            Some(cwd) if !file.starts_with('/') => {
+                format!("{}/{}", cwd.trim_end_matches('/'), file.trim_start_matches("./"))
+            }
+            _ => file.to_string(),
+        }
+    }
+
+    fn diagnostic(
+        &self,
+        file: &str,
+        line: &str,
+        column: Option<&str>,
+        severity: DiagnosticSeverity,
+        message: &str,
+        code: Option<&str>,
+    ) -> Option<Diagnostic> {
+        Some(Diagnostic {
+            file: self.resolve(file.trim()),
+            line: line.parse().ok()?,
+            column: column.and_then(|c| c.parse().ok()),
+            severity,
+            message: message.trim().to_string(),
+            code: code.map(str::to_string),
+        })
+    }
+
+    fn match_line(&mut self, raw: &str) -> Option<Diagnostic> {
+        // Progress bars redraw with bare carriage returns; keep the last frame.
+        let raw = raw.trim_end_matches('\r');
+        let raw = raw.rsplit('\r').next().unwrap_or(raw);
+        let line = self.ansi.replace_all(raw, "").into_owned();
+        if line.trim().is_empty() {
+            return None;
+        }
+
+        match self.kind {
+            MatcherKind::Rustc => {
+                if let Some(c) = self.patterns[0].captures(&line) {
+                    self.pending = Some((
+                        severity(&c[1]),
+                        c.get(2).map(|m| m.as_str().to_string()),
+                        c[3].to_string(),
+                    ));
+                    return None;
+                }
+                let c = self.patterns[1].captures(&line)?;
+                let (sev, code, message) = self.pending.take()?;
+                self.diagnostic(&c[1], &c[2], Some(&c[3]), sev, &message, code.as_deref())
+            }
+            MatcherKind::Tsc => {
+                let c = self.patterns.iter().find_map(|p| p.captures(&line))?;
+                self.diagnostic(&c[1], &c[2], Some(&c[3]), severity(&c[4]), &c[6], Some(&c[5]))
+            }
+            MatcherKind::Gcc => {
+                let c = self.patterns[0].captures(&line)?;
+                let code = c.get(6).map(|m| m.as_str());
+                self.diagnostic(&c[1], &c[2], Some(&c[3]), severity(&c[4]), &c[5], code)
+            }
+            MatcherKind::Eslint => {
+                if let Some(c) = self.patterns[1].captures(&line) {
+                    let code = c.get(6).map(|m| m.as_str());
+                    return self.diagnostic(&c[1], &c[2], Some(&c[3]), severity(&c[5]), &c[4], code);
+                }
+                if let Some(c) = self.patterns[0].captures(&line) {
+                    let file = self.current_file.clone()?;
+                    let code = c.get(5).map(|m| m.as_str());
+                    return self.diagnostic(&file, &c[1], Some(&c[2]), severity(&c[3]), &c[4], code);
+                }
+                // Stylish output names each file on an unindented line
+                // before listing its problems.
+                if !line.starts_with(char::is_whitespace) && !line.starts_with('\u{2716}') {
+                    self.current_file = Some(line.trim().to_string());
+                }
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matcher(kind: MatcherKind) -> ProblemMatcher {
+        ProblemMatcher::new(kind, Some("/workspace".to_string()))
+    }
+
+    #[test]
+    fn rustc_waits_for_the_location_line() {
+        let mut m = matcher(MatcherKind::Rustc);
+        assert!(m.feed("error[E0308]: mismatched types\n").is_empty());
+        let found = m.feed("  --> src/main.rs:4:13\n");
+        assert_eq!(found.len(), 1);
+        let d = &found[0];
+        assert_eq!(d.file, "/workspace/src/main.rs");
+        assert_eq!((d.line, d.column), (4, Some(13)));
+        assert_eq!(d.severity, DiagnosticSeverity::Error);
+        assert_eq!(d.message, "mismatched types");
+        assert_eq!(d.code.as_deref(), Some("E0308"));
+    }
+
+    #[test]
+    fn rustc_location_is_used_once() {
+        let mut m = matcher(MatcherKind::Rustc);
+        let found = m.feed("warning: unused variable: `x`\n --> src/lib.rs:2:9\n  ::: src/other.rs:1:1\n --> src/lib.rs:8:1\n");
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].severity, DiagnosticSeverity::Warning);
+        assert_eq!(found[0].code, None);
+        assert_eq!(found[0].line, 2);
+    }
+
+    #[test]
+    fn rustc_header_split_across_chunks() {
+        let mut m = matcher(MatcherKind::Rustc);
+        assert!(m.feed("error: cannot find value `y` in th").is_empty());
+        assert!(m.feed("is scope\n --> src/ma").is_empty());
+        let found = m.feed("in.rs:10:5\n");
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].message, "cannot find value `y` in this scope");
+        assert_eq!(found[0].file, "/workspace/src/main.rs");
+    }
+
+    #[test]
+    fn eslint_problems_belong_to_the_current_file() {
+        let mut m = matcher(MatcherKind::Eslint);
+        let found = m.feed(concat!(
+            "/workspace/src/a.js\n",
+            "  1:7  error  'x' is assigned a value but never used  no-unused-vars\n",
+            "  3:1  warning  Unexpected console statement  no-console\n",
+            "\n",
+            "src/b.js\n",
+            "  10:2  error  Missing semicolon  semi\n",
+            "\n",
+            "\u{2716} 3 problems (2 errors, 1 warning)\n",
+        ));
+        assert_eq!(found.len(), 3);
+        assert_eq!(found[0].file, "/workspace/src/a.js");
+        assert_eq!((found[0].line, found[0].column), (1, Some(7)));
+        assert_eq!(found[0].code.as_deref(), Some("no-unused-vars"));
+        assert_eq!(found[1].severity, DiagnosticSeverity::Warning);
+        assert_eq!(found[1].file, "/workspace/src/a.js");
+        assert_eq!(found[2].file, "/workspace/src/b.js");
+        assert_eq!(found[2].message, "Missing semicolon");
+    }
+
+    #[test]
+    fn eslint_problem_without_a_file_is_dropped() {
+        let mut m = matcher(MatcherKind::Eslint);
+        assert!(m.feed("  1:1  error  Parsing error  \n").is_empty());
+    }
+}
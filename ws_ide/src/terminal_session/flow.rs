@@ -0,0 +1,90 @@
+use std::time::{Duration, Instant};
+
+use crate::types::TerminalStatsPayload;
+
+/// Reads arriving within this window are coalesced into one message.
+pub const BATCH_WINDOW: Duration = Duration::from_millis(8);
+
+/// A batch is flushed early once it reaches this size.
+pub const BATCH_BYTES: usize = 64 * 1024;
+
+/// Unacknowledged bytes a flow-controlled socket may have outstanding before
+/// the PTY is paused (owner/editor) or its output is dropped (viewer).
+pub const HIGH_WATERMARK: usize = 512 * 1024;
+
+/// A paused PTY resumes once every driver is back under this.
+pub const LOW_WATERMARK: usize = 128 * 1024;
+
+/// Per-socket delivery state.
+#[derive(Debug, Clone, Default)]
+pub struct MemberFlow {
+    /// The client sends `terminal_ack` for what it has rendered.
+    pub acks: bool,
+    pub unacked: usize,
+    /// Bytes skipped since the last successful send, reported with a marker.
+    pub dropped: usize,
+}
+
+impl MemberFlow {
+    pub fn new(acks: bool) -> Self {
+        Self { acks, ..Default::default() }
+    }
+
+    pub fn over_cap(&self) -> bool {
+        self.acks && self.unacked >= HIGH_WATERMARK
+    }
+}
+
+pub fn dropped_marker(bytes: usize) -> String {
+    format!("\r\n\x1b[2m[ws_ide: {} bytes of output dropped]\x1b[0m\r\n", bytes)
+}
+
+/// Output counters for one terminal.
+#[derive(Debug)]
+pub struct OutputStats {
+    started_at: Instant,
+    pub bytes_read: u64,
+    pub batches: u64,
+    pub bytes_sent: u64,
+    pub messages_sent: u64,
+    pub bytes_dropped: u64,
+    pub pauses: u64,
+    pub paused: Duration,
+}
+
+impl Default for OutputStats {
+    fn default() -> Self {
+        Self {
+            started_at: Instant::now(),
+            bytes_read: 0,
+            batches: 0,
+            bytes_sent: 0,
+            messages_sent: 0,
+            bytes_dropped: 0,
+            pauses: 0,
+            paused: Duration::ZERO,
+        }
+    }
+}
+
+impl OutputStats {
+    pub fn to_payload(&self, terminal_id: &str) -> TerminalStatsPayload {
+        let uptime = self.started_at.elapsed();
+        let throughput_bps = match uptime.as_millis() {
+            0 => 0,
+            ms => (self.bytes_read as u128 * 1000 / ms) as u64,
+        };
+        TerminalStatsPayload {
+            terminal_id: terminal_id.to_string(),
+            bytes_read: self.bytes_read,
+            batches: self.batches,
+            bytes_sent: self.bytes_sent,
+            messages_sent: self.messages_sent,
+            bytes_dropped: self.bytes_dropped,
+            pauses: self.pauses,
+            paused_ms: self.paused.as_millis() as u64,
+            uptime_ms: uptime.as_millis() as u64,
+            throughput_bps,
+        }
+    }
+}
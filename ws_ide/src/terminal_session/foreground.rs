@@ -0,0 +1,159 @@
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+use crate::docker_vm::exec::exec_output;
+
+use super::TerminalSession;
+
+/// Env var tagging a terminal's shell and everything it starts. The PTY we
+/// hold belongs to the `docker exec` client, so `tcgetpgrp` on the master only
+/// ever sees docker; the shell's own terminal lives inside the container and
+/// is found through this marker instead.
+pub const TERMINAL_MARKER: &str = "AKS_TERMINAL_ID";
+
+/// Probes normally follow hints: the shell integration's OSC 133 command
+/// start/end marks, or Enter typed into a shell without it. This slow poll
+/// only catches what neither announces, like a job resumed from a script.
+const FALLBACK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Give a command the shell just announced time to be forked before looking.
+const HINT_DELAY: Duration = Duration::from_millis(150);
+
+/// Longest command line reported to clients.
+const MAX_COMMAND_CHARS: usize = 256;
+
+/// Prints the shell's pid, then the pid and argv (one per line) of the
+/// process group in the foreground of its terminal. Prints only the shell's
+/// pid while it sits at its prompt, and nothing once it is gone. The shell is
+/// the marked process whose parent isn't marked; `$4` is its pid from the
+/// last probe, to skip the scan while it is still alive.
+const PROBE_SCRIPT: &str = r#"has_marker() { tr '\0' '\n' 2>/dev/null < "/proc/$1/environ" | grep -qx "$marker"; }
+fields() {
+    s=$(cat "/proc/$1/stat" 2>/dev/null) || return 1
+    set -- ${s##*') '}
+    ppid=$2 pgrp=$3 tpgid=$6
+}
+marker="$1=$2" include_shell=$3 shell=$4
+if [ -z "$shell" ] || ! has_marker "$shell"; then
+    shell=""
+    for p in /proc/[0-9]*; do
+        pid=${p#/proc/}
+        if has_marker "$pid" && fields "$pid" && ! has_marker "$ppid"; then shell=$pid; break; fi
+    done
+fi
+[ -n "$shell" ] && fields "$shell" || exit 0
+echo "$shell"
+fg=$tpgid
+if [ "$fg" -le 0 ] || [ "$fg" = "$pgrp" ]; then
+    [ "$include_shell" = 1 ] || exit 0
+    fg=$shell
+fi
+echo "$fg"
+tr '\0' '\n' 2>/dev/null < "/proc/$fg/cmdline""#;
+
+/// The command in the foreground of a terminal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForegroundProcess {
+    pub pid: i32,
+    /// Program name, e.g. `vim` or `cargo`.
+    pub name: String,
+    /// Full command line, e.g. `cargo build --release`.
+    pub command: String,
+}
+
+impl ForegroundProcess {
+    fn from_argv(pid: i32, argv: &[&str]) -> Option<Self> {
+        let program = |arg: &str| arg.rsplit('/').next().unwrap_or(arg).to_string();
+        // A task runs as `sh -c '<command>'`; report the command itself.
+        let argv = match argv {
+            [sh, flag, rest @ ..]
+                if *flag == "-c" && matches!(program(sh).as_str(), "sh" | "bash" | "zsh" | "dash") =>
+            {
+                rest
+            }
+            _ => argv,
+        };
+        let first = argv.first()?;
+        let name = program(first.split_whitespace().next()?);
+        let mut command = argv.join(" ");
+        if let Some((cut, _)) = command.char_indices().nth(MAX_COMMAND_CHARS) {
+            command.truncate(cut);
+        }
+        Some(Self { pid, name, command })
+    }
+}
+
+/// What a probe found: the shell's pid and the command in its foreground.
+pub struct Probe {
+    pub shell_pid: i32,
+    pub process: Option<ForegroundProcess>,
+}
+
+/// Looks up the shell tagged with `marker` inside the container. `None` when
+/// the shell has exited. With `include_shell` the shell itself is reported
+/// while nothing else holds the terminal, which is what a task terminal's
+/// `sh -c` is.
+pub async fn probe(
+    container_id: &str,
+    marker: &str,
+    shell_pid: Option<i32>,
+    include_shell: bool,
+) -> Result<Option<Probe>, std::io::Error> {
+    let hint = shell_pid.map(|pid| pid.to_string()).unwrap_or_default();
+    let include = if include_shell { "1" } else { "0" };
+    let (code, output) = exec_output(
+        container_id,
+        &["/bin/sh", "-c", PROBE_SCRIPT, "sh", TERMINAL_MARKER, marker, include, &hint],
+        None,
+    )
+    .await?;
+    if code != 0 {
+        return Err(std::io::Error::other(format!(
+            "foreground probe exited with {}: {}", code, output.trim()
+        )));
+    }
+
+    let mut lines = output.lines();
+    let Some(shell_pid) = lines.next().and_then(|l| l.trim().parse().ok()) else {
+        return Ok(None);
+    };
+    let process = lines.next().and_then(|l| l.trim().parse().ok()).and_then(|pid| {
+        let argv: Vec<&str> = lines.filter(|l| !l.is_empty()).collect();
+        ForegroundProcess::from_argv(pid, &argv)
+    });
+    Ok(Some(Probe { shell_pid, process }))
+}
+
+/// Keeps `session`'s foreground process up to date while anyone is attached.
+/// Stops once the session is dropped.
+pub fn watch(session: &Arc<TerminalSession>) -> JoinHandle<()> {
+    let weak: Weak<TerminalSession> = Arc::downgrade(session);
+    tokio::spawn(async move {
+        let mut failures = 0u32;
+        loop {
+            let Some(session) = weak.upgrade() else {
+                break;
+            };
+            let hinted = tokio::select! {
+                _ = tokio::time::sleep(FALLBACK_INTERVAL) => false,
+                _ = session.foreground_hint.notified() => true,
+            };
+            if hinted {
+                tokio::time::sleep(HINT_DELAY).await;
+            }
+            if session.members().is_empty() {
+                continue;
+            }
+            match session.refresh_foreground().await {
+                Ok(_) => failures = 0,
+                Err(e) => {
+                    failures += 1;
+                    if failures == 1 {
+                        eprintln!("[session] {} foreground probe failed: {}", session.room(), e);
+                    }
+                }
+            }
+        }
+    })
+}
@@ -0,0 +1,218 @@
+use regex::Regex;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+
+use crate::{docker_vm::exec::exec_output, types::TerminalLink};
+
+/// A partial line longer than this is discarded rather than buffered.
+const MAX_LINE_BYTES: usize = 16 * 1024;
+
+/// Candidates checked against the container per batch of output.
+const MAX_CANDIDATES: usize = 32;
+
+/// Paths remembered as existing or not before the cache starts over.
+const MAX_CACHED_PATHS: usize = 2048;
+
+/// A `path:line[:column]` reference found in the output, not yet checked
+/// against the filesystem.
+#[derive(Debug, Clone)]
+pub struct LinkCandidate {
+    pub line_text: String,
+    pub start: usize,
+    pub end: usize,
+    pub path: String,
+    pub line: u32,
+    pub column: Option<u32>,
+}
+
+/// Finds `src/main.rs:42:7`-style references in terminal output, line by
+/// line. Offsets are in characters of the line with escape sequences removed.
+pub struct LinkDetector {
+    line: String,
+    ansi: Regex,
+    /// `path:line[:column]`, as printed by compilers, test runners and stack
+    /// traces.
+    colon: Regex,
+    /// Python tracebacks: `File "app.py", line 10`.
+    python: Regex,
+}
+
+impl Default for LinkDetector {
+    fn default() -> Self {
+        Self {
+            line: String::new(),
+            ansi: Regex::new(r"\x1b\[[0-9;?]*[A-Za-z]|\x1b\][^\x07\x1b]*(?:\x07|\x1b\\)").unwrap(),
+            colon: Regex::new(
+                r"(?:^|[^\w./\-@+~])((?:\.{1,2}/|/)?(?:[\w.\-@+]+/)*[\w\-@+]+\.[A-Za-z0-9]+):(\d+)(?::(\d+))?",
+            )
+            .unwrap(),
+            python: Regex::new(r#"File "([^"]+)", line (\d+)"#).unwrap(),
+        }
+    }
+}
+
+impl LinkDetector {
+    /// Feeds decoded output. Returns the references on the lines it completed.
+    pub fn feed(&mut self, text: &str) -> Vec<LinkCandidate> {
+        let mut found = Vec::new();
+        for (i, part) in text.split('\n').enumerate() {
+            if i > 0 {
+                let line = std::mem::take(&mut self.line);
+                self.match_line(&line, &mut found);
+            }
+            if self.line.len() + part.len() > MAX_LINE_BYTES {
+                self.line.clear();
+            } else {
+                self.line.push_str(part);
+            }
+        }
+        found.truncate(MAX_CANDIDATES);
+        found
+    }
+
+    fn match_line(&self, raw: &str, found: &mut Vec<LinkCandidate>) {
+        let raw = raw.trim_end_matches('\r');
+        let raw = raw.rsplit('\r').next().unwrap_or(raw);
+        if !raw.contains(':') && !raw.contains("File \"") {
+            return;
+        }
+        let line = self.ansi.replace_all(raw, "").into_owned();
+        let chars = |byte: usize| line[..byte].chars().count();
+
+        for c in self.colon.captures_iter(&line) {
+            let (Some(path), Some(number)) = (c.get(1), c.get(2).and_then(|m| m.as_str().parse().ok())) else {
+                continue;
+            };
+            let end = c.get(3).or(c.get(2)).map_or(path.end(), |m| m.end());
+            found.push(LinkCandidate {
+                line_text: line.clone(),
+                start: chars(path.start()),
+                end: chars(end),
+                path: path.as_str().to_string(),
+                line: number,
+                column: c.get(3).and_then(|m| m.as_str().parse().ok()),
+            });
+        }
+        for c in self.python.captures_iter(&line) {
+            let (Some(path), Some(number)) = (c.get(1), c.get(2).and_then(|m| m.as_str().parse().ok())) else {
+                continue;
+            };
+            let whole = c.get(0).unwrap();
+            found.push(LinkCandidate {
+                line_text: line.clone(),
+                start: chars(whole.start()),
+                end: chars(whole.end()),
+                path: path.as_str().to_string(),
+                line: number,
+                column: None,
+            });
+        }
+    }
+}
+
+/// Joins `path` onto `cwd` and folds `.` and `..` away.
+fn resolve(cwd: Option<&str>, path: &str) -> Option<String> {
+    let joined = if path.starts_with('/') {
+        path.to_string()
+    } else {
+        format!("{}/{}", cwd?.trim_end_matches('/'), path)
+    };
+    let mut parts: Vec<&str> = Vec::new();
+    for part in joined.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            _ => parts.push(part),
+        }
+    }
+    Some(format!("/{}", parts.join("/")))
+}
+
+/// Paths known to exist in the container, shared by every batch of one
+/// terminal so a file is only checked once. Misses aren't kept: a file the
+/// user creates later should become a link without reopening the terminal.
+#[derive(Clone, Default)]
+pub struct ExistenceCache(Arc<Mutex<HashSet<String>>>);
+
+impl ExistenceCache {
+    /// Checks the paths not known to exist with one `docker exec`.
+    async fn existing(&self, container_id: &str, paths: &[String]) -> Result<Vec<bool>, std::io::Error> {
+        let unknown: Vec<&str> = {
+            let cache = self.0.lock().unwrap();
+            let mut unknown: Vec<&str> = paths
+                .iter()
+                .filter(|p| !cache.contains(*p))
+                .map(String::as_str)
+                .collect();
+            unknown.sort_unstable();
+            unknown.dedup();
+            unknown
+        };
+
+        let mut found = HashSet::new();
+        if !unknown.is_empty() {
+            let mut argv = vec![
+                "/bin/sh",
+                "-c",
+                r#"for p; do [ -f "$p" ] && printf '%s\n' "$p"; done; true"#,
+                "sh",
+            ];
+            argv.extend(unknown.iter().copied());
+            let (_, output) = exec_output(container_id, &argv, None).await?;
+            found = output.lines().map(str::to_string).collect();
+            let mut cache = self.0.lock().unwrap();
+            if cache.len() + found.len() > MAX_CACHED_PATHS {
+                cache.clear();
+            }
+            cache.extend(found.iter().cloned());
+        }
+
+        let cache = self.0.lock().unwrap();
+        Ok(paths.iter().map(|p| found.contains(p) || cache.contains(p)).collect())
+    }
+}
+
+/// Resolves `candidates` against `cwd` and keeps the ones naming a file that
+/// exists under `root` (anywhere, when the workspace root is unknown).
+pub async fn resolve_links(
+    container_id: &str,
+    cwd: Option<&str>,
+    root: Option<&str>,
+    cache: &ExistenceCache,
+    candidates: Vec<LinkCandidate>,
+) -> Result<Vec<TerminalLink>, std::io::Error> {
+    let in_workspace = |path: &str| {
+        root.is_none_or(|root| {
+            let root = root.trim_end_matches('/');
+            path == root || path.starts_with(&format!("{}/", root))
+        })
+    };
+    let resolved: Vec<(LinkCandidate, String)> = candidates
+        .into_iter()
+        .filter_map(|c| {
+            let path = resolve(cwd, &c.path)?;
+            in_workspace(&path).then_some((c, path))
+        })
+        .collect();
+    if resolved.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let paths: Vec<String> = resolved.iter().map(|(_, path)| path.clone()).collect();
+    let exists = cache.existing(container_id, &paths).await?;
+    Ok(resolved
+        .into_iter()
+        .zip(exists)
+        .filter(|(_, exists)| *exists)
+        .map(|((c, path), _)| TerminalLink {
+            line_text: c.line_text,
+            start: c.start,
+            end: c.end,
+            path,
+            line: c.line,
+            column: c.column,
+        })
+        .collect())
+}
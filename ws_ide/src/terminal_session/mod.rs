@@ -0,0 +1,592 @@
+pub mod flow;
+pub mod foreground;
+pub mod links;
+pub mod options;
+pub mod recorder;
+pub mod registry;
+pub mod scrollback;
+pub mod shell_integration;
+pub mod utf8_decoder;
+
+use bytes::Bytes;
+use serde::Serialize;
+use socketioxide::{extract::SocketRef, socket::Sid};
+use std::collections::HashMap;
+use std::fs::File;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+use tokio::task::AbortHandle;
+
+use crate::{
+    docker_vm::exec::{kill_marked, signal_process_group},
+    events,
+    state::{terminal_key, AppState},
+    types::{
+        TerminalBinaryPayload, TerminalCwdPayload, TerminalDataPayload, TerminalProcessPayload,
+        TerminalRole, TerminalSignal, TerminalStatsPayload, TerminalStatusPayload,
+    },
+};
+
+use self::{
+    flow::{dropped_marker, MemberFlow, OutputStats, HIGH_WATERMARK, LOW_WATERMARK},
+    foreground::{ForegroundProcess, TERMINAL_MARKER},
+    options::TerminalOptions,
+    recorder::Recorder,
+    scrollback::Scrollback,
+};
+
+/// Bytes of output kept per terminal for replay on reattach.
+const SCROLLBACK_BYTES: usize = 256 * 1024;
+
+/// How long a terminal with no attached socket stays alive before it is reaped.
+const DETACH_GRACE_PERIOD: Duration = Duration::from_secs(300);
+
+/// A socket attached to a terminal and what it is allowed to do there.
+#[derive(Clone)]
+pub struct Member {
+    pub socket: SocketRef,
+    pub email: String,
+    pub role: TerminalRole,
+    pub flow: MemberFlow,
+}
+
+impl Member {
+    fn can_write(&self) -> bool {
+        matches!(self.role, TerminalRole::Owner | TerminalRole::Editor)
+    }
+}
+
+/// A live PTY-backed shell. Outlives the socket that spawned it so a client can
+/// reconnect with `attach_terminal` and pick up where it left off. The owner can
+/// share it with other users, who join its room as viewers or co-drivers.
+pub struct TerminalSession {
+    pub email: String,
+    pub terminal_id: String,
+    pub container_id: String,
+    /// Value of `TERMINAL_MARKER` in the shell's environment.
+    pub marker: String,
+    master: File,
+    /// Every socket in the terminal's room. Output is written to each of them
+    /// in turn under the scrollback lock, so a joining socket never sees a
+    /// chunk twice or misses one; socket.io room broadcasts are async and
+    /// can't give that guarantee.
+    members: Mutex<HashMap<Sid, Member>>,
+    /// Users the owner has shared the terminal with.
+    grants: Mutex<HashMap<String, TerminalRole>>,
+    scrollback: Mutex<Scrollback>,
+    detached_at: Mutex<Option<Instant>>,
+    /// Current size as (cols, rows).
+    size: Mutex<(u16, u16)>,
+    recorder: Mutex<Option<Recorder>>,
+    /// Send raw PTY bytes as binary attachments instead of decoded text.
+    binary: bool,
+    /// Last working directory reported by the shell over OSC 7.
+    cwd: Mutex<Option<String>>,
+    stats: Mutex<OutputStats>,
+    /// Woken whenever a driver acknowledges output or leaves, so a paused
+    /// read loop can re-check its backlog.
+    capacity: Notify,
+    /// Task terminals count as busy for as long as their command runs.
+    runs_task: bool,
+    shell_pid: Mutex<Option<i32>>,
+    foreground: Mutex<Option<ForegroundProcess>>,
+    /// Woken when the shell announces a command starting or finishing, or
+    /// Enter is typed, so the foreground process is looked up then rather
+    /// than on the slow fallback poll.
+    foreground_hint: Notify,
+    /// Background tasks serving this terminal, aborted when it is closed.
+    tasks: Mutex<Vec<AbortHandle>>,
+    /// Host pid of the `docker exec` client while it is running.
+    child_pid: Mutex<Option<u32>>,
+    closed: AtomicBool,
+}
+
+impl TerminalSession {
+    pub fn new(
+        s: &SocketRef,
+        email: String,
+        terminal_id: String,
+        container_id: String,
+        master: File,
+        options: &TerminalOptions,
+    ) -> Self {
+        s.join(room_name(&email, &terminal_id));
+        let owner = Member {
+            socket: s.clone(),
+            email: email.clone(),
+            role: TerminalRole::Owner,
+            flow: MemberFlow::new(options.flow_control),
+        };
+        Self {
+            email,
+            terminal_id,
+            container_id,
+            marker: options.env.get(TERMINAL_MARKER).cloned().unwrap_or_default(),
+            master,
+            members: Mutex::new(HashMap::from([(s.id, owner)])),
+            grants: Mutex::new(HashMap::new()),
+            scrollback: Mutex::new(Scrollback::new(SCROLLBACK_BYTES)),
+            detached_at: Mutex::new(None),
+            size: Mutex::new((options.cols, options.rows)),
+            recorder: Mutex::new(None),
+            binary: options.binary,
+            cwd: Mutex::new(None),
+            stats: Mutex::new(OutputStats::default()),
+            capacity: Notify::new(),
+            runs_task: options.task.is_some(),
+            shell_pid: Mutex::new(None),
+            foreground: Mutex::new(None),
+            foreground_hint: Notify::new(),
+            tasks: Mutex::new(Vec::new()),
+            child_pid: Mutex::new(None),
+            closed: AtomicBool::new(false),
+        }
+    }
+
+    pub fn master(&self) -> Result<File, std::io::Error> {
+        self.master.try_clone()
+    }
+
+    /// Ties a background task to the terminal's lifetime.
+    pub fn track(&self, task: AbortHandle) {
+        self.tasks.lock().unwrap().push(task);
+    }
+
+    pub fn set_child(&self, pid: Option<u32>) {
+        *self.child_pid.lock().unwrap() = pid;
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+
+    /// Kills the shell and everything started from it inside the container,
+    /// stops the terminal's tasks and tells every member it is gone. The PTY
+    /// is released once the last reference to the session drops, which the
+    /// exit watcher does as soon as `docker exec` is reaped.
+    pub async fn terminate(&self) {
+        if self.closed.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let key = terminal_key(&self.email, &self.terminal_id);
+
+        if let Some(name) = self.stop_recording() {
+            println!("[session] {} recording {} stopped on close", key, name);
+        }
+        for task in self.tasks.lock().unwrap().drain(..) {
+            task.abort();
+        }
+
+        if !self.marker.is_empty() {
+            if let Err(e) = kill_marked(&self.container_id, TERMINAL_MARKER, &self.marker).await {
+                eprintln!("[session] {} failed to kill shell in container: {}", key, e);
+            }
+        }
+        // With the container side gone the client exits by itself; this only
+        // matters when docker itself is stuck.
+        if let Some(pid) = *self.child_pid.lock().unwrap() {
+            nix::sys::signal::kill(
+                nix::unistd::Pid::from_raw(pid as i32),
+                nix::sys::signal::Signal::SIGKILL,
+            )
+            .ok();
+        }
+
+        let room = self.room();
+        let members: Vec<Member> = self.members.lock().unwrap().drain().map(|(_, m)| m).collect();
+        for member in members {
+            member
+                .socket
+                .emit(events::outgoing::TERMINAL_CLOSED, &TerminalStatusPayload {
+                    terminal_id: self.terminal_id.clone(),
+                    message: "Terminal session closed".to_string(),
+                })
+                .ok();
+            member.socket.leave(room.clone());
+        }
+        self.capacity.notify_waiters();
+        println!("[session] {} closed", key);
+    }
+
+    /// The socket.io room every member of this terminal is in.
+    pub fn room(&self) -> String {
+        room_name(&self.email, &self.terminal_id)
+    }
+
+    pub fn emit<T: Serialize>(&self, event: &'static str, payload: &T) {
+        for member in self.members.lock().unwrap().values() {
+            member.socket.emit(event, payload).ok();
+        }
+    }
+
+    pub fn emit_status(&self, event: &'static str, message: impl Into<String>) {
+        self.emit(event, &TerminalStatusPayload {
+            terminal_id: self.terminal_id.clone(),
+            message: message.into(),
+        });
+    }
+
+    /// Returns false when the socket's send buffer is full and the chunk was
+    /// not queued.
+    fn send_output(&self, s: &SocketRef, raw: &[u8], text: &str) -> bool {
+        if self.binary {
+            s.emit(events::outgoing::TERMINAL_BINARY, &TerminalBinaryPayload {
+                terminal_id: self.terminal_id.clone(),
+                data: Bytes::copy_from_slice(raw),
+            })
+            .is_ok()
+        } else if !text.is_empty() {
+            s.emit(events::outgoing::TERMINAL_DATA, &TerminalDataPayload {
+                terminal_id: self.terminal_id.clone(),
+                data: text.to_string(),
+            })
+            .is_ok()
+        } else {
+            true
+        }
+    }
+
+    /// Sends a chunk to one member, honouring its flow-control state. Viewers
+    /// over their cap, and sockets whose buffer is full, skip the chunk and
+    /// get a marker with the skipped byte count once they catch up.
+    fn deliver(&self, member: &mut Member, raw: &[u8], text: &str, stats: &mut OutputStats) {
+        if member.flow.over_cap() && !member.can_write() {
+            member.flow.dropped += raw.len();
+            stats.bytes_dropped += raw.len() as u64;
+            return;
+        }
+
+        if member.flow.dropped > 0 {
+            let marker = dropped_marker(member.flow.dropped);
+            if !self.send_output(&member.socket, marker.as_bytes(), &marker) {
+                member.flow.dropped += raw.len();
+                stats.bytes_dropped += raw.len() as u64;
+                return;
+            }
+            member.flow.dropped = 0;
+        }
+
+        if self.send_output(&member.socket, raw, text) {
+            if member.flow.acks {
+                member.flow.unacked += raw.len();
+            }
+            stats.bytes_sent += raw.len() as u64;
+            stats.messages_sent += 1;
+        } else {
+            member.flow.dropped += raw.len();
+            stats.bytes_dropped += raw.len() as u64;
+        }
+    }
+
+    /// Records PTY output in the scrollback and forwards it to every member of
+    /// the room. Both happen under the scrollback lock so a concurrent attach
+    /// never sees a chunk twice or misses one. `text` is `raw` run through the
+    /// session's streaming UTF-8 decoder.
+    pub fn output(&self, raw: &[u8], text: &str) {
+        let mut scrollback = self.scrollback.lock().unwrap();
+        scrollback.push(raw);
+        if let Some(recorder) = self.recorder.lock().unwrap().as_mut() {
+            recorder.output(text);
+        }
+        let mut stats = self.stats.lock().unwrap();
+        stats.bytes_read += raw.len() as u64;
+        stats.batches += 1;
+        for member in self.members.lock().unwrap().values_mut() {
+            self.deliver(member, raw, text, &mut stats);
+        }
+    }
+
+    /// Largest backlog among flow-controlled drivers. Viewers never hold the
+    /// PTY back.
+    fn writer_backlog(&self) -> usize {
+        self.members
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|m| m.flow.acks && m.can_write())
+            .map(|m| m.flow.unacked)
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn over_capacity(&self) -> bool {
+        self.writer_backlog() >= HIGH_WATERMARK
+    }
+
+    /// Blocks the read loop while a driver is over its cap. The shell then
+    /// blocks on a full PTY instead of output piling up in memory.
+    pub async fn wait_for_capacity(&self) {
+        if !self.over_capacity() {
+            return;
+        }
+        let paused_at = Instant::now();
+        loop {
+            let notified = self.capacity.notified();
+            if self.writer_backlog() <= LOW_WATERMARK {
+                break;
+            }
+            notified.await;
+        }
+        let mut stats = self.stats.lock().unwrap();
+        stats.pauses += 1;
+        stats.paused += paused_at.elapsed();
+    }
+
+    /// The client at `sid` has rendered `bytes` more of the output.
+    pub fn ack(&self, sid: Sid, bytes: usize) {
+        if let Some(member) = self.members.lock().unwrap().get_mut(&sid) {
+            member.flow.unacked = member.flow.unacked.saturating_sub(bytes);
+        }
+        self.capacity.notify_waiters();
+    }
+
+    pub fn stats(&self) -> TerminalStatsPayload {
+        self.stats.lock().unwrap().to_payload(&self.terminal_id)
+    }
+
+    pub fn cwd(&self) -> Option<String> {
+        self.cwd.lock().unwrap().clone()
+    }
+
+    pub fn set_cwd(&self, cwd: String) {
+        *self.cwd.lock().unwrap() = Some(cwd.clone());
+        self.emit(events::outgoing::TERMINAL_CWD, &TerminalCwdPayload {
+            terminal_id: self.terminal_id.clone(),
+            cwd,
+        });
+    }
+
+    fn process_payload(&self, process: Option<&ForegroundProcess>) -> TerminalProcessPayload {
+        TerminalProcessPayload {
+            terminal_id: self.terminal_id.clone(),
+            pid: process.map(|p| p.pid),
+            name: process.map(|p| p.name.clone()),
+            command: process.map(|p| p.command.clone()),
+        }
+    }
+
+    /// The command running in the terminal, as of the last probe. `None`
+    /// while the shell sits at its prompt.
+    pub fn foreground(&self) -> Option<ForegroundProcess> {
+        self.foreground.lock().unwrap().clone()
+    }
+
+    /// Probes the container for the terminal's foreground process and tells
+    /// the room when it changed.
+    pub async fn refresh_foreground(&self) -> Result<Option<ForegroundProcess>, std::io::Error> {
+        if self.marker.is_empty() {
+            return Ok(None);
+        }
+        let hint = *self.shell_pid.lock().unwrap();
+        let probe = foreground::probe(&self.container_id, &self.marker, hint, self.runs_task).await?;
+        *self.shell_pid.lock().unwrap() = probe.as_ref().map(|p| p.shell_pid);
+        let process = probe.and_then(|p| p.process);
+
+        let changed = {
+            let mut current = self.foreground.lock().unwrap();
+            let changed = *current != process;
+            current.clone_from(&process);
+            changed
+        };
+        if changed {
+            self.emit(events::outgoing::TERMINAL_PROCESS, &self.process_payload(process.as_ref()));
+        }
+        Ok(process)
+    }
+
+    /// Sends `signal` to the process group in the terminal's foreground.
+    /// Returns the process it went to, or `None` when nothing but the shell
+    /// was running.
+    pub async fn signal_foreground(
+        &self,
+        signal: TerminalSignal,
+    ) -> Result<Option<ForegroundProcess>, std::io::Error> {
+        let Some(process) = self.refresh_foreground().await? else {
+            return Ok(None);
+        };
+        signal_process_group(&self.container_id, process.pid, signal.name()).await?;
+        self.hint_foreground();
+        Ok(Some(process))
+    }
+
+    /// The shell reported a command boundary; look again soon.
+    pub fn hint_foreground(&self) {
+        self.foreground_hint.notify_one();
+    }
+
+    pub fn record_input(&self, data: &str) {
+        if let Some(recorder) = self.recorder.lock().unwrap().as_mut() {
+            recorder.input(data);
+        }
+    }
+
+    pub fn resized(&self, cols: u16, rows: u16) {
+        *self.size.lock().unwrap() = (cols, rows);
+        if let Some(recorder) = self.recorder.lock().unwrap().as_mut() {
+            recorder.resize(cols, rows);
+        }
+    }
+
+    /// Starts an asciicast recording, or returns the name of the one in
+    /// progress. Input is only kept when `record_input` is set.
+    pub fn start_recording(&self, record_input: bool) -> Result<String, std::io::Error> {
+        let mut recorder = self.recorder.lock().unwrap();
+        if let Some(active) = recorder.as_ref() {
+            return Ok(active.name().to_string());
+        }
+        let (cols, rows) = *self.size.lock().unwrap();
+        let started = Recorder::create(&self.email, &self.terminal_id, cols, rows, record_input)?;
+        let name = started.name().to_string();
+        *recorder = Some(started);
+        Ok(name)
+    }
+
+    pub fn stop_recording(&self) -> Option<String> {
+        self.recorder.lock().unwrap().take().map(Recorder::finish)
+    }
+
+    pub fn role(&self, sid: Sid) -> Option<TerminalRole> {
+        self.members.lock().unwrap().get(&sid).map(|m| m.role)
+    }
+
+    /// Whether input and resizes from `sid` should reach the PTY.
+    pub fn can_write(&self, sid: Sid) -> bool {
+        self.members.lock().unwrap().get(&sid).is_some_and(Member::can_write)
+    }
+
+    /// The role `email` may join with, if any.
+    pub fn grant_for(&self, email: &str) -> Option<TerminalRole> {
+        if email == self.email {
+            return Some(TerminalRole::Owner);
+        }
+        self.grants.lock().unwrap().get(email).copied()
+    }
+
+    /// Shares the terminal with `email`, updating any sockets they already
+    /// have in the room.
+    pub fn grant(&self, email: &str, role: TerminalRole) {
+        self.grants.lock().unwrap().insert(email.to_string(), role);
+        for member in self.members.lock().unwrap().values_mut() {
+            if member.email == email {
+                member.role = role;
+            }
+        }
+        self.capacity.notify_waiters();
+    }
+
+    /// Withdraws `email`'s access and returns the sockets that were removed.
+    pub fn revoke(&self, email: &str) -> Vec<SocketRef> {
+        self.grants.lock().unwrap().remove(email);
+        let mut members = self.members.lock().unwrap();
+        let removed: Vec<Sid> = members
+            .iter()
+            .filter(|(_, m)| m.email == email)
+            .map(|(sid, _)| *sid)
+            .collect();
+        let room = self.room();
+        let sockets = removed
+            .into_iter()
+            .filter_map(|sid| members.remove(&sid))
+            .map(|m| {
+                m.socket.leave(room.clone());
+                m.socket
+            })
+            .collect();
+        self.capacity.notify_waiters();
+        sockets
+    }
+
+    pub fn members(&self) -> Vec<Member> {
+        self.members.lock().unwrap().values().cloned().collect()
+    }
+
+    pub fn grants(&self) -> Vec<(String, TerminalRole)> {
+        self.grants
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(email, role)| (email.clone(), *role))
+            .collect()
+    }
+
+    /// Adds `s` to the room as `email` with `role` and replays the buffered
+    /// output to it. `acks` opts the socket into ack-based flow control.
+    pub fn attach(&self, s: &SocketRef, email: &str, role: TerminalRole, acks: bool) {
+        let scrollback = self.scrollback.lock().unwrap();
+        s.join(self.room());
+        let mut flow = MemberFlow::new(acks);
+        let replay = scrollback.bytes();
+        if !replay.is_empty() && self.send_output(s, &replay, &String::from_utf8_lossy(&replay)) && acks {
+            flow.unacked = replay.len();
+        }
+        self.members.lock().unwrap().insert(s.id, Member {
+            socket: s.clone(),
+            email: email.to_string(),
+            role,
+            flow,
+        });
+        *self.detached_at.lock().unwrap() = None;
+
+        if let Some(cwd) = self.cwd() {
+            s.emit(events::outgoing::TERMINAL_CWD, &TerminalCwdPayload {
+                terminal_id: self.terminal_id.clone(),
+                cwd,
+            })
+            .ok();
+        }
+        if let Some(process) = self.foreground() {
+            s.emit(events::outgoing::TERMINAL_PROCESS, &self.process_payload(Some(&process)))
+                .ok();
+        }
+    }
+
+    /// Removes `sid` from the room. Returns the detach time when that left the
+    /// terminal with nobody attached.
+    pub fn detach(&self, sid: Sid) -> Option<Instant> {
+        let mut members = self.members.lock().unwrap();
+        members.remove(&sid)?;
+        self.capacity.notify_waiters();
+        if !members.is_empty() {
+            return None;
+        }
+        let now = Instant::now();
+        *self.detached_at.lock().unwrap() = Some(now);
+        Some(now)
+    }
+
+    fn detached_at(&self) -> Option<Instant> {
+        *self.detached_at.lock().unwrap()
+    }
+}
+
+fn room_name(email: &str, terminal_id: &str) -> String {
+    format!("terminal:{}", terminal_key(email, terminal_id))
+}
+
+/// Detaches every terminal attached to `sid` and reaps the ones that are still
+/// orphaned once the grace period runs out.
+pub fn detach_socket(state: &AppState, sid: Sid) {
+    let detached: Vec<(String, Instant)> = state
+        .terminals
+        .sessions()
+        .into_iter()
+        .filter_map(|(key, session)| session.detach(sid).map(|at| (key, at)))
+        .collect();
+
+    for (key, at) in detached {
+        println!("[session] {} detached, keeping alive for {:?}", key, DETACH_GRACE_PERIOD);
+        let state = state.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(DETACH_GRACE_PERIOD).await;
+            let removed = state
+                .terminals
+                .close_if(&key, |session| session.detached_at() == Some(at))
+                .await;
+            if removed.is_some() {
+                println!("[session] {} reaped after grace period", key);
+            }
+        });
+    }
+}
@@ -0,0 +1,167 @@
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter};
+use std::collections::HashMap;
+
+use crate::{
+    docker_vm::exec::exec_output,
+    entities::{terminal_preferences, users},
+    state::AppState,
+    tasks::TaskRun,
+    types::LoadTerminalPayload,
+};
+
+const DEFAULT_SHELL: &str = "/bin/bash";
+pub const DEFAULT_ROWS: u16 = 24;
+pub const DEFAULT_COLS: u16 = 80;
+const MAX_DIMENSION: u16 = 1000;
+
+/// How a terminal's shell is started. Built from the `load_terminal` payload,
+/// falling back to the user's stored defaults and then to ws_ide's own.
+#[derive(Debug, Clone)]
+pub struct TerminalOptions {
+    pub shell: String,
+    /// Extra arguments for the shell, e.g. the shell integration rcfile.
+    pub shell_args: Vec<String>,
+    pub workdir: Option<String>,
+    pub env: HashMap<String, String>,
+    pub rows: u16,
+    pub cols: u16,
+    pub binary: bool,
+    /// The client acknowledges rendered output with `terminal_ack`.
+    pub flow_control: bool,
+    /// Set when the terminal runs a task rather than an interactive shell.
+    pub task: Option<TaskRun>,
+}
+
+impl TerminalOptions {
+    /// Variables every shell gets unless the user overrides them.
+    pub fn base_env() -> HashMap<String, String> {
+        HashMap::from([
+            ("TERM".to_string(), "xterm-256color".to_string()),
+            ("COLORTERM".to_string(), "truecolor".to_string()),
+            ("LC_ALL".to_string(), "C.UTF-8".to_string()),
+        ])
+    }
+}
+
+pub fn is_valid_env_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+pub fn clamp_dimension(value: Option<i64>, fallback: u16) -> u16 {
+    value
+        .and_then(|v| u16::try_from(v).ok())
+        .filter(|v| *v > 0)
+        .map(|v| v.min(MAX_DIMENSION))
+        .unwrap_or(fallback)
+}
+
+pub async fn load_user_defaults(
+    state: &AppState,
+    email: &str,
+) -> Result<Option<terminal_preferences::Model>, sea_orm::DbErr> {
+    let user = match users::Entity::find()
+        .filter(users::Column::Email.eq(email))
+        .one(&*state.db)
+        .await?
+    {
+        Some(user) => user,
+        None => return Ok(None),
+    };
+
+    terminal_preferences::Entity::find()
+        .filter(terminal_preferences::Column::UserId.eq(user.id))
+        .filter(terminal_preferences::Column::DeletedAt.is_null())
+        .one(&*state.db)
+        .await
+}
+
+/// Login shells listed in the container's `/etc/shells` that actually exist.
+pub async fn available_shells(container_id: &str) -> Result<Vec<String>, std::io::Error> {
+    let script = "grep -v '^#' /etc/shells | while read -r s; do [ -x \"$s\" ] && echo \"$s\"; done";
+    let (_, output) = exec_output(container_id, &["/bin/sh", "-c", script], None).await?;
+    let mut shells: Vec<String> = output
+        .lines()
+        .map(str::trim)
+        .filter(|l| l.starts_with('/'))
+        .map(str::to_string)
+        .collect();
+    shells.dedup();
+    Ok(shells)
+}
+
+/// Merges payload overrides over the user's defaults and validates the shell
+/// against the ones available in the container.
+pub async fn resolve_terminal_options(
+    state: &AppState,
+    container_id: &str,
+    payload: &LoadTerminalPayload,
+    workspace_root: Option<String>,
+) -> Result<TerminalOptions, String> {
+    let defaults = match load_user_defaults(state, &payload.email).await {
+        Ok(defaults) => defaults,
+        Err(e) => {
+            eprintln!("[terminal] failed to load terminal defaults for {}: {}", payload.email, e);
+            None
+        }
+    };
+
+    let shell = payload
+        .shell
+        .clone()
+        .or_else(|| defaults.as_ref().and_then(|d| d.shell.clone()))
+        .unwrap_or_else(|| DEFAULT_SHELL.to_string());
+    if shell != DEFAULT_SHELL {
+        let shells = available_shells(container_id)
+            .await
+            .map_err(|e| format!("Failed to list shells: {}", e))?;
+        if !shells.contains(&shell) {
+            return Err(format!(
+                "Shell {} is not available in this workspace (available: {})",
+                shell,
+                shells.join(", ")
+            ));
+        }
+    }
+
+    let workdir = payload
+        .cwd
+        .clone()
+        .or_else(|| defaults.as_ref().and_then(|d| d.cwd.clone()))
+        .or(workspace_root);
+
+    let mut env = TerminalOptions::base_env();
+    if let Some(serde_json::Value::Object(stored)) = defaults.as_ref().map(|d| d.env.clone()) {
+        for (name, value) in stored {
+            if let serde_json::Value::String(value) = value {
+                env.insert(name, value);
+            }
+        }
+    }
+    env.extend(payload.env.clone());
+    if let Some(bad) = env.keys().find(|k| !is_valid_env_name(k)) {
+        return Err(format!("Invalid environment variable name: {}", bad));
+    }
+
+    let rows = payload
+        .rows
+        .map(i64::from)
+        .or_else(|| defaults.as_ref().and_then(|d| d.rows));
+    let cols = payload
+        .cols
+        .map(i64::from)
+        .or_else(|| defaults.as_ref().and_then(|d| d.cols));
+
+    Ok(TerminalOptions {
+        shell,
+        shell_args: Vec::new(),
+        workdir,
+        env,
+        rows: clamp_dimension(rows, DEFAULT_ROWS),
+        cols: clamp_dimension(cols, DEFAULT_COLS),
+        binary: payload.binary,
+        flow_control: payload.flow_control,
+        task: None,
+    })
+}
@@ -0,0 +1,111 @@
+use serde_json::json;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+use crate::state::workspace_slug;
+
+/// Root directory for recordings, one sub-directory per workspace.
+pub fn recordings_dir() -> PathBuf {
+    PathBuf::from(std::env::var("RECORDINGS_DIR").unwrap_or_else(|_| "recordings".to_string()))
+}
+
+pub fn workspace_recordings_dir(email: &str) -> PathBuf {
+    recordings_dir().join(workspace_slug(email))
+}
+
+/// Recording names are generated by us, so anything else is rejected outright.
+pub fn is_valid_recording_name(name: &str) -> bool {
+    name.ends_with(".cast")
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// Writes a terminal session as an asciicast v2 file: a JSON header line
+/// followed by one `[time, code, data]` line per event. Keystrokes carry
+/// whatever was typed, passwords at prompts included, so "i" events are only
+/// written when the recording was started with `record_input`.
+pub struct Recorder {
+    writer: BufWriter<File>,
+    started: Instant,
+    name: String,
+    record_input: bool,
+}
+
+impl Recorder {
+    pub fn create(
+        email: &str,
+        terminal_id: &str,
+        cols: u16,
+        rows: u16,
+        record_input: bool,
+    ) -> Result<Self, std::io::Error> {
+        let dir = workspace_recordings_dir(email);
+        fs::create_dir_all(&dir)?;
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        // Two recordings can start within the same second.
+        let name = format!(
+            "{}-{}-{}.cast",
+            workspace_slug(terminal_id),
+            timestamp,
+            &Uuid::new_v4().simple().to_string()[..8]
+        );
+        let mut writer = BufWriter::new(File::create_new(dir.join(&name))?);
+
+        let header = json!({
+            "version": 2,
+            "width": cols,
+            "height": rows,
+            "timestamp": timestamp,
+            "title": format!("{} ({})", terminal_id, email),
+            "env": { "TERM": "xterm-256color", "SHELL": "/bin/bash" },
+        });
+        serde_json::to_writer(&mut writer, &header)?;
+        writer.write_all(b"\n")?;
+
+        Ok(Self { writer, started: Instant::now(), name, record_input })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn event(&mut self, code: &str, data: &str) {
+        let t = self.started.elapsed().as_secs_f64();
+        let line = serde_json::to_writer(&mut self.writer, &(t, code, data))
+            .map_err(std::io::Error::from)
+            .and_then(|_| self.writer.write_all(b"\n"));
+        if let Err(e) = line {
+            eprintln!("[recorder] failed to write {}: {}", self.name, e);
+        }
+    }
+
+    pub fn output(&mut self, data: &str) {
+        self.event("o", data);
+    }
+
+    pub fn input(&mut self, data: &str) {
+        if self.record_input {
+            self.event("i", data);
+        }
+    }
+
+    pub fn resize(&mut self, cols: u16, rows: u16) {
+        self.event("r", &format!("{}x{}", cols, rows));
+    }
+
+    pub fn finish(mut self) -> String {
+        if let Err(e) = self.writer.flush() {
+            eprintln!("[recorder] failed to flush {}: {}", self.name, e);
+        }
+        self.name
+    }
+}
@@ -0,0 +1,149 @@
+use dashmap::DashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::state::terminal_key;
+
+use super::TerminalSession;
+
+/// Terminals a user may have open at once, unless `MAX_TERMINALS_PER_USER`
+/// says otherwise.
+const DEFAULT_MAX_PER_USER: usize = 10;
+
+fn max_per_user() -> usize {
+    std::env::var("MAX_TERMINALS_PER_USER")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(DEFAULT_MAX_PER_USER)
+}
+
+/// Every live terminal, keyed by `terminal_key`. A session leaves it either
+/// through `close`, which kills its shell, or once its shell has exited on
+/// its own, so nothing keeps running in a container without an entry here.
+pub struct TerminalRegistry {
+    sessions: DashMap<String, Arc<TerminalSession>>,
+    max_per_user: usize,
+    /// Held across the count and the insert so two loads can't both slip
+    /// under the cap.
+    admit: Mutex<()>,
+    /// Keys claimed by a terminal that is still starting.
+    reserved: DashMap<String, ()>,
+}
+
+/// A key claimed through `TerminalRegistry::reserve`, released on drop.
+pub struct Reservation<'a> {
+    registry: &'a TerminalRegistry,
+    key: String,
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        self.registry.reserved.remove(&self.key);
+    }
+}
+
+impl Default for TerminalRegistry {
+    fn default() -> Self {
+        Self {
+            sessions: DashMap::new(),
+            max_per_user: max_per_user(),
+            admit: Mutex::new(()),
+            reserved: DashMap::new(),
+        }
+    }
+}
+
+impl TerminalRegistry {
+    pub fn get(&self, key: &str) -> Option<Arc<TerminalSession>> {
+        self.sessions.get(key).map(|r| r.clone())
+    }
+
+    /// Every session, with its key.
+    pub fn sessions(&self) -> Vec<(String, Arc<TerminalSession>)> {
+        self.sessions
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().clone()))
+            .collect()
+    }
+
+    fn count_for(&self, email: &str) -> usize {
+        self.sessions.iter().filter(|entry| entry.value().email == email).count()
+    }
+
+    /// Fails when `email` already has as many terminals as allowed. A
+    /// terminal that would replace `key` doesn't count against the cap.
+    pub fn check_capacity(&self, email: &str, key: &str) -> Result<(), std::io::Error> {
+        let open = self.count_for(email);
+        if self.sessions.contains_key(key) || open < self.max_per_user {
+            return Ok(());
+        }
+        Err(std::io::Error::other(format!(
+            "Terminal limit reached: {} of {} terminals already open; close one first",
+            open, self.max_per_user
+        )))
+    }
+
+    /// Claims `key` for a terminal about to start, so a second start for the
+    /// same key is refused until the claim is dropped. `None` when the key is
+    /// taken, live or claimed.
+    pub fn reserve(&self, key: &str) -> Option<Reservation<'_>> {
+        let _admit = self.admit.lock().unwrap();
+        if self.sessions.contains_key(key) {
+            return None;
+        }
+        match self.reserved.entry(key.to_string()) {
+            dashmap::Entry::Occupied(_) => None,
+            dashmap::Entry::Vacant(entry) => {
+                entry.insert(());
+                Some(Reservation { registry: self, key: key.to_string() })
+            }
+        }
+    }
+
+    /// Registers `session`, subject to the per-user cap. Returns the session
+    /// it replaced, which the caller should terminate.
+    pub fn insert(&self, session: Arc<TerminalSession>) -> Result<Option<Arc<TerminalSession>>, std::io::Error> {
+        let _admit = self.admit.lock().unwrap();
+        let key = terminal_key(&session.email, &session.terminal_id);
+        self.check_capacity(&session.email, &key)?;
+        Ok(self.sessions.insert(key, session))
+    }
+
+    /// Drops `key` if it still maps to `session`, e.g. once its shell exits.
+    pub fn remove_if_current(&self, key: &str, session: &Arc<TerminalSession>) -> bool {
+        self.sessions
+            .remove_if(key, |_, current| Arc::ptr_eq(current, session))
+            .is_some()
+    }
+
+    /// Removes `key` when `f` approves of its session and terminates it.
+    pub async fn close_if<F>(&self, key: &str, f: F) -> Option<Arc<TerminalSession>>
+    where
+        F: FnOnce(&TerminalSession) -> bool,
+    {
+        let (_, session) = self.sessions.remove_if(key, |_, session| f(session.as_ref()))?;
+        session.terminate().await;
+        Some(session)
+    }
+
+    /// Removes `key` and terminates its session.
+    pub async fn close(&self, key: &str) -> Option<Arc<TerminalSession>> {
+        self.close_if(key, |_| true).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_key_can_be_reserved_once_at_a_time() {
+        let registry = TerminalRegistry::default();
+        let first = registry.reserve("a@x:build");
+        assert!(first.is_some());
+        assert!(registry.reserve("a@x:build").is_none());
+        assert!(registry.reserve("a@x:test").is_some());
+        drop(first);
+        assert!(registry.reserve("a@x:build").is_some());
+    }
+}
@@ -0,0 +1,39 @@
+use std::collections::VecDeque;
+
+/// Bounded ring buffer of raw terminal output, replayed when a client reattaches.
+pub struct Scrollback {
+    buf: VecDeque<u8>,
+    capacity: usize,
+}
+
+impl Scrollback {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            buf: VecDeque::with_capacity(capacity.min(64 * 1024)),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, bytes: &[u8]) {
+        if bytes.len() >= self.capacity {
+            self.buf.clear();
+            self.buf.extend(&bytes[bytes.len() - self.capacity..]);
+            return;
+        }
+        let overflow = (self.buf.len() + bytes.len()).saturating_sub(self.capacity);
+        self.buf.drain(..overflow);
+        self.buf.extend(bytes);
+    }
+
+    /// The buffered output. Eviction can cut a multibyte character in half,
+    /// so leading continuation bytes are skipped.
+    pub fn bytes(&self) -> Vec<u8> {
+        let start = self
+            .buf
+            .iter()
+            .position(|b| (b & 0xC0) != 0x80)
+            .unwrap_or(self.buf.len());
+        self.buf.range(start..).copied().collect()
+    }
+
+}
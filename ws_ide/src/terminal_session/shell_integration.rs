@@ -0,0 +1,249 @@
+use crate::docker_vm::exec::exec_output;
+
+use super::options::TerminalOptions;
+
+/// Where the integration scripts are written inside the container.
+const INTEGRATION_DIR: &str = "/tmp/.aks/shell-integration";
+
+/// An OSC sequence longer than this without a terminator is treated as garbage.
+const MAX_PENDING: usize = 4096;
+
+/// Sourced via `bash --rcfile`. Loads the user's own ~/.bashrc first, then
+/// reports the cwd (OSC 7) and prompt/command boundaries (OSC 133).
+const BASH_RC: &str = r#"[ -f ~/.bashrc ] && . ~/.bashrc
+
+__aks_at_prompt=0
+__aks_ran=0
+__aks_prompt_begin() { __aks_status=$?; __aks_at_prompt=0; }
+__aks_precmd() {
+    if [ "$__aks_ran" = 1 ]; then printf '\033]133;D;%s\007' "$__aks_status"; fi
+    __aks_ran=0
+    printf '\033]7;file://%s%s\007' "$HOSTNAME" "$PWD"
+    printf '\033]133;A\007'
+    __aks_at_prompt=1
+}
+__aks_preexec() {
+    case "$BASH_COMMAND" in __aks_*) return ;; esac
+    [ -n "$COMP_LINE" ] && return
+    [ "$__aks_at_prompt" = 1 ] || return
+    __aks_at_prompt=0
+    __aks_ran=1
+    printf '\033]133;C\007'
+}
+PROMPT_COMMAND="__aks_prompt_begin;${PROMPT_COMMAND:+$PROMPT_COMMAND;}__aks_precmd"
+trap '__aks_preexec' DEBUG
+PS1="$PS1\[\033]133;B\007\]"
+"#;
+
+/// Picked up through ZDOTDIR; hands ZDOTDIR back to the user's home so their
+/// own config loads as usual.
+const ZSH_ENV: &str = r#"ZDOTDIR=$HOME
+[ -f "$HOME/.zshenv" ] && . "$HOME/.zshenv"
+ZDOTDIR=/tmp/.aks/shell-integration/zsh
+"#;
+
+const ZSH_RC: &str = r#"ZDOTDIR=$HOME
+[ -f "$HOME/.zshrc" ] && . "$HOME/.zshrc"
+
+__aks_ran=0
+__aks_precmd() {
+    local ret=$?
+    if [[ $__aks_ran == 1 ]]; then printf '\033]133;D;%s\007' "$ret"; fi
+    __aks_ran=0
+    printf '\033]7;file://%s%s\007' "$HOST" "$PWD"
+    printf '\033]133;A\007'
+}
+__aks_preexec() {
+    __aks_ran=1
+    printf '\033]133;C\007'
+}
+precmd_functions=(__aks_precmd $precmd_functions)
+preexec_functions+=(__aks_preexec)
+PS1="$PS1%{"$'\033]133;B\007'"%}"
+"#;
+
+/// Writes the integration scripts into the container and points `options` at
+/// them. Shells other than bash and zsh start without integration.
+pub async fn prepare(container_id: &str, options: &mut TerminalOptions) -> Result<(), std::io::Error> {
+    let name = options.shell.rsplit('/').next().unwrap_or_default().to_string();
+    if name != "bash" && name != "zsh" {
+        return Ok(());
+    }
+
+    let script = r#"mkdir -p "$1/zsh" && printf '%s' "$2" > "$1/bashrc" && printf '%s' "$3" > "$1/zsh/.zshrc" && printf '%s' "$4" > "$1/zsh/.zshenv""#;
+    let (code, output) = exec_output(
+        container_id,
+        &["/bin/sh", "-c", script, "sh", INTEGRATION_DIR, BASH_RC, ZSH_RC, ZSH_ENV],
+        None,
+    )
+    .await?;
+    if code != 0 {
+        return Err(std::io::Error::other(format!(
+            "installing shell integration exited with {}: {}", code, output.trim()
+        )));
+    }
+
+    if name == "bash" {
+        options.shell_args = vec!["--rcfile".to_string(), format!("{}/bashrc", INTEGRATION_DIR)];
+    } else {
+        options.env.insert("ZDOTDIR".to_string(), format!("{}/zsh", INTEGRATION_DIR));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ShellEvent {
+    /// OSC 7: the shell's working directory.
+    Cwd(String),
+    /// OSC 133;A: a prompt is about to be drawn.
+    PromptStart,
+    /// OSC 133;C: the user's command started running.
+    CommandStarted,
+    /// OSC 133;D: the command finished, with its exit code when reported.
+    CommandFinished(Option<i32>),
+}
+
+/// Pulls OSC 7 and OSC 133 sequences out of decoded PTY output. Sequences split
+/// across reads are held back until their terminator arrives. The output
+/// itself is left untouched; xterm ignores OSCs it does not handle.
+#[derive(Default)]
+pub struct ShellIntegrationParser {
+    pending: String,
+}
+
+impl ShellIntegrationParser {
+    pub fn feed(&mut self, text: &str) -> Vec<ShellEvent> {
+        let mut events = Vec::new();
+        let buf = std::mem::take(&mut self.pending) + text;
+        let mut rest = buf.as_str();
+
+        loop {
+            let Some(start) = rest.find("\x1b]") else {
+                if rest.ends_with('\x1b') {
+                    self.pending.push('\x1b');
+                }
+                break;
+            };
+            let body = &rest[start + 2..];
+            match find_terminator(body) {
+                Some((end, len)) => {
+                    if let Some(event) = parse_osc(&body[..end]) {
+                        events.push(event);
+                    }
+                    rest = &body[end + len..];
+                }
+                None => {
+                    if rest.len() - start < MAX_PENDING {
+                        self.pending = rest[start..].to_string();
+                    }
+                    break;
+                }
+            }
+        }
+        events
+    }
+}
+
+/// Finds BEL or ST (`ESC \`). Returns its offset and length.
+fn find_terminator(body: &str) -> Option<(usize, usize)> {
+    body.char_indices().find_map(|(i, c)| match c {
+        '\x07' => Some((i, 1)),
+        '\x1b' if body[i + 1..].starts_with('\\') => Some((i, 2)),
+        _ => None,
+    })
+}
+
+fn parse_osc(body: &str) -> Option<ShellEvent> {
+    if let Some(url) = body.strip_prefix("7;") {
+        let rest = url.strip_prefix("file://")?;
+        let path = &rest[rest.find('/')?..];
+        return Some(ShellEvent::Cwd(percent_decode(path)));
+    }
+
+    let mut parts = body.strip_prefix("133;")?.split(';');
+    match parts.next()? {
+        "A" => Some(ShellEvent::PromptStart),
+        "C" => Some(ShellEvent::CommandStarted),
+        "D" => Some(ShellEvent::CommandFinished(parts.next().and_then(|c| c.parse().ok()))),
+        _ => None,
+    }
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        // Exactly two hex digits; `from_str_radix` alone would take `%+f`.
+        if bytes[i] == b'%'
+            && i + 2 < bytes.len()
+            && bytes[i + 1].is_ascii_hexdigit()
+            && bytes[i + 2].is_ascii_hexdigit()
+        {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or_default();
+            if let Ok(byte) = u8::from_str_radix(hex, 16) {
+                out.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed_all(chunks: &[&str]) -> Vec<ShellEvent> {
+        let mut parser = ShellIntegrationParser::default();
+        chunks.iter().flat_map(|chunk| parser.feed(chunk)).collect()
+    }
+
+    #[test]
+    fn bel_and_st_terminate_a_sequence() {
+        assert_eq!(feed_all(&["\x1b]133;A\x07$ "]), [ShellEvent::PromptStart]);
+        assert_eq!(feed_all(&["\x1b]133;C\x1b\\out"]), [ShellEvent::CommandStarted]);
+        assert_eq!(
+            feed_all(&["\x1b]133;D;2\x07\x1b]7;file://host/home/u\x1b\\"]),
+            [ShellEvent::CommandFinished(Some(2)), ShellEvent::Cwd("/home/u".to_string())]
+        );
+    }
+
+    #[test]
+    fn sequences_split_across_reads() {
+        assert_eq!(feed_all(&["ls\r\n\x1b]133;", "C\x07"]), [ShellEvent::CommandStarted]);
+        assert_eq!(feed_all(&["done\x1b", "]133;D;0\x07"]), [ShellEvent::CommandFinished(Some(0))]);
+        assert_eq!(feed_all(&["\x1b]133;A\x1b", "\\"]), [ShellEvent::PromptStart]);
+        assert_eq!(
+            feed_all(&["\x1b]7;file://h/tmp/a", "%20b\x07"]),
+            [ShellEvent::Cwd("/tmp/a b".to_string())]
+        );
+    }
+
+    #[test]
+    fn malformed_sequences_are_skipped() {
+        assert_eq!(feed_all(&["\x1b]133;Z\x07\x1b]133;C\x07"]), [ShellEvent::CommandStarted]);
+        assert_eq!(feed_all(&["\x1b]133;D;oops\x07"]), [ShellEvent::CommandFinished(None)]);
+        assert_eq!(feed_all(&["\x1b]7;http://h/tmp\x07\x1b]7;file://h\x07"]), []);
+        assert_eq!(feed_all(&["\x1b]0;title\x07plain \x1b[31mred"]), []);
+    }
+
+    #[test]
+    fn an_unterminated_sequence_is_dropped_past_the_limit() {
+        let garbage = format!("\x1b]133;{}", "x".repeat(MAX_PENDING));
+        assert_eq!(feed_all(&[&garbage, "\x07\x1b]133;A\x07"]), [ShellEvent::PromptStart]);
+    }
+
+    #[test]
+    fn percent_decoding() {
+        assert_eq!(percent_decode("/a%20b"), "/a b");
+        assert_eq!(percent_decode("/%E2%82%ac"), "/\u{20ac}");
+        assert_eq!(percent_decode("/%+f"), "/%+f");
+        assert_eq!(percent_decode("/%-1"), "/%-1");
+        assert_eq!(percent_decode("/%zz%4"), "/%zz%4");
+        assert_eq!(percent_decode("/100%"), "/100%");
+    }
+}
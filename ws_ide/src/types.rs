@@ -1,20 +1,140 @@
+use bytes::Bytes;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::{docker_vm::exec::OutputStream, tasks::TaskDefinition};
 
 fn default_terminal_id() -> String {
     "t1".to_string()
 }
 
+fn default_true() -> bool {
+    true
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct LoadTerminalPayload {
     pub email: String,
     #[serde(default = "default_terminal_id", alias = "terminalId")]
     pub terminal_id: String,
+    #[serde(default, alias = "gitUrl")]
+    pub git_url: Option<String>,
+    #[serde(default, alias = "gitBranch", alias = "branch")]
+    pub git_branch: Option<String>,
+    /// Stream raw PTY bytes on `terminal_binary` instead of text on `terminal_data`.
+    #[serde(default)]
+    pub binary: bool,
+    /// The client will acknowledge rendered output with `terminal_ack`.
+    #[serde(default, alias = "flowControl")]
+    pub flow_control: bool,
+    #[serde(default)]
+    pub shell: Option<String>,
+    #[serde(default)]
+    pub cwd: Option<String>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    #[serde(default)]
+    pub rows: Option<u16>,
+    #[serde(default)]
+    pub cols: Option<u16>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct EmailPayload {
+    pub email: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SetTerminalDefaultsPayload {
+    pub email: String,
+    #[serde(default)]
+    pub shell: Option<String>,
+    #[serde(default)]
+    pub cwd: Option<String>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    #[serde(default)]
+    pub rows: Option<u16>,
+    #[serde(default)]
+    pub cols: Option<u16>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TerminalDefaultsPayload {
+    pub shell: Option<String>,
+    pub cwd: Option<String>,
+    pub env: HashMap<String, String>,
+    pub rows: Option<u16>,
+    pub cols: Option<u16>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ShellsPayload {
+    pub shells: Vec<String>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct RepoTreePayload {
     pub email: String,
     pub path: Option<String>,
+    /// Levels to list below `path`; 1 lists only its direct children.
+    #[serde(default)]
+    pub depth: Option<u32>,
+    /// Entries to skip, to page through large directories.
+    #[serde(default)]
+    pub offset: usize,
+    #[serde(default)]
+    pub limit: Option<usize>,
+    /// List excluded entries too, flagged with `excluded`, instead of
+    /// leaving them out.
+    #[serde(default, alias = "showExcluded")]
+    pub show_excluded: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryKind {
+    File,
+    Dir,
+    Symlink,
+    /// Sockets, pipes and device nodes.
+    Other,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DirEntry {
+    pub name: String,
+    pub path: String,
+    pub kind: EntryKind,
+    /// A directory, or a symlink to one; either can be expanded.
+    pub is_dir: bool,
+    pub size: u64,
+    /// Seconds since the epoch.
+    pub mtime: u64,
+    /// Permission bits, e.g. `0o644`.
+    pub mode: u32,
+    /// `mode` as `ls` shows it, e.g. `rw-r--r--`.
+    pub permissions: String,
+    pub symlink_target: Option<String>,
+    /// 1 for direct children of the listed directory.
+    pub depth: u32,
+    /// Matched by `files.exclude` or, when enabled, `.gitignore`.
+    pub excluded: bool,
+}
+
+/// One page of a listing. Entries come parents first, directories before
+/// files, then by name; `next_offset` is set while more pages remain.
+#[derive(Debug, Clone, Serialize)]
+pub struct RepoStructurePayload {
+    pub current_directory: String,
+    pub items: Vec<DirEntry>,
+    pub depth: u32,
+    pub offset: usize,
+    pub total: usize,
+    pub next_offset: Option<usize>,
+    /// The directory had more entries than are listed at all.
+    pub truncated: bool,
+    pub error: Option<String>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -34,11 +154,404 @@ pub struct TerminalResizePayload {
     pub terminal_id: String,
 }
 
+#[derive(Debug, Clone, Deserialize)]
+pub struct AttachTerminalPayload {
+    pub email: String,
+    #[serde(default = "default_terminal_id", alias = "terminalId")]
+    pub terminal_id: String,
+    #[serde(default, alias = "flowControl")]
+    pub flow_control: bool,
+}
+
+/// What a socket attached to a terminal may do. Viewers only see output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TerminalRole {
+    Owner,
+    Editor,
+    Viewer,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ShareTerminalPayload {
+    pub email: String,
+    #[serde(default = "default_terminal_id", alias = "terminalId")]
+    pub terminal_id: String,
+    /// The user being given access.
+    pub user: String,
+    pub role: TerminalRole,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RevokeTerminalAccessPayload {
+    pub email: String,
+    #[serde(default = "default_terminal_id", alias = "terminalId")]
+    pub terminal_id: String,
+    pub user: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct JoinTerminalPayload {
+    /// The terminal owner's email.
+    #[serde(alias = "ownerEmail")]
+    pub owner: String,
+    #[serde(default = "default_terminal_id", alias = "terminalId")]
+    pub terminal_id: String,
+    /// The joining user's email, as the client sees it. Access is decided by
+    /// the email the socket is already bound to.
+    pub email: String,
+    #[serde(default, alias = "flowControl")]
+    pub flow_control: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TerminalJoinedPayload {
+    pub terminal_id: String,
+    pub owner: String,
+    pub role: TerminalRole,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TerminalParticipantPayload {
+    pub email: String,
+    pub role: TerminalRole,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TerminalAccessPayload {
+    pub terminal_id: String,
+    pub owner: String,
+    /// Sockets currently in the room.
+    pub participants: Vec<TerminalParticipantPayload>,
+    /// Users the owner has shared the terminal with.
+    pub grants: Vec<TerminalParticipantPayload>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TerminalAckPayload {
+    pub email: String,
+    #[serde(default = "default_terminal_id", alias = "terminalId")]
+    pub terminal_id: String,
+    /// Bytes of output rendered since the previous ack.
+    pub bytes: usize,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct GetTerminalStatsPayload {
+    pub email: String,
+    #[serde(default = "default_terminal_id", alias = "terminalId")]
+    pub terminal_id: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TerminalStatsPayload {
+    pub terminal_id: String,
+    pub bytes_read: u64,
+    pub batches: u64,
+    pub bytes_sent: u64,
+    pub messages_sent: u64,
+    pub bytes_dropped: u64,
+    pub pauses: u64,
+    pub paused_ms: u64,
+    pub uptime_ms: u64,
+    /// Average PTY output rate since the terminal started.
+    pub throughput_bps: u64,
+}
+
+/// A one-off command. Exactly one of `argv` or `command` (run with `sh -c`)
+/// must be given.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExecRequest {
+    #[serde(default)]
+    pub argv: Option<Vec<String>>,
+    #[serde(default)]
+    pub command: Option<String>,
+    #[serde(default)]
+    pub cwd: Option<String>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    #[serde(default, alias = "timeoutMs")]
+    pub timeout_ms: Option<u64>,
+    /// Deliver output as it arrives instead of only in the result.
+    #[serde(default)]
+    pub stream: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExecResult {
+    pub stdout: String,
+    pub stderr: String,
+    /// `None` when the command was killed.
+    pub exit_code: Option<i32>,
+    pub duration_ms: u64,
+    pub timed_out: bool,
+    /// Captured output hit the size cap and was cut short.
+    pub truncated: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExecCommandPayload {
+    pub email: String,
+    /// Echoed back on every reply so callers can match them up.
+    #[serde(default, alias = "requestId")]
+    pub request_id: Option<String>,
+    #[serde(flatten)]
+    pub request: ExecRequest,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExecOutputPayload {
+    pub request_id: Option<String>,
+    pub stream: OutputStream,
+    pub data: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExecResultPayload {
+    pub request_id: Option<String>,
+    #[serde(flatten)]
+    pub result: ExecResult,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExecErrorPayload {
+    pub request_id: Option<String>,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiagnosticSeverity {
+    Error,
+    Warning,
+    Info,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Diagnostic {
+    pub file: String,
+    pub line: u32,
+    pub column: Option<u32>,
+    pub severity: DiagnosticSeverity,
+    pub message: String,
+    /// Compiler or linter code, e.g. `E0308`, `TS2322` or `no-unused-vars`.
+    pub code: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TasksPayload {
+    pub tasks: Vec<TaskDefinition>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RunTaskPayload {
+    pub email: String,
+    pub name: String,
+    /// Defaults to `task-<name>`.
+    #[serde(default, alias = "terminalId")]
+    pub terminal_id: Option<String>,
+    #[serde(default, alias = "flowControl")]
+    pub flow_control: bool,
+    #[serde(default)]
+    pub rows: Option<u16>,
+    #[serde(default)]
+    pub cols: Option<u16>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskStartedPayload {
+    pub terminal_id: String,
+    pub task: String,
+    pub command: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskDiagnosticsPayload {
+    pub terminal_id: String,
+    pub task: String,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskFinishedPayload {
+    pub terminal_id: String,
+    pub task: String,
+    pub exit_code: Option<i32>,
+    pub errors: u32,
+    pub warnings: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskErrorPayload {
+    pub task: Option<String>,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Running,
+    Stopping,
+    Stopped,
+    /// Finished with exit code 0.
+    Exited,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct JobInfo {
+    pub id: String,
+    pub name: String,
+    pub command: String,
+    pub cwd: Option<String>,
+    pub status: JobStatus,
+    pub exit_code: Option<i32>,
+    /// Unix time in milliseconds of the current run's start.
+    pub started_at: u64,
+    pub finished_at: Option<u64>,
+    pub restarts: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct StartJobPayload {
+    pub email: String,
+    /// Defaults to the command itself.
+    #[serde(default)]
+    pub name: Option<String>,
+    pub command: String,
+    #[serde(default)]
+    pub cwd: Option<String>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+}
+
+/// Names one job; used by stop, restart and remove.
+#[derive(Debug, Clone, Deserialize)]
+pub struct JobPayload {
+    pub email: String,
+    #[serde(alias = "jobId")]
+    pub job_id: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TailJobPayload {
+    pub email: String,
+    #[serde(alias = "jobId")]
+    pub job_id: String,
+    /// Keep streaming new output after the replay; `false` stops following.
+    #[serde(default = "default_true")]
+    pub follow: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct JobsPayload {
+    pub jobs: Vec<JobInfo>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct JobOutputPayload {
+    pub job_id: String,
+    /// `None` for replayed output, where the streams are interleaved.
+    pub stream: Option<OutputStream>,
+    pub data: String,
+    pub replay: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct JobErrorPayload {
+    pub job_id: Option<String>,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RecordingPayload {
+    pub email: String,
+    #[serde(default = "default_terminal_id", alias = "terminalId")]
+    pub terminal_id: String,
+    /// Also record keystrokes. Off by default since they include passwords.
+    #[serde(default, alias = "recordInput")]
+    pub record_input: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RecordingStatusPayload {
+    pub terminal_id: String,
+    pub name: String,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct CloseTerminalPayload {
     pub email: String,
     #[serde(default = "default_terminal_id", alias = "terminalId")]
     pub terminal_id: String,
+    /// Close even if a command is still running.
+    #[serde(default)]
+    pub force: bool,
+}
+
+/// Signals a client may send to a terminal's foreground process group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TerminalSignal {
+    Int,
+    Term,
+    Kill,
+    Tstp,
+    Hup,
+}
+
+impl TerminalSignal {
+    /// Accepts `SIGINT`, `INT` or `int` and so on.
+    pub fn parse(name: &str) -> Option<Self> {
+        let name = name.trim().to_ascii_uppercase();
+        match name.strip_prefix("SIG").unwrap_or(&name) {
+            "INT" => Some(Self::Int),
+            "TERM" => Some(Self::Term),
+            "KILL" => Some(Self::Kill),
+            "TSTP" => Some(Self::Tstp),
+            "HUP" => Some(Self::Hup),
+            _ => None,
+        }
+    }
+
+    /// The name `kill -s` takes.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Int => "INT",
+            Self::Term => "TERM",
+            Self::Kill => "KILL",
+            Self::Tstp => "TSTP",
+            Self::Hup => "HUP",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TerminalSignalPayload {
+    pub email: String,
+    #[serde(default = "default_terminal_id", alias = "terminalId")]
+    pub terminal_id: String,
+    pub signal: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TerminalSignalResultPayload {
+    pub terminal_id: String,
+    pub signal: String,
+    pub delivered: bool,
+    /// Process group the signal went to.
+    pub pid: Option<i32>,
+    pub command: Option<String>,
+    pub message: String,
+}
+
+/// The command in a terminal's foreground; all `None` while the shell is at
+/// its prompt.
+#[derive(Debug, Clone, Serialize)]
+pub struct TerminalProcessPayload {
+    pub terminal_id: String,
+    pub pid: Option<i32>,
+    pub name: Option<String>,
+    pub command: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -47,23 +560,357 @@ pub struct TerminalDataPayload {
     pub data: String,
 }
 
+#[derive(Debug, Clone, Serialize)]
+pub struct TerminalCwdPayload {
+    pub terminal_id: String,
+    pub cwd: String,
+}
+
+/// A `path:line[:column]` reference in terminal output that names an
+/// existing workspace file. `start`/`end` are character offsets into
+/// `line_text`, the output line with escape sequences removed.
+#[derive(Debug, Clone, Serialize)]
+pub struct TerminalLink {
+    pub line_text: String,
+    pub start: usize,
+    pub end: usize,
+    pub path: String,
+    pub line: u32,
+    pub column: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TerminalLinksPayload {
+    pub terminal_id: String,
+    pub links: Vec<TerminalLink>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CommandStartedPayload {
+    pub terminal_id: String,
+    pub cwd: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CommandFinishedPayload {
+    pub terminal_id: String,
+    pub cwd: Option<String>,
+    pub exit_code: Option<i32>,
+    pub duration_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TerminalBinaryPayload {
+    pub terminal_id: String,
+    pub data: Bytes,
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct TerminalStatusPayload {
     pub terminal_id: String,
     pub message: String,
 }
 
+#[derive(Debug, Clone, Serialize)]
+pub struct CloneProgressPayload {
+    pub terminal_id: String,
+    pub data: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WorkspaceReadyPayload {
+    pub terminal_id: String,
+    pub root: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HookOutputPayload {
+    pub terminal_id: String,
+    pub stage: String,
+    pub command: String,
+    pub data: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HookStatusPayload {
+    pub terminal_id: String,
+    pub stage: String,
+    pub command: String,
+    pub status: String,
+    pub exit_code: Option<i32>,
+    pub message: String,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct FileContentPayload {
     pub email: String,
     pub path: String,
 }
 
+/// A file's contents. Text comes as `content`, anything else as raw bytes
+/// in `data`.
+#[derive(Debug, Clone, Serialize)]
+pub struct FileDataPayload {
+    pub path: String,
+    pub content: Option<String>,
+    pub data: Option<Bytes>,
+    pub mime_type: String,
+    pub size: u64,
+    pub is_binary: bool,
+    /// Version token to send back as `expected_version` when saving.
+    pub version: String,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct SaveFileContentPayload {
     pub email: String,
     pub path: String,
+    #[serde(default)]
     pub content: String,
+    /// Raw bytes for a binary file; written instead of `content` when set.
+    #[serde(default)]
+    pub data: Option<Bytes>,
+    /// Version the edit was based on. The save is refused with
+    /// `file_conflict` if the file has changed since.
+    #[serde(default, alias = "expectedVersion")]
+    pub expected_version: Option<String>,
+    /// Overwrite whatever is on disk, skipping the version check.
+    #[serde(default)]
+    pub force: bool,
+}
+
+/// Replaces `length` characters at `offset` with `text`. Offsets count
+/// UTF-16 code units, as the editor does, and each edit applies to the
+/// text left by the ones before it.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TextEdit {
+    pub offset: usize,
+    #[serde(default)]
+    pub length: usize,
+    #[serde(default)]
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PatchFilePayload {
+    pub email: String,
+    pub path: String,
+    /// Version the edits were made against.
+    #[serde(alias = "baseVersion")]
+    pub base_version: String,
+    pub edits: Vec<TextEdit>,
+}
+
+/// A patch that wasn't applied; the client should send the whole file
+/// with `save_data` instead.
+#[derive(Debug, Clone, Serialize)]
+pub struct PatchRejectedPayload {
+    pub path: String,
+    pub base_version: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FileSavedPayload {
+    pub path: String,
+    pub version: String,
+}
+
+/// A save refused because the file changed on disk, with what it holds now
+/// (`None` once it has been deleted).
+#[derive(Debug, Clone, Serialize)]
+pub struct FileConflictPayload {
+    pub path: String,
+    pub expected_version: String,
+    pub current: Option<FileDataPayload>,
+}
+
+/// The file operation a `file_op_result` or `file_error` is about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileOp {
+    Read,
+    Save,
+    Patch,
+    CreateFile,
+    CreateDirectory,
+    Delete,
+    Rename,
+    Copy,
+    Upload,
+    Download,
+    Watch,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FileErrorPayload {
+    pub op: FileOp,
+    pub path: String,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FileOpResultPayload {
+    pub op: FileOp,
+    pub path: String,
+    /// Where the entry ended up, for rename and copy.
+    pub new_path: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CreatePathPayload {
+    pub email: String,
+    pub path: String,
+    #[serde(default)]
+    pub overwrite: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DeletePathPayload {
+    pub email: String,
+    pub path: String,
+    #[serde(default)]
+    pub recursive: bool,
+    /// A recursive delete must be confirmed by the user.
+    #[serde(default)]
+    pub confirm: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TransferPathPayload {
+    pub email: String,
+    pub from: String,
+    pub to: String,
+    #[serde(default)]
+    pub overwrite: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct StartUploadPayload {
+    pub email: String,
+    pub path: String,
+    pub size: u64,
+    /// CRC-32 of the whole file, checked once every chunk is in.
+    #[serde(default)]
+    pub checksum: Option<u32>,
+    /// Resumes this upload instead of starting a new one.
+    #[serde(default, alias = "uploadId")]
+    pub upload_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct UploadChunkPayload {
+    pub email: String,
+    #[serde(alias = "uploadId")]
+    pub upload_id: String,
+    pub offset: u64,
+    pub data: Bytes,
+    /// CRC-32 of `data`.
+    pub checksum: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct UploadPayload {
+    pub email: String,
+    #[serde(alias = "uploadId")]
+    pub upload_id: String,
+}
+
+/// Where an upload stands; the client sends its next chunk from `offset`.
+#[derive(Debug, Clone, Serialize)]
+pub struct UploadStatusPayload {
+    pub upload_id: String,
+    pub path: String,
+    pub size: u64,
+    pub offset: u64,
+    pub complete: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DownloadFilePayload {
+    pub email: String,
+    pub path: String,
+    /// Byte to start from, to resume an interrupted download.
+    #[serde(default)]
+    pub offset: u64,
+    #[serde(default, alias = "downloadId")]
+    pub download_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DownloadAckPayload {
+    pub email: String,
+    #[serde(alias = "downloadId")]
+    pub download_id: String,
+    /// Every byte before this has been received.
+    pub offset: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DownloadPayload {
+    pub email: String,
+    #[serde(alias = "downloadId")]
+    pub download_id: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DownloadStartedPayload {
+    pub download_id: String,
+    pub path: String,
+    pub size: u64,
+    pub offset: u64,
+    pub mime_type: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DownloadChunkPayload {
+    pub download_id: String,
+    pub offset: u64,
+    pub data: Bytes,
+    /// CRC-32 of `data`.
+    pub checksum: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DownloadCompletePayload {
+    pub download_id: String,
+    pub path: String,
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FileDownloadQuery {
+    pub path: String,
+}
+
+/// A directory the client has expanded (or collapsed) in the explorer.
+#[derive(Debug, Clone, Deserialize)]
+pub struct WatchDirectoryPayload {
+    pub email: String,
+    pub path: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FsChangeKind {
+    Created,
+    Deleted,
+    Modified,
+    Renamed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FsChange {
+    pub kind: FsChangeKind,
+    pub path: String,
+    /// Where a renamed entry used to be.
+    pub old_path: Option<String>,
+    pub is_dir: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FsChangedPayload {
+    pub changes: Vec<FsChange>,
 }
 
 #[derive(Debug, Clone, Deserialize)]